@@ -17,6 +17,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         enable_summarization: true,
         session_storage_path: std::path::PathBuf::from("./demo_sessions"),
         session_max_age_days: 7,
+        ..MemoryConfig::default()
     };
 
     // 2. Initialize memory manager
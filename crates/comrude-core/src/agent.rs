@@ -0,0 +1,291 @@
+//! Multi-step tool use: ask the model, run the tools it calls, send the
+//! results back and repeat until it answers without calling any
+
+use crate::{
+    config::AgentConfig,
+    engine::ComrudeEngine,
+    error::Result,
+    types::{
+        FinishReason, GenerationRequest, GenerationResponse, TokenUsage, ToolCall, ToolDefinition,
+        ToolExchange, ToolResult,
+    },
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Whatever answers the agent's requests, usually the provider manager
+#[async_trait]
+pub trait AgentBackend: Send + Sync {
+    async fn generate(&self, request: GenerationRequest) -> Result<GenerationResponse>;
+}
+
+/// Runs the tool calls the model makes
+#[async_trait]
+pub trait ToolExecutor: Send + Sync {
+    fn definitions(&self) -> Vec<ToolDefinition>;
+
+    /// Run one call; the result must carry the call's id
+    async fn execute(&self, call: &ToolCall) -> ToolResult;
+}
+
+/// One model response that called tools, and what the tools returned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+    pub results: Vec<ToolResult>,
+    pub tokens_used: TokenUsage,
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentStopReason {
+    /// The model answered without calling tools
+    Finished,
+    MaxIterations,
+    MaxCost,
+}
+
+#[derive(Debug, Clone)]
+pub struct AgentOutcome {
+    /// The final answer, or the last text produced when a limit was hit
+    pub content: String,
+    pub model_used: String,
+    pub finish_reason: FinishReason,
+    pub stop_reason: AgentStopReason,
+    pub steps: Vec<AgentStep>,
+    pub tokens_used: TokenUsage,
+    pub cost: f64,
+    /// Metadata of the last response, such as which provider answered
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+pub struct Agent<'a> {
+    backend: &'a dyn AgentBackend,
+    tools: &'a dyn ToolExecutor,
+    config: AgentConfig,
+}
+
+impl<'a> Agent<'a> {
+    pub fn new(backend: &'a dyn AgentBackend, tools: &'a dyn ToolExecutor, config: AgentConfig) -> Self {
+        Self { backend, tools, config }
+    }
+
+    /// Run the loop for the engine's current conversation turn, recording
+    /// every step in it as soon as the step's tools have run
+    pub async fn run(&self, engine: &ComrudeEngine, mut request: GenerationRequest) -> Result<AgentOutcome> {
+        request.tools = self.tools.definitions();
+        request.stream = false;
+
+        let mut steps = Vec::new();
+        let mut tokens_used = TokenUsage::default();
+        let mut cost = 0.0;
+
+        for iteration in 1..=self.config.max_iterations {
+            let response = self.backend.generate(request.clone()).await?;
+            tokens_used.prompt_tokens += response.tokens_used.prompt_tokens;
+            tokens_used.completion_tokens += response.tokens_used.completion_tokens;
+            tokens_used.total_tokens += response.tokens_used.total_tokens;
+            cost += response.cost;
+
+            if response.tool_calls.is_empty() {
+                return Ok(AgentOutcome {
+                    content: response.content,
+                    model_used: response.model_used,
+                    finish_reason: response.finish_reason,
+                    stop_reason: AgentStopReason::Finished,
+                    steps,
+                    tokens_used,
+                    cost,
+                    metadata: response.metadata,
+                });
+            }
+
+            // Results the model would never see are not worth running the
+            // tools for, so a limit stops the loop before they run
+            let over_budget = self.config.max_cost.is_some_and(|max_cost| cost >= max_cost);
+            if over_budget {
+                return Ok(self.stopped(Some(response), AgentStopReason::MaxCost, steps, tokens_used, cost));
+            }
+            if iteration == self.config.max_iterations {
+                return Ok(self.stopped(Some(response), AgentStopReason::MaxIterations, steps, tokens_used, cost));
+            }
+
+            let mut results = Vec::with_capacity(response.tool_calls.len());
+            for call in &response.tool_calls {
+                results.push(self.tools.execute(call).await);
+            }
+
+            let step = AgentStep {
+                content: response.content.clone(),
+                tool_calls: response.tool_calls.clone(),
+                results: results.clone(),
+                tokens_used: response.tokens_used.clone(),
+                cost: response.cost,
+            };
+            engine.record_agent_step(step.clone()).await?;
+            steps.push(step);
+
+            request.tool_exchanges.push(ToolExchange {
+                content: response.content,
+                calls: response.tool_calls,
+                results,
+            });
+        }
+
+        // Only reached with `max_iterations` set to zero
+        Ok(self.stopped(None, AgentStopReason::MaxIterations, steps, tokens_used, cost))
+    }
+
+    /// The outcome when a limit is hit on `last`, whose tool calls are not run
+    fn stopped(
+        &self,
+        last: Option<GenerationResponse>,
+        stop_reason: AgentStopReason,
+        steps: Vec<AgentStep>,
+        tokens_used: TokenUsage,
+        cost: f64,
+    ) -> AgentOutcome {
+        let (content, model_used, metadata) = last
+            .map(|response| (response.content, response.model_used, response.metadata))
+            .unwrap_or_default();
+
+        AgentOutcome {
+            content,
+            model_used,
+            finish_reason: FinishReason::ToolCalls,
+            stop_reason,
+            steps,
+            tokens_used,
+            cost,
+            metadata,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryConfig;
+    use crate::types::Message;
+    use std::sync::Mutex;
+
+    /// Calls `echo` until it has seen `rounds` tool results, then answers
+    struct ScriptedBackend {
+        rounds: usize,
+        requests: Mutex<Vec<GenerationRequest>>,
+    }
+
+    #[async_trait]
+    impl AgentBackend for ScriptedBackend {
+        async fn generate(&self, request: GenerationRequest) -> Result<GenerationResponse> {
+            let round = request.tool_exchanges.len();
+            self.requests.lock().unwrap().push(request);
+
+            let tool_calls = if round < self.rounds {
+                vec![ToolCall {
+                    id: format!("call_{}", round),
+                    name: "echo".to_string(),
+                    arguments: serde_json::json!({"text": round.to_string()}),
+                }]
+            } else {
+                Vec::new()
+            };
+
+            Ok(GenerationResponse {
+                content: if tool_calls.is_empty() { "all done".to_string() } else { String::new() },
+                model_used: "test".to_string(),
+                tokens_used: TokenUsage { prompt_tokens: 10, completion_tokens: 5, total_tokens: 15 },
+                cost: 0.01,
+                finish_reason: if tool_calls.is_empty() { FinishReason::Stop } else { FinishReason::ToolCalls },
+                tool_calls,
+                metadata: HashMap::new(),
+            })
+        }
+    }
+
+    struct EchoTool;
+
+    #[async_trait]
+    impl ToolExecutor for EchoTool {
+        fn definitions(&self) -> Vec<ToolDefinition> {
+            vec![ToolDefinition {
+                name: "echo".to_string(),
+                description: "Echo text".to_string(),
+                parameters: serde_json::json!({"type": "object"}),
+            }]
+        }
+
+        async fn execute(&self, call: &ToolCall) -> ToolResult {
+            ToolResult {
+                call_id: call.id.clone(),
+                content: call.arguments["text"].as_str().unwrap_or_default().to_string(),
+                is_error: false,
+            }
+        }
+    }
+
+    async fn engine_with_turn(dir: &tempfile::TempDir) -> ComrudeEngine {
+        let engine = ComrudeEngine::new_with_config(MemoryConfig {
+            session_storage_path: dir.path().to_path_buf(),
+            ..MemoryConfig::default()
+        });
+        engine.create_session(None).await.unwrap();
+        engine.start_conversation_turn(Message::new_user("go".to_string()), Vec::new()).await.unwrap();
+        engine
+    }
+
+    #[tokio::test]
+    async fn test_run_feeds_results_back_until_the_model_stops() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_turn(&dir).await;
+        let backend = ScriptedBackend { rounds: 2, requests: Mutex::new(Vec::new()) };
+
+        let outcome = Agent::new(&backend, &EchoTool, AgentConfig::default())
+            .run(&engine, GenerationRequest::default())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.stop_reason, AgentStopReason::Finished);
+        assert_eq!(outcome.content, "all done");
+        assert_eq!(outcome.steps.len(), 2);
+        assert_eq!(outcome.tokens_used.total_tokens, 45);
+        assert!((outcome.cost - 0.03).abs() < 1e-9);
+
+        {
+            let requests = backend.requests.lock().unwrap();
+            assert_eq!(requests[0].tools.len(), 1);
+            assert_eq!(requests[2].tool_exchanges[1].results[0].content, "1");
+        }
+
+        let turns = engine.get_conversation_summary(None).await.unwrap();
+        assert_eq!(turns[0].agent_steps.len(), 2);
+        assert_eq!(turns[0].agent_steps[0].tool_calls[0].id, "call_0");
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_turn(&dir).await;
+        let backend = ScriptedBackend { rounds: usize::MAX, requests: Mutex::new(Vec::new()) };
+
+        let limited = |max_iterations, max_cost| AgentConfig { max_iterations, max_cost, ..AgentConfig::default() };
+
+        let outcome = Agent::new(&backend, &EchoTool, limited(3, None))
+            .run(&engine, GenerationRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(outcome.stop_reason, AgentStopReason::MaxIterations);
+        // The third response's tool calls are not run, since no request is left to send their results
+        assert_eq!(outcome.steps.len(), 2);
+        assert_eq!(backend.requests.lock().unwrap().len(), 3);
+
+        let outcome = Agent::new(&backend, &EchoTool, limited(10, Some(0.015)))
+            .run(&engine, GenerationRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(outcome.stop_reason, AgentStopReason::MaxCost);
+        assert_eq!(outcome.steps.len(), 1);
+    }
+}
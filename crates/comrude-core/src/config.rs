@@ -1,5 +1,8 @@
 use crate::error::{ConfigError, ConfigResult};
+use crate::types::CostPer1k;
+use crate::usage::UsageConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -8,6 +11,15 @@ pub struct Config {
     pub providers: ProvidersConfig,
     pub files: FilesConfig,
     pub memory: MemoryConfigSerde,
+    /// Per-1k-token prices keyed by model id, overriding the built-in tables
+    #[serde(default)]
+    pub pricing: HashMap<String, CostPer1k>,
+    #[serde(default)]
+    pub usage: UsageConfig,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub agent: AgentConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +43,9 @@ pub struct ProvidersConfig {
     pub ollama: Option<OllamaConfig>,
     pub google: Option<GoogleConfig>,
     pub huggingface: Option<HuggingFaceConfig>,
+    /// Providers tried in order when the current one fails
+    #[serde(default)]
+    pub fallback: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,7 +55,20 @@ pub struct OpenAIConfig {
     pub default_model: String,
     pub max_tokens: u32,
     pub timeout_seconds: u64,
+    /// Attempts per request, including the first, before giving up on transient failures
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Override the advertised limits to match the account tier
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
     pub base_url: String,
+    /// Ask for token usage at the end of streams with `stream_options`;
+    /// unset, only api.openai.com is asked, since other compatible servers
+    /// may reject the field
+    #[serde(default)]
+    pub stream_usage: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +78,14 @@ pub struct AnthropicConfig {
     pub default_model: String,
     pub max_tokens: u32,
     pub timeout_seconds: u64,
+    /// Attempts per request, including the first, before giving up on transient failures
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Override the advertised limits to match the account tier
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
     pub base_url: String,
 }
 
@@ -59,9 +95,21 @@ pub struct OllamaConfig {
     pub endpoint: String,
     pub default_model: String,
     pub timeout_seconds: u64,
+    /// Attempts per request, including the first, before giving up on transient failures
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Override the advertised limits to match the account tier
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
     pub auto_pull_models: bool,
 }
 
+fn default_max_attempts() -> u32 {
+    3
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GoogleConfig {
     pub enabled: bool,
@@ -85,7 +133,89 @@ pub struct HuggingFaceConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilesConfig {
     pub max_file_size_mb: u64,
+    /// Extensions file tools may read and write; empty allows any
     pub allowed_extensions: Vec<String>,
+    /// Directory file tools are confined to, the current directory if unset
+    #[serde(default)]
+    pub workspace_root: Option<String>,
+}
+
+/// Spending limits in dollars; unset limits are not enforced
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetLimits {
+    #[serde(default)]
+    pub session: Option<f64>,
+    #[serde(default)]
+    pub daily: Option<f64>,
+    #[serde(default)]
+    pub monthly: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    /// Limits across all providers
+    #[serde(default)]
+    pub session: Option<f64>,
+    #[serde(default)]
+    pub daily: Option<f64>,
+    #[serde(default)]
+    pub monthly: Option<f64>,
+    /// Fraction of a limit at which to start warning
+    #[serde(default = "default_budget_warn_at")]
+    pub warn_at: f64,
+    /// Limits for individual providers, keyed by provider name
+    #[serde(default)]
+    pub providers: HashMap<String, BudgetLimits>,
+}
+
+fn default_budget_warn_at() -> f64 {
+    0.8
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            session: None,
+            daily: None,
+            monthly: None,
+            warn_at: default_budget_warn_at(),
+            providers: HashMap::new(),
+        }
+    }
+}
+
+impl BudgetConfig {
+    pub fn global_limits(&self) -> BudgetLimits {
+        BudgetLimits {
+            session: self.session,
+            daily: self.daily,
+            monthly: self.monthly,
+        }
+    }
+}
+
+/// Limits for one multi-step tool-using answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentConfig {
+    /// Answer through native tool calls where the provider supports them,
+    /// instead of streaming printed commands
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model requests allowed before the agent stops
+    pub max_iterations: u32,
+    /// Dollars one answer may spend before the agent stops
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_iterations: 10,
+            max_cost: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -96,6 +226,11 @@ pub struct MemoryConfigSerde {
     pub enable_summarization: bool,
     pub session_storage_path: String,
     pub session_max_age_days: u32,
+    /// Provider that summarizes old turns, e.g. "ollama"
+    #[serde(default)]
+    pub summary_provider: Option<String>,
+    #[serde(default)]
+    pub summary_model: Option<String>,
 }
 
 impl Default for MemoryConfigSerde {
@@ -107,6 +242,8 @@ impl Default for MemoryConfigSerde {
             enable_summarization: true,
             session_storage_path: ".comrude/sessions".to_string(),
             session_max_age_days: 30,
+            summary_provider: None,
+            summary_model: None,
         }
     }
 }
@@ -120,6 +257,8 @@ impl From<MemoryConfigSerde> for crate::memory::MemoryConfig {
             enable_summarization: config.enable_summarization,
             session_storage_path: std::path::PathBuf::from(config.session_storage_path),
             session_max_age_days: config.session_max_age_days,
+            summary_provider: config.summary_provider,
+            summary_model: config.summary_model,
         }
     }
 }
@@ -133,6 +272,8 @@ impl From<crate::memory::MemoryConfig> for MemoryConfigSerde {
             enable_summarization: config.enable_summarization,
             session_storage_path: config.session_storage_path.to_string_lossy().to_string(),
             session_max_age_days: config.session_max_age_days,
+            summary_provider: config.summary_provider,
+            summary_model: config.summary_model,
         }
     }
 }
@@ -157,7 +298,11 @@ impl Default for Config {
                     default_model: "gpt-4o-mini".to_string(),
                     max_tokens: 4096,
                     timeout_seconds: 30,
+                    max_attempts: default_max_attempts(),
+                    requests_per_minute: None,
+                    tokens_per_minute: None,
                     base_url: "https://api.openai.com/v1".to_string(),
+                    stream_usage: None,
                 }),
                 anthropic: Some(AnthropicConfig {
                     enabled: true,
@@ -165,6 +310,9 @@ impl Default for Config {
                     default_model: "claude-3-5-sonnet-20241022".to_string(),
                     max_tokens: 4096,
                     timeout_seconds: 30,
+                    max_attempts: default_max_attempts(),
+                    requests_per_minute: None,
+                    tokens_per_minute: None,
                     base_url: "https://api.anthropic.com".to_string(),
                 }),
                 ollama: Some(OllamaConfig {
@@ -172,10 +320,14 @@ impl Default for Config {
                     endpoint: "http://localhost:11434".to_string(),
                     default_model: "codellama:7b".to_string(),
                     timeout_seconds: 60,
+                    max_attempts: default_max_attempts(),
+                    requests_per_minute: None,
+                    tokens_per_minute: None,
                     auto_pull_models: false,
                 }),
                 google: None,
                 huggingface: None,
+                fallback: Vec::new(),
             },
             files: FilesConfig {
                 max_file_size_mb: 10,
@@ -183,8 +335,13 @@ impl Default for Config {
                     "rs", "py", "js", "ts", "go", "java", "cpp", "c", 
                     "md", "txt", "json", "yaml", "toml"
                 ].into_iter().map(String::from).collect(),
+                workspace_root: None,
             },
             memory: MemoryConfigSerde::default(),
+            pricing: HashMap::new(),
+            usage: UsageConfig::default(),
+            budget: BudgetConfig::default(),
+            agent: AgentConfig::default(),
         }
     }
 }
@@ -314,7 +471,11 @@ impl Default for OpenAIConfig {
             default_model: "gpt-4o-mini".to_string(),
             max_tokens: 4096,
             timeout_seconds: 30,
+            max_attempts: default_max_attempts(),
+            requests_per_minute: None,
+            tokens_per_minute: None,
             base_url: "https://api.openai.com/v1".to_string(),
+            stream_usage: None,
         }
     }
 }
@@ -327,6 +488,9 @@ impl Default for AnthropicConfig {
             default_model: "claude-3-5-sonnet-20241022".to_string(),
             max_tokens: 4096,
             timeout_seconds: 30,
+            max_attempts: default_max_attempts(),
+            requests_per_minute: None,
+            tokens_per_minute: None,
             base_url: "https://api.anthropic.com".to_string(),
         }
     }
@@ -339,6 +503,9 @@ impl Default for OllamaConfig {
             endpoint: "http://localhost:11434".to_string(),
             default_model: "codellama:7b".to_string(),
             timeout_seconds: 60,
+            max_attempts: default_max_attempts(),
+            requests_per_minute: None,
+            tokens_per_minute: None,
             auto_pull_models: false,
         }
     }
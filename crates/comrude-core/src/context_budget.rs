@@ -0,0 +1,312 @@
+//! Fitting a request into the model's context window
+//!
+//! Room for the answer is set aside first. The system prompt and the
+//! question always go in; what is left is handed out by priority, so pinned
+//! instructions and attached files win over old conversation. An item that
+//! does not fit is cut down with a marker saying so, or left out when too
+//! little room remains to be useful.
+
+use crate::tokenizer::Tokenizer;
+use crate::types::{ContextItem, ContextType};
+use std::fmt;
+
+/// Room below which an item is dropped rather than cut down
+const MIN_TRUNCATED_TOKENS: usize = 64;
+
+/// What a piece of context is, in the order it is given room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ContextPriority {
+    SystemPrompt,
+    PinnedInstruction,
+    /// Files, command output and diffs the user attached
+    Attached,
+    Summary,
+    RecentTurn,
+    OlderTurn,
+}
+
+impl ContextPriority {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ContextPriority::SystemPrompt => "system prompt",
+            ContextPriority::PinnedInstruction => "pinned",
+            ContextPriority::Attached => "attached",
+            ContextPriority::Summary => "summary",
+            ContextPriority::RecentTurn => "recent turn",
+            ContextPriority::OlderTurn => "older turn",
+        }
+    }
+
+    /// Within a kind, conversation prefers the newest items and the rest
+    /// the first ones given
+    fn newest_first(&self) -> bool {
+        matches!(self, ContextPriority::RecentTurn | ContextPriority::OlderTurn)
+    }
+}
+
+/// A context item competing for room
+#[derive(Debug, Clone)]
+pub struct ContextCandidate {
+    pub priority: ContextPriority,
+    pub item: ContextItem,
+}
+
+impl ContextCandidate {
+    pub fn new(priority: ContextPriority, item: ContextItem) -> Self {
+        Self { priority, item }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationStatus {
+    Included,
+    /// Cut down to `kept` tokens, marker included
+    Truncated { kept: usize },
+    Dropped,
+}
+
+/// What happened to one piece of the request
+#[derive(Debug, Clone)]
+pub struct AllocationEntry {
+    pub priority: ContextPriority,
+    pub description: String,
+    /// Tokens the piece needed in full
+    pub tokens: usize,
+    pub status: AllocationStatus,
+}
+
+impl AllocationEntry {
+    /// Tokens the piece takes up in the request
+    pub fn granted(&self) -> usize {
+        match self.status {
+            AllocationStatus::Included => self.tokens,
+            AllocationStatus::Truncated { kept } => kept,
+            AllocationStatus::Dropped => 0,
+        }
+    }
+}
+
+/// How the window of one request was shared out
+#[derive(Debug, Clone)]
+pub struct AllocationReport {
+    pub context_window: usize,
+    pub reserved_output: usize,
+    /// Name of the tokenizer that counted
+    pub tokenizer: String,
+    /// In the order room was given
+    pub entries: Vec<AllocationEntry>,
+}
+
+impl AllocationReport {
+    pub fn used(&self) -> usize {
+        self.entries.iter().map(AllocationEntry::granted).sum()
+    }
+
+    /// Whether anything was cut down or left out
+    pub fn trimmed(&self) -> bool {
+        self.entries.iter().any(|entry| entry.status != AllocationStatus::Included)
+    }
+}
+
+impl fmt::Display for AllocationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Window {} tokens ({}): {} reserved for the answer, {} used, {} free",
+            self.context_window,
+            self.tokenizer,
+            self.reserved_output,
+            self.used(),
+            self.context_window.saturating_sub(self.reserved_output + self.used()),
+        )?;
+        for entry in &self.entries {
+            let status = match entry.status {
+                AllocationStatus::Included => format!("{} tokens", entry.tokens),
+                AllocationStatus::Truncated { kept } => format!("{} of {} tokens, truncated", kept, entry.tokens),
+                AllocationStatus::Dropped => format!("{} tokens, left out", entry.tokens),
+            };
+            writeln!(f, "  {:<14} {:<40} {}", entry.priority.label(), entry.description, status)?;
+        }
+        Ok(())
+    }
+}
+
+/// The context for a request and how it was chosen
+#[derive(Debug, Clone)]
+pub struct ContextAllocation {
+    /// Items that fit, in the order they were given
+    pub items: Vec<ContextItem>,
+    pub report: AllocationReport,
+}
+
+/// The model's window and the part of it kept for the answer
+#[derive(Debug, Clone, Copy)]
+pub struct ContextBudget {
+    pub context_window: usize,
+    pub reserved_output: usize,
+}
+
+impl ContextBudget {
+    /// Fill what the system prompt and question leave of the window
+    ///
+    /// Candidates are given in the order they should appear in the request;
+    /// room goes to them by priority.
+    pub fn allocate(
+        &self,
+        tokenizer: &dyn Tokenizer,
+        system_prompt: &str,
+        prompt: &str,
+        candidates: Vec<ContextCandidate>,
+    ) -> ContextAllocation {
+        let mut entries = Vec::new();
+        let mut remaining = self.context_window.saturating_sub(self.reserved_output);
+
+        for (description, text) in [("instructions", system_prompt), ("question", prompt)] {
+            if text.is_empty() {
+                continue;
+            }
+            let tokens = tokenizer.count(text);
+            remaining = remaining.saturating_sub(tokens);
+            entries.push(AllocationEntry {
+                priority: ContextPriority::SystemPrompt,
+                description: description.to_string(),
+                tokens,
+                status: AllocationStatus::Included,
+            });
+        }
+
+        let mut order: Vec<usize> = (0..candidates.len()).collect();
+        order.sort_by_key(|&i| {
+            let priority = candidates[i].priority;
+            let position = if priority.newest_first() { usize::MAX - i } else { i };
+            (priority, position)
+        });
+
+        let mut kept: Vec<Option<ContextItem>> = vec![None; candidates.len()];
+        for i in order {
+            let ContextCandidate { priority, item } = &candidates[i];
+            let tokens = tokenizer.count(&item.content);
+            let status = if tokens <= remaining {
+                kept[i] = Some(item.clone());
+                AllocationStatus::Included
+            } else if remaining >= MIN_TRUNCATED_TOKENS {
+                let content = truncate_to_tokens(tokenizer, &item.content, tokens, remaining);
+                let granted = tokenizer.count(&content);
+                kept[i] = Some(ContextItem { content, ..item.clone() });
+                AllocationStatus::Truncated { kept: granted }
+            } else {
+                AllocationStatus::Dropped
+            };
+
+            let entry = AllocationEntry { priority: *priority, description: describe(item), tokens, status };
+            remaining = remaining.saturating_sub(entry.granted());
+            entries.push(entry);
+        }
+
+        ContextAllocation {
+            items: kept.into_iter().flatten().collect(),
+            report: AllocationReport {
+                context_window: self.context_window,
+                reserved_output: self.reserved_output,
+                tokenizer: tokenizer.name().to_string(),
+                entries,
+            },
+        }
+    }
+}
+
+/// The longest start of `text` that fits in `max_tokens` together with a
+/// marker saying how much was kept
+fn truncate_to_tokens(tokenizer: &dyn Tokenizer, text: &str, total: usize, max_tokens: usize) -> String {
+    let marker = |kept: usize| format!("\n[... truncated: {} of {} tokens shown ...]", kept, total);
+    let room = max_tokens.saturating_sub(tokenizer.count(&marker(total)));
+
+    // Binary search over character boundaries for the longest prefix that fits
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).chain([text.len()]).collect();
+    let (mut low, mut high) = (0, boundaries.len() - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if tokenizer.count(&text[..boundaries[mid]]) <= room {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    let prefix = &text[..boundaries[low]];
+    format!("{}{}", prefix, marker(tokenizer.count(prefix)))
+}
+
+/// A short name for an item in the report
+fn describe(item: &ContextItem) -> String {
+    let description = match &item.item_type {
+        ContextType::File { path } => path.clone(),
+        ContextType::Command { command } => format!("$ {}", command),
+        ContextType::GitDiff => match item.metadata.get("path").and_then(|path| path.as_str()) {
+            Some(path) => format!("diff {}", path),
+            None => "diff".to_string(),
+        },
+        ContextType::Code { .. } | ContextType::Text => {
+            let role = item.metadata.get("role").and_then(|role| role.as_str()).unwrap_or("text");
+            let first_line = item.content.lines().next().unwrap_or_default();
+            format!("{}: {}", role, first_line)
+        }
+    };
+    match description.char_indices().nth(37) {
+        Some((end, _)) => format!("{}...", &description[..end]),
+        None => description,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::BpeTokenizer;
+    use std::collections::HashMap;
+
+    fn item(item_type: ContextType, content: String) -> ContextItem {
+        ContextItem { item_type, content, metadata: HashMap::new() }
+    }
+
+    #[test]
+    fn test_allocate_fills_by_priority_and_marks_truncation() {
+        let tokenizer = BpeTokenizer::cl100k();
+        let file = item(ContextType::File { path: "src/lib.rs".to_string() }, "fn answer() -> u32 { 42 }\n".repeat(20));
+        let candidates = vec![
+            ContextCandidate::new(ContextPriority::OlderTurn, item(ContextType::Text, "an old question ".repeat(400))),
+            ContextCandidate::new(ContextPriority::RecentTurn, item(ContextType::Text, "a recent question".to_string())),
+            ContextCandidate::new(ContextPriority::Attached, file),
+            ContextCandidate::new(ContextPriority::PinnedInstruction, item(ContextType::Text, "Answer in Portuguese".to_string())),
+        ];
+        let budget = ContextBudget { context_window: 1000, reserved_output: 500 };
+
+        let allocation = budget.allocate(&tokenizer, "You are helpful.", "Why?", candidates);
+        let report = &allocation.report;
+        let priorities: Vec<_> = report.entries.iter().map(|entry| entry.priority).collect();
+        assert_eq!(priorities, [
+            ContextPriority::SystemPrompt,
+            ContextPriority::SystemPrompt,
+            ContextPriority::PinnedInstruction,
+            ContextPriority::Attached,
+            ContextPriority::RecentTurn,
+            ContextPriority::OlderTurn,
+        ]);
+        assert_eq!(report.entries[3].status, AllocationStatus::Included);
+        assert!(matches!(report.entries[5].status, AllocationStatus::Truncated { .. }));
+        assert!(report.used() <= 500 && report.trimmed());
+
+        // Items keep the order they were given in
+        assert_eq!(allocation.items.len(), 4);
+        assert!(allocation.items[0].content.starts_with("an old question"));
+        assert!(allocation.items[0].content.ends_with("tokens shown ...]"));
+        assert_eq!(allocation.items[3].content, "Answer in Portuguese");
+
+        // With no room left over, the oldest conversation is left out
+        let tight = ContextBudget { context_window: 40, reserved_output: 20 };
+        let allocation = tight.allocate(&tokenizer, "", "Why?", vec![
+            ContextCandidate::new(ContextPriority::OlderTurn, item(ContextType::Text, "an old question ".repeat(40))),
+        ]);
+        assert!(allocation.items.is_empty());
+        assert_eq!(allocation.report.entries[1].status, AllocationStatus::Dropped);
+    }
+}
@@ -0,0 +1,366 @@
+//! Line-based diffs (Myers' algorithm) and unified diff rendering
+
+/// Edit distance past which the remaining middle of the inputs is reported
+/// as one replacement instead of searched for a minimal diff. Keeps time and
+/// memory bounded on unrelated inputs.
+const MAX_EDIT_DISTANCE: usize = 2048;
+
+/// Lines of unchanged context shown around each hunk
+pub const DEFAULT_CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Split text into lines without their terminators
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.lines().collect()
+}
+
+/// Shortest sequence of line deletions and insertions turning `old` into `new`
+pub fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<LineChange<'a>> {
+    // Shared leading and trailing lines never need the search
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut changes: Vec<LineChange<'a>> = old[..prefix].iter().map(|line| LineChange::Equal(line)).collect();
+    changes.extend(myers(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]));
+    changes.extend(old[old.len() - suffix..].iter().map(|line| LineChange::Equal(line)));
+    changes
+}
+
+fn myers<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<LineChange<'a>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = old.len() + new.len();
+    let offset = max as isize;
+    let mut v = vec![0isize; 2 * max + 2];
+    // trace[d] holds the furthest x on diagonals -d..=d before round d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut found = false;
+
+    'search: for d in 0..=max.min(MAX_EDIT_DISTANCE) as isize {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+
+        let mut k = -d;
+        while k <= d {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    if !found {
+        return old.iter().map(|line| LineChange::Delete(line))
+            .chain(new.iter().map(|line| LineChange::Insert(line)))
+            .collect();
+    }
+
+    // Walk back from the end, one edit per round
+    let (mut x, mut y) = (n, m);
+    let mut changes = Vec::new();
+    for (d, furthest) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let at = |k: isize| furthest[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            changes.push(LineChange::Equal(old[(x - 1) as usize]));
+            x -= 1;
+            y -= 1;
+        }
+        if x == prev_x {
+            changes.push(LineChange::Insert(new[(y - 1) as usize]));
+            y -= 1;
+        } else {
+            changes.push(LineChange::Delete(old[(x - 1) as usize]));
+            x -= 1;
+        }
+    }
+    while x > 0 && y > 0 {
+        changes.push(LineChange::Equal(old[(x - 1) as usize]));
+        x -= 1;
+        y -= 1;
+    }
+
+    changes.reverse();
+    changes
+}
+
+/// Render the changes between two texts as a unified diff, or an empty
+/// string when they have the same lines
+pub fn unified_diff(old: &str, new: &str, path: &str, context: usize) -> String {
+    let (old_lines, new_lines) = (split_lines(old), split_lines(new));
+    let changes = diff_lines(&old_lines, &new_lines);
+
+    let changed: Vec<usize> = changes.iter().enumerate()
+        .filter(|(_, change)| !matches!(change, LineChange::Equal(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Group changes whose context would overlap into one hunk
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(changes.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    // Line numbers where each change starts, counted before it is applied
+    let mut positions = Vec::with_capacity(changes.len());
+    let (mut old_line, mut new_line) = (1, 1);
+    for change in &changes {
+        positions.push((old_line, new_line));
+        match change {
+            LineChange::Equal(_) => {
+                old_line += 1;
+                new_line += 1;
+            }
+            LineChange::Delete(_) => old_line += 1,
+            LineChange::Insert(_) => new_line += 1,
+        }
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    for (start, end) in ranges {
+        let hunk = &changes[start..end];
+        let old_len = hunk.iter().filter(|c| !matches!(c, LineChange::Insert(_))).count();
+        let new_len = hunk.iter().filter(|c| !matches!(c, LineChange::Delete(_))).count();
+        let (old_start, new_start) = positions[start];
+        // An empty side is numbered by the line it follows
+        let old_start = if old_len == 0 { old_start - 1 } else { old_start };
+        let new_start = if new_len == 0 { new_start - 1 } else { new_start };
+
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start, old_len, new_start, new_len));
+        for change in hunk {
+            let (marker, line) = match change {
+                LineChange::Equal(line) => (' ', line),
+                LineChange::Delete(line) => ('-', line),
+                LineChange::Insert(line) => ('+', line),
+            };
+            out.push(marker);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+/// A compact patch holding just what `apply_line_patch` needs to rebuild
+/// `new` from `old`
+///
+/// Each hunk is a header `@@ -{start},{removed} +{added} @@`, where `start`
+/// is the 0-based index of the first replaced line of the base, followed by
+/// the `added` new lines prefixed with `+`. A last line without a newline is
+/// followed by `\ No newline at end of file`, so texts round-trip exactly.
+pub fn line_patch(old: &str, new: &str) -> String {
+    let (old_lines, new_lines): (Vec<&str>, Vec<&str>) = (old.split_inclusive('\n').collect(), new.split_inclusive('\n').collect());
+    let changes = diff_lines(&old_lines, &new_lines);
+
+    let mut patch = String::new();
+    let mut old_index = 0;
+    let mut i = 0;
+    while i < changes.len() {
+        if let LineChange::Equal(_) = changes[i] {
+            old_index += 1;
+            i += 1;
+            continue;
+        }
+
+        let start = old_index;
+        let mut added = Vec::new();
+        while let Some(change) = changes.get(i) {
+            match change {
+                LineChange::Equal(_) => break,
+                LineChange::Delete(_) => old_index += 1,
+                LineChange::Insert(line) => added.push(*line),
+            }
+            i += 1;
+        }
+
+        patch.push_str(&format!("@@ -{},{} +{} @@\n", start, old_index - start, added.len()));
+        for line in added {
+            patch.push('+');
+            patch.push_str(line);
+            if !line.ends_with('\n') {
+                patch.push('\n');
+                patch.push_str(NO_NEWLINE_MARKER);
+                patch.push('\n');
+            }
+        }
+    }
+    patch
+}
+
+struct PatchHunk {
+    start: usize,
+    removed: usize,
+    added: String,
+}
+
+fn parse_line_patch(patch: &str) -> Result<Vec<PatchHunk>, String> {
+    let mut hunks = Vec::new();
+    let mut lines = patch.split_inclusive('\n').peekable();
+
+    while let Some(header) = lines.next() {
+        let (start, removed, count) = header.trim_end()
+            .strip_prefix("@@ -")
+            .and_then(|rest| rest.strip_suffix(" @@"))
+            .and_then(|rest| rest.split_once(" +"))
+            .and_then(|(old, added)| {
+                let (start, removed) = old.split_once(',')?;
+                Some((start.parse().ok()?, removed.parse().ok()?, added.parse::<usize>().ok()?))
+            })
+            .ok_or_else(|| format!("malformed hunk header: {}", header.trim_end()))?;
+
+        let mut added = String::new();
+        for _ in 0..count {
+            let line = lines.next()
+                .and_then(|line| line.strip_prefix('+'))
+                .ok_or("hunk ends before its added lines")?;
+            added.push_str(line);
+        }
+        if lines.peek().is_some_and(|line| line.trim_end_matches('\n') == NO_NEWLINE_MARKER) {
+            lines.next();
+            if added.pop() != Some('\n') {
+                return Err("misplaced end-of-file marker".to_string());
+            }
+        }
+
+        hunks.push(PatchHunk { start, removed, added });
+    }
+    Ok(hunks)
+}
+
+/// Rebuild the new text from the base a `line_patch` was made against
+pub fn apply_line_patch(base: &str, patch: &str) -> Result<String, String> {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let mut result = String::with_capacity(base.len());
+    let mut cursor = 0;
+
+    for hunk in parse_line_patch(patch)? {
+        if hunk.start < cursor || hunk.start + hunk.removed > base_lines.len() {
+            return Err(format!(
+                "hunk at line {} does not fit a base of {} lines", hunk.start + 1, base_lines.len()
+            ));
+        }
+        result.extend(base_lines[cursor..hunk.start].iter().copied());
+        result.push_str(&hunk.added);
+        cursor = hunk.start + hunk.removed;
+    }
+    result.extend(base_lines[cursor..].iter().copied());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply<'a>(changes: &[LineChange<'a>]) -> (Vec<&'a str>, Vec<&'a str>) {
+        let old = changes.iter().filter_map(|c| match c {
+            LineChange::Equal(line) | LineChange::Delete(line) => Some(*line),
+            LineChange::Insert(_) => None,
+        }).collect();
+        let new = changes.iter().filter_map(|c| match c {
+            LineChange::Equal(line) | LineChange::Insert(line) => Some(*line),
+            LineChange::Delete(_) => None,
+        }).collect();
+        (old, new)
+    }
+
+    #[test]
+    fn test_diff_lines_is_minimal_and_reconstructs_both_sides() {
+        let old = split_lines("a\nb\nc\na\nb\nb\na");
+        let new = split_lines("c\nb\na\nb\na\nc");
+        let changes = diff_lines(&old, &new);
+
+        assert_eq!(apply(&changes), (old.clone(), new.clone()));
+        let edits = changes.iter().filter(|c| !matches!(c, LineChange::Equal(_))).count();
+        assert_eq!(edits, 5);
+
+        assert_eq!(diff_lines(&[], &["x"]), [LineChange::Insert("x")]);
+        assert_eq!(diff_lines(&["x"], &[]), [LineChange::Delete("x")]);
+    }
+
+    #[test]
+    fn test_unified_diff_renders_hunks_with_context() {
+        let old = (1..=20).map(|i| format!("line {}\n", i)).collect::<String>();
+        let new = old.replace("line 2\n", "line two\n").replace("line 18\n", "");
+
+        let diff = unified_diff(&old, &new, "src/lib.rs", 3);
+        assert_eq!(diff, "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,5 +1,5 @@
+ line 1
+-line 2
++line two
+ line 3
+ line 4
+ line 5
+@@ -15,6 +15,5 @@
+ line 15
+ line 16
+ line 17
+-line 18
+ line 19
+ line 20
+");
+        assert_eq!(unified_diff(&old, &old, "src/lib.rs", 3), "");
+    }
+
+    #[test]
+    fn test_line_patch_round_trips() {
+        let cases = [
+            ("", ""),
+            ("", "new\n"),
+            ("gone\n", ""),
+            ("a\nb\nc\n", "a\nB\nc\nd\n"),
+            ("a\nb", "a\nb\n"),
+            ("a\nb\n", "a\nb"),
+            ("x\r\ny\r\n", "x\r\nz\r\n"),
+            ("héllo\nwörld\n", "hello\nwörld\n+plus\n"),
+            ("@@ -1,1 +1 @@\n+x\n", "+x\n@@ -0,0 +0 @@\n"),
+        ];
+        for (old, new) in cases {
+            let patch = line_patch(old, new);
+            assert_eq!(apply_line_patch(old, &patch).unwrap(), new, "{:?} -> {:?} via {:?}", old, new, patch);
+        }
+
+        assert_eq!(line_patch("a\nb\nc\n", "a\nB\nc\n"), "@@ -1,1 +1 @@\n+B\n");
+        assert_eq!(line_patch("same\n", "same\n"), "");
+        assert!(apply_line_patch("one line\n", "@@ -3,1 +0 @@\n").is_err());
+        assert!(apply_line_patch("a\n", "DIFF: 2 -> 3").is_err());
+    }
+}
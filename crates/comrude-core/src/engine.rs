@@ -16,6 +16,8 @@ pub struct ComrudeEngine {
     // New memory management system
     memory_manager: Arc<RwLock<ContextMemoryManager>>,
     current_turn_id: Arc<RwLock<Option<Uuid>>>,
+    // Context attached by the user for the next turn only
+    attached_context: Arc<RwLock<Vec<ContextItem>>>,
 }
 
 impl ComrudeEngine {
@@ -26,6 +28,7 @@ impl ComrudeEngine {
             current_context: Arc::new(RwLock::new(Vec::new())),
             memory_manager: Arc::new(RwLock::new(ContextMemoryManager::new(memory_config))),
             current_turn_id: Arc::new(RwLock::new(None)),
+            attached_context: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
@@ -35,6 +38,7 @@ impl ComrudeEngine {
             current_context: Arc::new(RwLock::new(Vec::new())),
             memory_manager: Arc::new(RwLock::new(ContextMemoryManager::new(memory_config))),
             current_turn_id: Arc::new(RwLock::new(None)),
+            attached_context: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
@@ -70,8 +74,37 @@ impl ComrudeEngine {
         ctx.clear();
     }
 
+    /// Attach an item, such as command output, to the next conversation turn
+    pub async fn attach_context(&self, item: ContextItem) {
+        self.attached_context.write().await.push(item);
+    }
+
+    /// Remove and return the items attached since the last turn started
+    pub async fn take_attached_context(&self) -> Vec<ContextItem> {
+        std::mem::take(&mut *self.attached_context.write().await)
+    }
+
     // New memory-aware methods
 
+    /// Count tokens the way the current model does, see `tokenizer_for`
+    pub async fn set_tokenizer(&self, tokenizer: Arc<dyn crate::tokenizer::Tokenizer>) {
+        self.memory_manager.write().await.set_tokenizer(tokenizer);
+    }
+
+    /// Size requests for a model that reads `tokens`
+    pub async fn set_context_window(&self, tokens: Option<usize>) {
+        self.memory_manager.write().await.set_context_window(tokens);
+    }
+
+    pub async fn tokenizer(&self) -> Arc<dyn crate::tokenizer::Tokenizer> {
+        self.memory_manager.read().await.tokenizer()
+    }
+
+    /// Have old turns summarized by a model, see `Summarizer`
+    pub async fn set_summarizer(&self, summarizer: Arc<dyn crate::summary::Summarizer>) {
+        self.memory_manager.write().await.set_summarizer(summarizer);
+    }
+
     /// Initialize a new session with memory management
     pub async fn create_session(&self, name: Option<String>) -> Result<Uuid> {
         let mut manager = self.memory_manager.write().await;
@@ -90,9 +123,26 @@ impl ComrudeEngine {
 
     /// Start a new conversation turn with context-aware processing
     pub async fn start_conversation_turn(&self, user_message: Message, context: Vec<ContextItem>) -> Result<Uuid> {
-        let mut manager = self.memory_manager.write().await;
-        let turn_id = manager.add_conversation_turn(user_message.clone(), context).await
-            .map_err(|e| crate::error::ComrudeError::Memory(e.to_string()))?;
+        let (turn_id, pending_summary) = {
+            let mut manager = self.memory_manager.write().await;
+            let turn_id = manager.add_conversation_turn(user_message.clone(), context).await
+                .map_err(|e| crate::error::ComrudeError::Memory(e.to_string()))?;
+            (turn_id, manager.pending_summary())
+        };
+
+        // The summarizer may wait on a model, so the memory lock is released
+        // while it works and taken again to swap the turns for the summary
+        if let Some((summarizer, turns)) = pending_summary {
+            let summary = match summarizer.summarize(&turns).await {
+                Ok(summary) => Some(summary),
+                Err(e) => {
+                    tracing::warn!("Summarizer failed, falling back to keyword summary: {}", e);
+                    None
+                }
+            };
+            self.memory_manager.write().await.apply_summary(&turns, summary).await
+                .map_err(|e| crate::error::ComrudeError::Memory(e.to_string()))?;
+        }
         
         // Store current turn ID
         let mut current_turn = self.current_turn_id.write().await;
@@ -120,6 +170,18 @@ impl ComrudeEngine {
         Ok(())
     }
 
+    /// Record a tool-using step in the current conversation turn
+    pub async fn record_agent_step(&self, step: crate::agent::AgentStep) -> Result<()> {
+        let current_turn = self.current_turn_id.read().await;
+        let Some(turn_id) = *current_turn else {
+            return Err(crate::error::ComrudeError::Memory("No active conversation turn".to_string()));
+        };
+
+        let mut manager = self.memory_manager.write().await;
+        manager.add_agent_step(turn_id, step).await
+            .map_err(|e| crate::error::ComrudeError::Memory(e.to_string()))
+    }
+
     /// Get contextual information for the next LLM request
     pub async fn get_context_for_request(&self) -> Result<Vec<ContextItem>> {
         let manager = self.memory_manager.read().await;
@@ -127,6 +189,31 @@ impl ComrudeEngine {
             .map_err(|e| crate::error::ComrudeError::Memory(e.to_string()))
     }
 
+    /// Choose the context for a request within the model's window, see `ContextBudget`
+    pub async fn assemble_context(
+        &self,
+        system_prompt: &str,
+        prompt: &str,
+        attached: Vec<ContextItem>,
+        reserved_output: usize,
+    ) -> Result<crate::context_budget::ContextAllocation> {
+        let mut manager = self.memory_manager.write().await;
+        manager.assemble_context(system_prompt, prompt, attached, reserved_output)
+            .map_err(|e| crate::error::ComrudeError::Memory(e.to_string()))
+    }
+
+    /// How the last assembled request used the window
+    pub async fn last_allocation(&self) -> Option<crate::context_budget::AllocationReport> {
+        self.memory_manager.read().await.last_allocation().cloned()
+    }
+
+    /// Keep an instruction in every request from now on
+    pub async fn pin_instruction(&self, instruction: String) -> Result<()> {
+        let mut manager = self.memory_manager.write().await;
+        manager.pin_instruction(instruction).await
+            .map_err(|e| crate::error::ComrudeError::Memory(e.to_string()))
+    }
+
     /// Get conversation summary with optional limit
     pub async fn get_conversation_summary(&self, limit: Option<usize>) -> Result<Vec<crate::memory::ConversationTurn>> {
         let manager = self.memory_manager.read().await;
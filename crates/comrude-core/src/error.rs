@@ -84,7 +84,12 @@ pub enum ProviderError {
     RateLimited(String),
 
     #[error("API error from {provider}: {message}")]
-    ApiError { provider: String, message: String },
+    ApiError {
+        provider: String,
+        message: String,
+        /// Status of the failed HTTP response, if the error came from one
+        status: Option<u16>,
+    },
 
     #[error("Model not available: {model} on provider {provider}")]
     ModelNotAvailable { provider: String, model: String },
@@ -94,6 +99,53 @@ pub enum ProviderError {
 
     #[error("Invalid response from provider {0}")]
     InvalidResponse(String),
+
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+}
+
+impl ProviderError {
+    /// Build the error for a non-success HTTP response from a provider
+    pub fn from_http_status(provider: &str, status: reqwest::StatusCode, body: String) -> Self {
+        let detail = format!("HTTP {}: {}", status, body);
+        match status.as_u16() {
+            401 | 403 => ProviderError::AuthFailed(format!("{} ({})", provider, detail)),
+            429 => ProviderError::RateLimited(format!("{} ({})", provider, detail)),
+            code => ProviderError::ApiError {
+                provider: provider.to_string(),
+                message: detail,
+                status: Some(code),
+            },
+        }
+    }
+
+    /// HTTP status carried by an `ApiError` built from a failed response
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            ProviderError::ApiError { status, .. } => *status,
+            _ => None,
+        }
+    }
+}
+
+impl ComrudeError {
+    /// Whether the same request may succeed if sent again after a pause
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ComrudeError::Provider(ProviderError::RateLimited(_) | ProviderError::Timeout(_)) => true,
+            // Includes Anthropic's 529 "overloaded"
+            ComrudeError::Provider(error) => {
+                error.http_status().is_some_and(|status| (500..600).contains(&status))
+            }
+            ComrudeError::Network(error) => error.is_timeout() || error.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// Whether another provider might succeed where this one failed
+    pub fn is_failover_candidate(&self) -> bool {
+        self.is_retryable() || matches!(self, ComrudeError::Provider(ProviderError::AuthFailed(_)))
+    }
 }
 
 pub type Result<T> = std::result::Result<T, ComrudeError>;
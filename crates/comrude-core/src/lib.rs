@@ -1,11 +1,23 @@
+pub mod agent;
 pub mod config;
+pub mod context_budget;
+pub mod diff;
 pub mod engine;
 pub mod error;
 pub mod memory;
+pub mod summary;
+pub mod tokenizer;
 pub mod types;
+pub mod usage;
 
+pub use agent::*;
 pub use config::*;
+pub use context_budget::*;
+pub use diff::*;
 pub use engine::*;
 pub use error::*;
 pub use memory::*;
-pub use types::*;
\ No newline at end of file
+pub use summary::*;
+pub use tokenizer::*;
+pub use types::*;
+pub use usage::*;
\ No newline at end of file
@@ -1,11 +1,16 @@
 use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
+use std::sync::Arc;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use tokio::fs;
+use crate::agent::AgentStep;
+use crate::context_budget::{AllocationReport, ContextAllocation, ContextBudget, ContextCandidate, ContextPriority};
 use crate::types::{Message, ContextItem};
 use crate::error::ComrudeResult;
+use crate::summary::Summarizer;
+use crate::tokenizer::{BpeTokenizer, Tokenizer};
 
 /// Configuration for the memory system
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +27,12 @@ pub struct MemoryConfig {
     pub session_storage_path: PathBuf,
     /// Maximum age of sessions before archival (in days)
     pub session_max_age_days: u32,
+    /// Provider that writes summaries; the keyword summary is used without one
+    #[serde(default)]
+    pub summary_provider: Option<String>,
+    /// Model of `summary_provider` to summarize with, its default when unset
+    #[serde(default)]
+    pub summary_model: Option<String>,
 }
 
 impl Default for MemoryConfig {
@@ -33,6 +44,8 @@ impl Default for MemoryConfig {
             enable_summarization: true,
             session_storage_path: PathBuf::from(".comrude/sessions"),
             session_max_age_days: 30,
+            summary_provider: None,
+            summary_model: None,
         }
     }
 }
@@ -46,6 +59,9 @@ pub struct ConversationTurn {
     pub assistant_response: Option<Message>,
     pub context_snapshot: Vec<ContextItem>,
     pub tokens_used: u32,
+    /// Tool-using steps taken while answering, in order
+    #[serde(default)]
+    pub agent_steps: Vec<AgentStep>,
 }
 
 /// Differential representation of content changes between contexts
@@ -56,13 +72,19 @@ pub struct ContextDiff {
     pub removed_item_ids: Vec<String>,
     pub modified_items: Vec<ModifiedContextItem>,
     pub compression_ratio: f32,
+    /// Keys of the new context's items in order, so applying the diff
+    /// rebuilds the same order
+    #[serde(default)]
+    pub item_order: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModifiedContextItem {
+    /// Key of the item, see `DiffEngine::keyed`
     pub item_id: String,
     pub previous_content_hash: String,
-    pub content_diff: String, // Text-based diff representation
+    /// Line patch from the previous content to the new one, see `diff::line_patch`
+    pub content_diff: String,
 }
 
 /// Session containing conversation history and context
@@ -76,6 +98,9 @@ pub struct ConversationSession {
     pub cumulative_context: Vec<ContextItem>,
     pub session_metadata: HashMap<String, serde_json::Value>,
     pub config: MemoryConfig,
+    /// Instructions sent with every request, whatever else is dropped
+    #[serde(default)]
+    pub pinned_instructions: Vec<String>,
 }
 
 /// Core memory management system
@@ -85,6 +110,11 @@ pub struct ContextMemoryManager {
     config: MemoryConfig,
     session_cache: HashMap<Uuid, ConversationSession>,
     diff_engine: DiffEngine,
+    summarizer: Option<Arc<dyn Summarizer>>,
+    tokenizer: Arc<dyn Tokenizer>,
+    /// Tokens the current model reads, `max_context_tokens` when unknown
+    context_window: Option<usize>,
+    last_allocation: Option<AllocationReport>,
 }
 
 /// Engine for computing and applying diffs between contexts
@@ -103,9 +133,32 @@ impl ContextMemoryManager {
             config,
             session_cache: HashMap::new(),
             diff_engine: DiffEngine::new(),
+            summarizer: None,
+            tokenizer: Arc::new(BpeTokenizer::cl100k()),
+            context_window: None,
+            last_allocation: None,
         }
     }
 
+    /// Size requests for a model that reads `tokens`, usually its `ModelInfo::context_length`
+    pub fn set_context_window(&mut self, tokens: Option<usize>) {
+        self.context_window = tokens;
+    }
+
+    /// Count tokens the way the current model does, see `tokenizer_for`
+    pub fn set_tokenizer(&mut self, tokenizer: Arc<dyn Tokenizer>) {
+        self.tokenizer = tokenizer;
+    }
+
+    pub fn tokenizer(&self) -> Arc<dyn Tokenizer> {
+        self.tokenizer.clone()
+    }
+
+    /// Have old turns summarized by `summarizer` instead of by keywords
+    pub fn set_summarizer(&mut self, summarizer: Arc<dyn Summarizer>) {
+        self.summarizer = Some(summarizer);
+    }
+
     /// Create a new conversation session
     pub async fn create_session(&mut self, name: Option<String>) -> ComrudeResult<Uuid> {
         let session_id = Uuid::new_v4();
@@ -121,6 +174,7 @@ impl ContextMemoryManager {
             cumulative_context: Vec::new(),
             session_metadata: HashMap::new(),
             config: self.config.clone(),
+            pinned_instructions: Vec::new(),
         };
 
         self.current_session = Some(session.clone());
@@ -139,7 +193,7 @@ impl ContextMemoryManager {
         context: Vec<ContextItem>,
     ) -> ComrudeResult<Uuid> {
         let turn_id = Uuid::new_v4();
-        let tokens_estimate = Self::estimate_tokens(&user_message, &context);
+        let tokens_estimate = Self::estimate_tokens(self.tokenizer.as_ref(), &user_message, &context);
 
         let conversation_turn = ConversationTurn {
             id: turn_id,
@@ -148,6 +202,7 @@ impl ContextMemoryManager {
             assistant_response: None,
             context_snapshot: context.clone(),
             tokens_used: tokens_estimate,
+            agent_steps: Vec::new(),
         };
 
         // Extract session_id for later use
@@ -203,7 +258,7 @@ impl ContextMemoryManager {
 
             if let Some(turn) = session.conversation_turns.iter_mut()
                 .find(|turn| turn.id == turn_id) {
-                let response_tokens = Self::estimate_response_tokens(&Some(assistant_response.clone()));
+                let response_tokens = Self::estimate_response_tokens(self.tokenizer.as_ref(), &Some(assistant_response.clone()));
                 turn.assistant_response = Some(assistant_response);
                 turn.tokens_used += response_tokens;
             }
@@ -221,34 +276,145 @@ impl ContextMemoryManager {
         Ok(())
     }
 
+    /// Append an agent step to a conversation turn
+    pub async fn add_agent_step(&mut self, turn_id: Uuid, step: AgentStep) -> ComrudeResult<()> {
+        let session_id = {
+            let session = self.current_session.as_mut()
+                .ok_or_else(|| crate::error::ComrudeError::InvalidState("No active session".to_string()))?;
+
+            if let Some(turn) = session.conversation_turns.iter_mut()
+                .find(|turn| turn.id == turn_id) {
+                turn.tokens_used += step.tokens_used.total_tokens;
+                turn.agent_steps.push(step);
+            }
+
+            session.updated_at = Utc::now();
+            session.id
+        };
+
+        // Update cache and persist
+        {
+            let session = self.current_session.as_ref().unwrap();
+            self.session_cache.insert(session_id, session.clone());
+        }
+        self.save_session(session_id).await?;
+
+        Ok(())
+    }
+
+    /// Keep an instruction in every request from now on
+    pub async fn pin_instruction(&mut self, instruction: String) -> ComrudeResult<()> {
+        let session_id = {
+            let session = self.current_session.as_mut()
+                .ok_or_else(|| crate::error::ComrudeError::InvalidState("No active session".to_string()))?;
+            session.pinned_instructions.push(instruction);
+            session.updated_at = Utc::now();
+            session.id
+        };
+
+        // Update cache and persist
+        {
+            let session = self.current_session.as_ref().unwrap();
+            self.session_cache.insert(session_id, session.clone());
+        }
+        self.save_session(session_id).await?;
+
+        Ok(())
+    }
+
     /// Get contextual information for the next LLM request
     pub fn get_context_for_request(&self) -> ComrudeResult<Vec<ContextItem>> {
+        Ok(self.allocate_context("", "", Vec::new(), 0)?.items)
+    }
+
+    /// Choose the context for a request so it fits the model's window with
+    /// `reserved_output` tokens left for the answer, see `ContextBudget`
+    ///
+    /// The report is kept for `last_allocation`.
+    pub fn assemble_context(
+        &mut self,
+        system_prompt: &str,
+        prompt: &str,
+        attached: Vec<ContextItem>,
+        reserved_output: usize,
+    ) -> ComrudeResult<ContextAllocation> {
+        let allocation = self.allocate_context(system_prompt, prompt, attached, reserved_output)?;
+        self.last_allocation = Some(allocation.report.clone());
+        Ok(allocation)
+    }
+
+    /// How the last assembled request used the window
+    pub fn last_allocation(&self) -> Option<&AllocationReport> {
+        self.last_allocation.as_ref()
+    }
+
+    fn allocate_context(
+        &self,
+        system_prompt: &str,
+        prompt: &str,
+        attached: Vec<ContextItem>,
+        reserved_output: usize,
+    ) -> ComrudeResult<ContextAllocation> {
         let session = self.current_session.as_ref()
             .ok_or_else(|| crate::error::ComrudeError::InvalidState("No active session".to_string()))?;
 
-        let mut context_items = Vec::new();
-
-        // Add conversation history as context
-        let recent_turns = session.conversation_turns.iter()
-            .rev()
-            .take(self.config.max_context_turns);
+        let mut candidates = Vec::new();
+        for instruction in &session.pinned_instructions {
+            let item = self.message_to_context_item(&Message::new_system(format!("[PERSISTENT INSTRUCTION] {}", instruction)), "system");
+            candidates.push(ContextCandidate::new(ContextPriority::PinnedInstruction, item));
+        }
+        candidates.extend(attached.into_iter().map(|item| ContextCandidate::new(ContextPriority::Attached, item)));
 
-        for turn in recent_turns {
-            // Add user message as context
-            context_items.push(self.message_to_context_item(&turn.user_message, "user"));
+        // The turn being answered goes in as the prompt
+        let mut turns: Vec<&ConversationTurn> = session.conversation_turns.iter().collect();
+        if turns.last().is_some_and(|turn| turn.assistant_response.is_none()) {
+            turns.pop();
+        }
+        let conversation_turns = turns.iter().filter(|turn| Self::turn_kind(turn).is_none()).count();
+        let recent_from = conversation_turns.saturating_sub(self.config.max_context_turns.div_ceil(2).max(1));
 
-            // Add assistant response if available
-            if let Some(ref response) = turn.assistant_response {
-                context_items.push(self.message_to_context_item(response, "assistant"));
+        let mut position = 0;
+        for turn in turns {
+            match Self::turn_kind(turn) {
+                Some(ContextPriority::Summary) => {
+                    if let Some(ref summary) = turn.assistant_response {
+                        candidates.push(ContextCandidate::new(ContextPriority::Summary, self.message_to_context_item(summary, "summary")));
+                    }
+                }
+                // Instructions pinned before they had a place of their own
+                Some(priority) => {
+                    candidates.push(ContextCandidate::new(priority, self.message_to_context_item(&turn.user_message, "system")));
+                }
+                None => {
+                    let priority = if position >= recent_from { ContextPriority::RecentTurn } else { ContextPriority::OlderTurn };
+                    position += 1;
+                    candidates.push(ContextCandidate::new(priority, self.message_to_context_item(&turn.user_message, "user")));
+                    if let Some(ref response) = turn.assistant_response {
+                        candidates.push(ContextCandidate::new(priority, self.message_to_context_item(response, "assistant")));
+                    }
+                }
             }
         }
 
         // Apply diff compression to reduce redundancy
         if self.config.enable_diff_compression {
-            context_items = self.diff_engine.compress_context_items(context_items)?;
+            candidates = self.diff_engine.compress_context_items(candidates);
         }
 
-        Ok(context_items)
+        let budget = ContextBudget {
+            context_window: self.context_window.unwrap_or(self.config.max_context_tokens),
+            reserved_output,
+        };
+        Ok(budget.allocate(self.tokenizer.as_ref(), system_prompt, prompt, candidates))
+    }
+
+    /// Summary and pinned-instruction turns, which are not conversation
+    fn turn_kind(turn: &ConversationTurn) -> Option<ContextPriority> {
+        match &turn.user_message.content {
+            crate::types::MessageContent::Text(text) if text.starts_with("[SUMMARY]") => Some(ContextPriority::Summary),
+            crate::types::MessageContent::Text(text) if text.starts_with("[PERSISTENT INSTRUCTION]") => Some(ContextPriority::PinnedInstruction),
+            _ => None,
+        }
     }
 
     /// Get conversation history formatted for display
@@ -282,7 +448,7 @@ impl ContextMemoryManager {
         }
 
         let session_data = fs::read_to_string(&session_path).await
-            .map_err(|e| crate::error::ComrudeError::IoError(e))?;
+            .map_err(crate::error::ComrudeError::IoError)?;
 
         let session: ConversationSession = serde_json::from_str(&session_data)
             .map_err(|e| crate::error::ComrudeError::SerializationError(e.to_string()))?;
@@ -302,14 +468,14 @@ impl ContextMemoryManager {
 
         // Ensure storage directory exists
         fs::create_dir_all(&self.config.session_storage_path).await
-            .map_err(|e| crate::error::ComrudeError::IoError(e))?;
+            .map_err(crate::error::ComrudeError::IoError)?;
 
         let session_path = self.get_session_path(session_id);
         let session_data = serde_json::to_string_pretty(session)
             .map_err(|e| crate::error::ComrudeError::SerializationError(e.to_string()))?;
 
         fs::write(&session_path, session_data).await
-            .map_err(|e| crate::error::ComrudeError::IoError(e))?;
+            .map_err(crate::error::ComrudeError::IoError)?;
 
         Ok(())
     }
@@ -323,14 +489,14 @@ impl ContextMemoryManager {
         }
 
         let mut entries = fs::read_dir(&self.config.session_storage_path).await
-            .map_err(|e| crate::error::ComrudeError::IoError(e))?;
+            .map_err(crate::error::ComrudeError::IoError)?;
 
         while let Some(entry) = entries.next_entry().await
-            .map_err(|e| crate::error::ComrudeError::IoError(e))? {
+            .map_err(crate::error::ComrudeError::IoError)? {
             
             if let Some(filename) = entry.file_name().to_str() {
-                if filename.ends_with(".json") {
-                    if let Ok(session_id) = Uuid::parse_str(&filename[..filename.len()-5]) {
+                if let Some(stem) = filename.strip_suffix(".json") {
+                    if let Ok(session_id) = Uuid::parse_str(stem) {
                         // Quick metadata read without full session load
                         if let Ok(metadata) = self.read_session_metadata(session_id).await {
                             sessions.push((session_id, metadata.0, metadata.1));
@@ -341,7 +507,7 @@ impl ContextMemoryManager {
         }
 
         // Sort by last updated (most recent first)
-        sessions.sort_by(|a, b| b.2.cmp(&a.2));
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.2));
 
         Ok(sessions)
     }
@@ -374,30 +540,6 @@ impl ContextMemoryManager {
         Ok(())
     }
 
-    async fn apply_context_compression(
-        &self,
-        session: &mut ConversationSession,
-        new_context: &[ContextItem],
-    ) -> ComrudeResult<()> {
-        // Create diff from previous context
-        if !session.cumulative_context.is_empty() {
-            let diff = self.diff_engine.create_context_diff(
-                &session.cumulative_context,
-                new_context,
-            )?;
-
-            // Update cumulative context with diff
-            session.cumulative_context = self.diff_engine.apply_diff(
-                &session.cumulative_context,
-                &diff,
-            )?;
-        } else {
-            session.cumulative_context = new_context.to_vec();
-        }
-
-        Ok(())
-    }
-
     async fn maintain_context_window_for_current_session(&mut self) -> ComrudeResult<()> {
         let session = self.current_session.as_mut()
             .ok_or_else(|| crate::error::ComrudeError::InvalidState("No active session".to_string()))?;
@@ -408,125 +550,106 @@ impl ContextMemoryManager {
         }
 
         // Check token limit and compress if needed
-        let total_tokens: u32 = session.conversation_turns.iter()
-            .map(|turn| turn.tokens_used)
-            .sum();
-
-        if total_tokens > self.config.max_context_tokens as u32 {
-            if self.config.enable_summarization {
-                // Intelligent summarization inline to avoid borrow conflicts
-                let turns_to_keep = self.config.max_context_turns / 2;
-                let turns_count = session.conversation_turns.len();
-                
-                if turns_count > turns_to_keep {
-                    let turns_to_summarize = turns_count - turns_to_keep;
-                    let mut summarized_turns = Vec::new();
-                    
-                    // Extract oldest turns for summarization
-                    for _ in 0..turns_to_summarize {
-                        if let Some(turn) = session.conversation_turns.pop_front() {
-                            summarized_turns.push(turn);
-                        }
-                    }
-                    
-                    // Create a condensed summary of the old conversations
-                    let summary = Self::create_conversation_summary(&summarized_turns)?;
-                    
-                    // Create a summary turn to represent the condensed conversation
-                    let summary_turn = ConversationTurn {
-                        id: Uuid::new_v4(),
-                        timestamp: Utc::now(),
-                        user_message: Message::new_system(format!("[SUMMARY] Previous conversation containing {} turns", summarized_turns.len())),
-                        assistant_response: Some(Message::new_system(summary)),
-                        context_snapshot: Vec::new(),
-                        tokens_used: Self::estimate_tokens(
-                            &Message::new_system("[SUMMARY]".to_string()), 
-                            &[]
-                        ),
-                    };
-                    
-                    // Insert summary at the beginning
-                    session.conversation_turns.push_front(summary_turn);
-                    
-                    // Update metadata to track summarization
-                    session.session_metadata.insert(
-                        "last_summarization".to_string(),
-                        serde_json::Value::String(Utc::now().to_rfc3339())
-                    );
-                    session.session_metadata.insert(
-                        "turns_summarized".to_string(),
-                        serde_json::Value::Number(serde_json::Number::from(turns_to_summarize))
-                    );
-                }
-            } else {
+        if Self::over_token_limit(&self.config, session) {
+            if !self.config.enable_summarization {
                 // Fallback: just remove oldest turns
                 while session.conversation_turns.len() > self.config.max_context_turns / 2 {
                     session.conversation_turns.pop_front();
                 }
+            } else if self.summarizer.is_none() {
+                // Keyword summaries are cheap to write in place; a summarizer's
+                // are left to `pending_summary` so no lock is held meanwhile
+                if let Some(turns) = Self::turns_to_summarize(&self.config, session) {
+                    Self::replace_with_summary(self.tokenizer.as_ref(), session, &turns, None)?;
+                }
             }
         }
 
         Ok(())
     }
 
-    async fn maintain_context_window(&self, session: &mut ConversationSession) -> ComrudeResult<()> {
-        // Remove old turns if exceeding max context
-        while session.conversation_turns.len() > self.config.max_context_turns {
-            session.conversation_turns.pop_front();
-        }
-
-        // Check token limit and compress if needed
+    fn over_token_limit(config: &MemoryConfig, session: &ConversationSession) -> bool {
         let total_tokens: u32 = session.conversation_turns.iter()
             .map(|turn| turn.tokens_used)
             .sum();
+        total_tokens > config.max_context_tokens as u32
+    }
 
-        if total_tokens > self.config.max_context_tokens as u32 {
-            if self.config.enable_summarization {
-                // TODO: Implement intelligent summarization
-                // For now, just remove oldest turns
-                while session.conversation_turns.len() > self.config.max_context_turns / 2 {
-                    session.conversation_turns.pop_front();
-                }
-            }
+    /// The oldest turns, leaving half the turn limit as recent ones
+    fn turns_to_summarize(config: &MemoryConfig, session: &ConversationSession) -> Option<Vec<ConversationTurn>> {
+        let turns_to_keep = config.max_context_turns / 2;
+        let turns_count = session.conversation_turns.len();
+        if turns_count <= turns_to_keep {
+            return None; // Nothing to summarize
         }
+        Some(session.conversation_turns.iter().take(turns_count - turns_to_keep).cloned().collect())
+    }
 
-        Ok(())
+    /// Turns the summarizer should fold into a summary, when the current
+    /// session is over its token limit
+    ///
+    /// Summarizing may take a model request, so callers run it without
+    /// holding the manager and hand the result to `apply_summary`.
+    pub fn pending_summary(&self) -> Option<(Arc<dyn Summarizer>, Vec<ConversationTurn>)> {
+        let summarizer = self.summarizer.clone().filter(|_| self.config.enable_summarization)?;
+        let session = self.current_session.as_ref()?;
+        if !Self::over_token_limit(&self.config, session) {
+            return None;
+        }
+        Some((summarizer, Self::turns_to_summarize(&self.config, session)?))
     }
 
-    /// Intelligent summarization strategy for context compression
-    async fn intelligent_summarization(&self, session: &mut ConversationSession) -> ComrudeResult<()> {
-        // Strategy: Summarize older conversation turns while preserving recent ones
-        let turns_to_keep = self.config.max_context_turns / 2; // Keep half the limit as recent
-        let turns_count = session.conversation_turns.len();
-        
-        if turns_count <= turns_to_keep {
-            return Ok(()); // Nothing to summarize
+    /// Replace `turns` from `pending_summary` with a summary turn, the
+    /// keyword summary when the summarizer gave none
+    ///
+    /// Nothing happens when the session changed in the meantime and the
+    /// turns are no longer its oldest.
+    pub async fn apply_summary(&mut self, turns: &[ConversationTurn], summary: Option<String>) -> ComrudeResult<()> {
+        let Some(session) = self.current_session.as_mut() else { return Ok(()) };
+        if !Self::replace_with_summary(self.tokenizer.as_ref(), session, turns, summary)? {
+            return Ok(());
         }
-        
-        let turns_to_summarize = turns_count - turns_to_keep;
-        let mut summarized_turns = Vec::new();
-        
-        // Extract oldest turns for summarization
-        for _ in 0..turns_to_summarize {
-            if let Some(turn) = session.conversation_turns.pop_front() {
-                summarized_turns.push(turn);
-            }
+
+        let session_id = session.id;
+        self.session_cache.insert(session_id, session.clone());
+        self.save_session(session_id).await
+    }
+
+    /// Intelligent summarization strategy for context compression
+    ///
+    /// Swaps `turns`, which must be the session's oldest, for one summary
+    /// turn. The summary is `model_summary` when there is one and keywords
+    /// otherwise. Returns whether the turns were still there to replace.
+    fn replace_with_summary(
+        tokenizer: &dyn Tokenizer,
+        session: &mut ConversationSession,
+        turns: &[ConversationTurn],
+        model_summary: Option<String>,
+    ) -> ComrudeResult<bool> {
+        let still_oldest = turns.len() <= session.conversation_turns.len()
+            && turns.iter().zip(&session.conversation_turns).all(|(turn, current)| turn.id == current.id);
+        if turns.is_empty() || !still_oldest {
+            return Ok(false);
         }
-        
+        session.conversation_turns.drain(..turns.len());
+
         // Create a condensed summary of the old conversations
-        let summary = ContextMemoryManager::create_conversation_summary(&summarized_turns)?;
+        let summarized_by = if model_summary.is_some() { "model" } else { "keywords" };
+        let summary = match model_summary {
+            Some(summary) => summary,
+            None => ContextMemoryManager::create_conversation_summary(turns)?,
+        };
+        let summary_message = Message::new_system(summary);
         
         // Create a summary turn to represent the condensed conversation
         let summary_turn = ConversationTurn {
             id: Uuid::new_v4(),
             timestamp: Utc::now(),
-            user_message: Message::new_system(format!("[SUMMARY] Previous conversation containing {} turns", summarized_turns.len())),
-            assistant_response: Some(Message::new_system(summary)),
+            user_message: Message::new_system(format!("[SUMMARY] Previous conversation containing {} turns", turns.len())),
+            tokens_used: Self::estimate_response_tokens(tokenizer, &Some(summary_message.clone())),
+            assistant_response: Some(summary_message),
             context_snapshot: Vec::new(),
-            tokens_used: Self::estimate_tokens(
-                &Message::new_system("[SUMMARY]".to_string()), 
-                &[]
-            ),
+            agent_steps: Vec::new(),
         };
         
         // Insert summary at the beginning
@@ -539,10 +662,14 @@ impl ContextMemoryManager {
         );
         session.session_metadata.insert(
             "turns_summarized".to_string(),
-            serde_json::Value::Number(serde_json::Number::from(turns_to_summarize))
+            serde_json::Value::Number(serde_json::Number::from(turns.len()))
+        );
+        session.session_metadata.insert(
+            "summarized_by".to_string(),
+            serde_json::Value::String(summarized_by.to_string())
         );
         
-        Ok(())
+        Ok(true)
     }
     
     /// Create a condensed summary from conversation turns
@@ -740,31 +867,34 @@ impl ContextMemoryManager {
         found_actions.into_iter().take(3).collect()
     }
 
-    fn estimate_tokens(message: &Message, context: &[ContextItem]) -> u32 {
-        // Simple token estimation (roughly 4 characters per token)
-        let message_tokens = match &message.content {
-            crate::types::MessageContent::Text(text) => text.len() / 4,
-            crate::types::MessageContent::Code { content, .. } => content.len() / 4,
-            _ => 50, // Default estimation for other types
-        };
+    fn estimate_tokens(tokenizer: &dyn Tokenizer, message: &Message, context: &[ContextItem]) -> u32 {
+        let message_tokens = Self::message_tokens(tokenizer, message);
 
         let context_tokens: usize = context.iter()
-            .map(|item| item.content.len() / 4)
+            .map(|item| tokenizer.count(&item.content))
             .sum();
 
         (message_tokens + context_tokens) as u32
     }
 
-    fn estimate_response_tokens(response: &Option<Message>) -> u32 {
+    fn estimate_response_tokens(tokenizer: &dyn Tokenizer, response: &Option<Message>) -> u32 {
         response.as_ref()
-            .map(|msg| match &msg.content {
-                crate::types::MessageContent::Text(text) => text.len() / 4,
-                crate::types::MessageContent::Code { content, .. } => content.len() / 4,
-                _ => 50,
-            })
+            .map(|msg| Self::message_tokens(tokenizer, msg))
             .unwrap_or(0) as u32
     }
 
+    fn message_tokens(tokenizer: &dyn Tokenizer, message: &Message) -> usize {
+        match &message.content {
+            crate::types::MessageContent::Text(text) => tokenizer.count(text),
+            crate::types::MessageContent::Code { content, .. } => tokenizer.count(content),
+            crate::types::MessageContent::File { path, preview } => {
+                tokenizer.count(path) + preview.as_deref().map_or(0, |preview| tokenizer.count(preview))
+            }
+            crate::types::MessageContent::Error { error_type, message } => tokenizer.count(error_type) + tokenizer.count(message),
+            crate::types::MessageContent::Progress { stage, .. } => tokenizer.count(stage),
+        }
+    }
+
     fn message_to_context_item(&self, message: &Message, role: &str) -> ContextItem {
         let content = match &message.content {
             crate::types::MessageContent::Text(text) => text.clone(),
@@ -793,7 +923,7 @@ impl ContextMemoryManager {
     async fn read_session_metadata(&self, session_id: Uuid) -> ComrudeResult<(String, DateTime<Utc>)> {
         let session_path = self.get_session_path(session_id);
         let session_data = fs::read_to_string(&session_path).await
-            .map_err(|e| crate::error::ComrudeError::IoError(e))?;
+            .map_err(crate::error::ComrudeError::IoError)?;
 
         // Parse only the metadata we need
         let session_value: serde_json::Value = serde_json::from_str(&session_data)
@@ -824,51 +954,33 @@ impl DiffEngine {
         new_context: &[ContextItem],
     ) -> ComrudeResult<ContextDiff> {
         let base_context_id = Uuid::new_v4();
+        let old_items = Self::keyed(old_context);
+        let new_items = Self::keyed(new_context);
+        let old_map: HashMap<&str, &ContextItem> = old_items.iter().map(|(key, item)| (key.as_str(), *item)).collect();
+        let new_keys: std::collections::HashSet<&str> = new_items.iter().map(|(key, _)| key.as_str()).collect();
+
         let mut added_items = Vec::new();
-        let mut removed_item_ids = Vec::new();
         let mut modified_items = Vec::new();
+        for (key, new_item) in &new_items {
+            let Some(old_item) = old_map.get(key.as_str()) else {
+                added_items.push((*new_item).clone());
+                continue;
+            };
 
-        // Create maps for efficient lookup
-        let old_map: HashMap<String, &ContextItem> = old_context.iter()
-            .enumerate()
-            .map(|(i, item)| (i.to_string(), item))
-            .collect();
-
-        let new_map: HashMap<String, &ContextItem> = new_context.iter()
-            .enumerate()
-            .map(|(i, item)| (i.to_string(), item))
-            .collect();
-
-        // Find added items
-        for (key, item) in &new_map {
-            if !old_map.contains_key(key) {
-                added_items.push((*item).clone());
-            }
-        }
-
-        // Find removed items
-        for key in old_map.keys() {
-            if !new_map.contains_key(key) {
-                removed_item_ids.push(key.clone());
-            }
-        }
-
-        // Find modified items
-        for (key, new_item) in &new_map {
-            if let Some(old_item) = old_map.get(key) {
-                let old_hash = self.content_hasher.hash_content(&old_item.content);
-                let new_hash = self.content_hasher.hash_content(&new_item.content);
-                
-                if old_hash != new_hash {
-                    let content_diff = self.compute_text_diff(&old_item.content, &new_item.content);
-                    modified_items.push(ModifiedContextItem {
-                        item_id: key.clone(),
-                        previous_content_hash: old_hash,
-                        content_diff,
-                    });
-                }
+            let old_hash = self.content_hasher.hash_content(&old_item.content);
+            let new_hash = self.content_hasher.hash_content(&new_item.content);
+            if old_hash != new_hash {
+                modified_items.push(ModifiedContextItem {
+                    item_id: key.clone(),
+                    previous_content_hash: old_hash,
+                    content_diff: self.compute_text_diff(&old_item.content, &new_item.content),
+                });
             }
         }
+        let removed_item_ids = old_items.iter()
+            .filter(|(key, _)| !new_keys.contains(key.as_str()))
+            .map(|(key, _)| key.clone())
+            .collect();
 
         // Calculate compression ratio
         let original_size = old_context.iter().map(|item| item.content.len()).sum::<usize>();
@@ -887,75 +999,90 @@ impl DiffEngine {
             removed_item_ids,
             modified_items,
             compression_ratio,
+            item_order: new_items.into_iter().map(|(key, _)| key).collect(),
         })
     }
 
+    /// Each item's `ContextItem::id`, with `#2`, `#3`... appended to repeats
+    /// so every item in a context has a distinct key
+    fn keyed(items: &[ContextItem]) -> Vec<(String, &ContextItem)> {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        items.iter().map(|item| {
+            let id = item.id();
+            let count = seen.entry(id.clone()).or_insert(0);
+            *count += 1;
+            let key = if *count == 1 { id } else { format!("{}#{}", id, count) };
+            (key, item)
+        }).collect()
+    }
+
     fn apply_diff(
         &self,
         base_context: &[ContextItem],
         diff: &ContextDiff,
     ) -> ComrudeResult<Vec<ContextItem>> {
-        let mut result = base_context.to_vec();
-
-        // Remove items
-        result.retain(|item| {
-            let item_index = base_context.iter().position(|x| std::ptr::eq(x, item))
-                .map(|i| i.to_string())
-                .unwrap_or_default();
-            !diff.removed_item_ids.contains(&item_index)
-        });
-
-        // Apply modifications
-        for modification in &diff.modified_items {
-            if let Ok(index) = modification.item_id.parse::<usize>() {
-                if index < result.len() {
-                    // Apply text diff (simplified - in production would use proper diff algorithm)
-                    result[index].content = modification.content_diff.clone();
+        let mut items: HashMap<String, ContextItem> = HashMap::new();
+        let mut base_order = Vec::with_capacity(base_context.len());
+
+        for (key, item) in Self::keyed(base_context) {
+            if diff.removed_item_ids.contains(&key) {
+                continue;
+            }
+
+            let mut item = item.clone();
+            if let Some(modification) = diff.modified_items.iter().find(|m| m.item_id == key) {
+                // A patch only rebuilds the content it was made against
+                if self.content_hasher.hash_content(&item.content) != modification.previous_content_hash {
+                    return Err(crate::error::ComrudeError::Memory(format!(
+                        "context item {} changed since its diff was computed", key
+                    )));
                 }
+                item.content = crate::diff::apply_line_patch(&item.content, &modification.content_diff)
+                    .map_err(|e| crate::error::ComrudeError::Memory(format!(
+                        "cannot apply diff to context item {}: {}", key, e
+                    )))?;
             }
+            base_order.push(key.clone());
+            items.insert(key, item);
         }
 
-        // Add new items
-        result.extend(diff.added_items.clone());
+        // Diffs from before the order was recorded keep base items first
+        if diff.item_order.is_empty() {
+            let mut result: Vec<ContextItem> = base_order.iter().filter_map(|key| items.remove(key)).collect();
+            result.extend(diff.added_items.iter().cloned());
+            return Ok(result);
+        }
 
-        Ok(result)
+        // Keys missing from the base belong to the added items, in the same order
+        let mut added = diff.added_items.iter().cloned();
+        Ok(diff.item_order.iter().filter_map(|key| items.remove(key).or_else(|| added.next())).collect())
     }
 
-    fn compress_context_items(&self, items: Vec<ContextItem>) -> ComrudeResult<Vec<ContextItem>> {
-        // Simple deduplication based on content hashes
+    fn compress_context_items(&self, candidates: Vec<ContextCandidate>) -> Vec<ContextCandidate> {
+        // Simple deduplication based on content hashes; pinned and attached
+        // items come first, so their copies are the ones kept
         let mut seen_hashes = std::collections::HashSet::new();
         let mut compressed = Vec::new();
 
-        for item in items {
-            let content_hash = self.content_hasher.hash_content(&item.content);
+        for candidate in candidates {
+            let content_hash = self.content_hasher.hash_content(&candidate.item.content);
             if !seen_hashes.contains(&content_hash) {
                 seen_hashes.insert(content_hash);
-                compressed.push(item);
+                compressed.push(candidate);
             }
         }
 
-        Ok(compressed)
+        compressed
     }
 
     fn compute_text_diff(&self, old_text: &str, new_text: &str) -> String {
-        // Simplified diff - in production would use proper diff algorithm like Myers
-        if old_text == new_text {
-            new_text.to_string()
-        } else {
-            format!("DIFF: {} -> {}", old_text.len(), new_text.len())
-        }
+        crate::diff::line_patch(old_text, new_text)
     }
 }
 
 impl ContentHasher {
     fn hash_content(&self, content: &str) -> String {
-        // Simple hash implementation - in production would use SHA-256 or similar
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-
-        let mut hasher = DefaultHasher::new();
-        content.hash(&mut hasher);
-        format!("{:x}", hasher.finish())
+        crate::types::content_hash(content)
     }
 }
 
@@ -1039,30 +1166,146 @@ mod tests {
         assert_eq!(summary.len(), 2); // Should maintain only 2 turns
     }
 
+    #[tokio::test]
+    async fn test_assemble_context_keeps_pinned_instructions() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = MemoryConfig {
+            session_storage_path: temp_dir.path().to_path_buf(),
+            max_context_turns: 2,
+            ..Default::default()
+        };
+        let mut manager = ContextMemoryManager::new(config);
+        manager.create_session(None).await.unwrap();
+        manager.pin_instruction("Answer in Portuguese".to_string()).await.unwrap();
+
+        for i in 0..3 {
+            let turn_id = manager.add_conversation_turn(Message::new_user(format!("Question {}", i)), vec![]).await.unwrap();
+            let answer = Message::new_assistant(format!("Answer {}", i), "test".to_string(), "test-model".to_string());
+            manager.complete_conversation_turn(turn_id, answer).await.unwrap();
+        }
+        manager.add_conversation_turn(Message::new_user("Question 3".to_string()), vec![]).await.unwrap();
+
+        manager.set_context_window(Some(4096));
+        let allocation = manager.assemble_context("Be brief.", "Question 3", vec![], 1024).unwrap();
+        let contents: Vec<_> = allocation.items.iter().map(|item| item.content.as_str()).collect();
+        // Evicted turns are gone, the pinned instruction is not, and the
+        // question being answered is only sent as the prompt
+        assert_eq!(contents, ["[PERSISTENT INSTRUCTION] Answer in Portuguese", "Question 2", "Answer 2"]);
+
+        let report = manager.last_allocation().unwrap();
+        assert_eq!((report.context_window, report.reserved_output), (4096, 1024));
+        assert_eq!(report.entries[2].priority, ContextPriority::PinnedInstruction);
+        assert!(!report.trimmed());
+    }
+
+    #[derive(Debug)]
+    struct FixedSummarizer(Option<&'static str>);
+
+    #[async_trait::async_trait]
+    impl Summarizer for FixedSummarizer {
+        async fn summarize(&self, turns: &[ConversationTurn]) -> ComrudeResult<String> {
+            assert!(!turns.is_empty());
+            self.0.map(String::from)
+                .ok_or_else(|| crate::error::ComrudeError::InvalidState("model offline".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarizer_writes_summary_and_falls_back() {
+        for (summarizer, expected_by) in [(FixedSummarizer(Some("Chose tokio for the runtime")), "model"), (FixedSummarizer(None), "keywords")] {
+            let temp_dir = TempDir::new().unwrap();
+            let config = MemoryConfig {
+                session_storage_path: temp_dir.path().to_path_buf(),
+                max_context_turns: 4,
+                max_context_tokens: 10,
+                ..Default::default()
+            };
+            let mut manager = ContextMemoryManager::new(config);
+            manager.set_summarizer(Arc::new(summarizer));
+            manager.create_session(None).await.unwrap();
+
+            for i in 0..3 {
+                let user_msg = Message::new_user(format!("Which async runtime should the project use? ({})", i));
+                manager.add_conversation_turn(user_msg, vec![]).await.unwrap();
+
+                // The summarizer runs apart from the manager, as the engine does it
+                if let Some((summarizer, turns)) = manager.pending_summary() {
+                    let summary = summarizer.summarize(&turns).await.ok();
+                    manager.apply_summary(&turns, summary).await.unwrap();
+                }
+            }
+
+            let session = manager.current_session.as_ref().unwrap();
+            assert_eq!(session.session_metadata["summarized_by"], expected_by);
+            let summary = match &session.conversation_turns[0].assistant_response.as_ref().unwrap().content {
+                crate::types::MessageContent::Text(text) => text.clone(),
+                other => panic!("unexpected summary {:?}", other),
+            };
+            assert_eq!(summary == "Chose tokio for the runtime", expected_by == "model");
+
+            // A summary for turns that are gone by the time it arrives is dropped
+            let stale = session.conversation_turns.iter().skip(1).cloned().collect::<Vec<_>>();
+            let turns_before = session.conversation_turns.len();
+            manager.apply_summary(&stale, Some("stale".to_string())).await.unwrap();
+            assert_eq!(manager.current_session.as_ref().unwrap().conversation_turns.len(), turns_before);
+        }
+    }
+
     #[test]
     fn test_diff_engine() {
         let engine = DiffEngine::new();
-        
+        let text = |content: &str| ContextItem {
+            item_type: crate::types::ContextType::Text,
+            content: content.to_string(),
+            metadata: HashMap::new(),
+        };
+        let file = |content: &str| ContextItem {
+            item_type: crate::types::ContextType::File { path: "src/main.rs".to_string() },
+            content: content.to_string(),
+            metadata: HashMap::new(),
+        };
+        let contents = |items: &[ContextItem]| items.iter().map(|item| item.content.clone()).collect::<Vec<_>>();
+
         let old_context = vec![
-            ContextItem {
-                item_type: crate::types::ContextType::Text,
-                content: "Original content".to_string(),
-                metadata: HashMap::new(),
-            }
+            file("fn main() {\n    println!(\"hi\");\n}\n"),
+            text("unchanged"),
+            text("dropped"),
         ];
-
+        // Inserting an item up front must not mark the others as modified
         let new_context = vec![
-            ContextItem {
-                item_type: crate::types::ContextType::Text,
-                content: "Modified content".to_string(),
-                metadata: HashMap::new(),
-            }
+            text("inserted"),
+            file("fn main() {\n    println!(\"hello\");\n}\n"),
+            text("unchanged"),
+            text("unchanged"),
         ];
 
         let diff = engine.create_context_diff(&old_context, &new_context).unwrap();
         assert!(diff.compression_ratio > 0.0);
-        
+        assert_eq!(diff.modified_items.len(), 1);
+        assert_eq!(diff.modified_items[0].item_id, "file:src/main.rs");
+        assert_eq!(diff.added_items.len(), 2);
+        assert_eq!(diff.removed_item_ids, [text("dropped").id()]);
+
         let applied = engine.apply_diff(&old_context, &diff).unwrap();
-        assert_eq!(applied.len(), 1);
+        assert_eq!(contents(&applied), contents(&new_context));
+
+        // The diff survives being persisted and read back
+        let stored: ContextDiff = serde_json::from_str(&serde_json::to_string(&diff).unwrap()).unwrap();
+        assert_eq!(contents(&engine.apply_diff(&old_context, &stored).unwrap()), contents(&new_context));
+
+        // A patch is refused against content it was not made from
+        let stale = vec![file("fn main() {}\n"), text("unchanged"), text("dropped")];
+        assert!(engine.apply_diff(&stale, &diff).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_context_item_ids_are_stable() {
+        let item = |item_type| ContextItem { item_type, content: "abc".to_string(), metadata: HashMap::new() };
+
+        assert_eq!(item(crate::types::ContextType::File { path: "a.rs".to_string() }).id(), "file:a.rs");
+        assert_eq!(
+            item(crate::types::ContextType::Text).id(),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Summaries of old conversation turns written by a model
+//!
+//! When the context window fills up, `ContextMemoryManager` folds the oldest
+//! turns into a `[SUMMARY]` turn. With a `Summarizer` set the summary is
+//! written by a model, typically a cheap local one; without one, or when the
+//! model fails, a keyword-based summary is used instead.
+
+use crate::{
+    agent::AgentBackend,
+    error::Result,
+    memory::ConversationTurn,
+    types::{GenerationRequest, Message, MessageContent},
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Characters of a single message shown to the summarizer
+const MAX_MESSAGE_CHARS: usize = 4000;
+
+/// Writes the text of a `[SUMMARY]` turn
+#[async_trait]
+pub trait Summarizer: Send + Sync + std::fmt::Debug {
+    async fn summarize(&self, turns: &[ConversationTurn]) -> Result<String>;
+}
+
+/// A summarizer that asks a model through an `AgentBackend`
+pub struct ModelSummarizer {
+    backend: Arc<dyn AgentBackend>,
+    provider: Option<String>,
+    model: Option<String>,
+}
+
+impl ModelSummarizer {
+    /// Summaries from `provider`'s `model`; `None` leaves the choice to the backend
+    pub fn new(backend: Arc<dyn AgentBackend>, provider: Option<String>, model: Option<String>) -> Self {
+        Self { backend, provider, model }
+    }
+}
+
+impl std::fmt::Debug for ModelSummarizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ModelSummarizer")
+            .field("provider", &self.provider)
+            .field("model", &self.model)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Summarizer for ModelSummarizer {
+    async fn summarize(&self, turns: &[ConversationTurn]) -> Result<String> {
+        let mut request = summary_request(turns);
+        request.model = self.model.clone();
+        if let Some(provider) = &self.provider {
+            request.metadata.insert("preferred_provider".to_string(), serde_json::json!(provider));
+        }
+        // A failed summary falls back to keywords, never to another, possibly
+        // paid, provider that would be sent the transcript
+        request.metadata.insert("no_fallback".to_string(), serde_json::json!(true));
+
+        let response = self.backend.generate(request).await?;
+        let summary = response.content.trim();
+        if summary.is_empty() {
+            return Err(crate::error::ComrudeError::InvalidState("summarizer returned an empty summary".to_string()));
+        }
+        Ok(summary.to_string())
+    }
+}
+
+/// A request asking for a summary of `turns`
+pub fn summary_request(turns: &[ConversationTurn]) -> GenerationRequest {
+    let system_prompt = "You summarize a conversation between a developer and a coding assistant \
+        so the assistant can carry on without the full transcript. Keep every fact, decision, \
+        preference, file path, command, identifier and open question; drop greetings and filler. \
+        Do not add anything the transcript does not say. Reply with the summary only, as short \
+        paragraphs or bullet points."
+        .to_string();
+
+    GenerationRequest {
+        prompt: format!("Conversation to summarize:\n\n{}", transcript(turns)),
+        system_prompt: Some(system_prompt),
+        max_tokens: Some(600),
+        temperature: Some(0.2),
+        ..GenerationRequest::default()
+    }
+}
+
+/// The turns as plain text, earlier summaries included
+fn transcript(turns: &[ConversationTurn]) -> String {
+    let mut text = String::new();
+    for turn in turns {
+        text.push_str(&format!("User: {}\n", message_text(&turn.user_message)));
+        let tools: Vec<&str> = turn.agent_steps.iter()
+            .flat_map(|step| step.tool_calls.iter().map(|call| call.name.as_str()))
+            .collect();
+        if !tools.is_empty() {
+            text.push_str(&format!("Tools used: {}\n", tools.join(", ")));
+        }
+        if let Some(response) = &turn.assistant_response {
+            text.push_str(&format!("Assistant: {}\n", message_text(response)));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn message_text(message: &Message) -> String {
+    let text = match &message.content {
+        MessageContent::Text(text) => text.clone(),
+        MessageContent::Code { language, content } => format!("```{}\n{}\n```", language, content),
+        MessageContent::File { path, .. } => format!("[file {}]", path),
+        MessageContent::Error { message, .. } => format!("[error] {}", message),
+        MessageContent::Progress { stage, .. } => format!("[progress] {}", stage),
+    };
+    match text.char_indices().nth(MAX_MESSAGE_CHARS) {
+        Some((end, _)) => format!("{} [...]", &text[..end]),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::AgentStep,
+        memory::{ContextMemoryManager, MemoryConfig},
+        types::{FinishReason, GenerationResponse, TokenUsage, ToolCall},
+    };
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    /// Answers every request with `reply` and keeps the requests
+    struct StubBackend {
+        reply: &'static str,
+        requests: Mutex<Vec<GenerationRequest>>,
+    }
+
+    #[async_trait]
+    impl AgentBackend for StubBackend {
+        async fn generate(&self, request: GenerationRequest) -> Result<GenerationResponse> {
+            self.requests.lock().unwrap().push(request);
+            Ok(GenerationResponse {
+                content: self.reply.to_string(),
+                model_used: "llama3.2".to_string(),
+                tokens_used: TokenUsage::default(),
+                cost: 0.0,
+                finish_reason: FinishReason::Stop,
+                tool_calls: Vec::new(),
+                metadata: HashMap::new(),
+            })
+        }
+    }
+
+    fn backend(reply: &'static str) -> Arc<StubBackend> {
+        Arc::new(StubBackend { reply, requests: Mutex::new(Vec::new()) })
+    }
+
+    fn turn(question: String) -> ConversationTurn {
+        ConversationTurn {
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            user_message: Message::new_user(question),
+            assistant_response: Some(Message::new_system("Use tokio".to_string())),
+            context_snapshot: Vec::new(),
+            tokens_used: 0,
+            agent_steps: vec![AgentStep {
+                content: String::new(),
+                tool_calls: vec![ToolCall {
+                    id: "call_1".to_string(),
+                    name: "read_file".to_string(),
+                    arguments: serde_json::json!({"path": "Cargo.toml"}),
+                }],
+                results: Vec::new(),
+                tokens_used: TokenUsage::default(),
+                cost: 0.0,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_model_summarizer_stays_on_its_provider() {
+        let stub = backend("  Chose tokio for the runtime\n");
+        let summarizer = ModelSummarizer::new(stub.clone(), Some("ollama".to_string()), Some("llama3.2".to_string()));
+
+        let long_question = "x".repeat(MAX_MESSAGE_CHARS + 100);
+        let summary = summarizer.summarize(&[turn(long_question)]).await.unwrap();
+        assert_eq!(summary, "Chose tokio for the runtime");
+
+        let requests = stub.requests.lock().unwrap();
+        let request = &requests[0];
+        assert_eq!(request.model.as_deref(), Some("llama3.2"));
+        assert_eq!(request.metadata["preferred_provider"], "ollama");
+        assert_eq!(request.metadata["no_fallback"], true);
+        assert!(request.prompt.contains("Tools used: read_file\n"));
+        assert!(request.prompt.contains("Assistant: Use tokio\n"));
+        // Overlong messages are cut with a marker
+        assert!(request.prompt.contains(&format!("User: {} [...]\n", "x".repeat(MAX_MESSAGE_CHARS))));
+    }
+
+    #[tokio::test]
+    async fn test_empty_model_summary_falls_back_to_keywords() {
+        let stub = backend(" \n");
+        let summarizer = ModelSummarizer::new(stub.clone(), None, None);
+        assert!(summarizer.summarize(&[turn("Which runtime?".to_string())]).await.is_err());
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut manager = ContextMemoryManager::new(MemoryConfig {
+            session_storage_path: temp_dir.path().to_path_buf(),
+            max_context_turns: 4,
+            max_context_tokens: 10,
+            ..Default::default()
+        });
+        manager.set_summarizer(Arc::new(summarizer));
+        let session_id = manager.create_session(None).await.unwrap();
+
+        for i in 0..3 {
+            let question = Message::new_user(format!("Which async runtime should the project use? ({})", i));
+            manager.add_conversation_turn(question, vec![]).await.unwrap();
+            if let Some((summarizer, turns)) = manager.pending_summary() {
+                let summary = summarizer.summarize(&turns).await.ok();
+                manager.apply_summary(&turns, summary).await.unwrap();
+            }
+        }
+
+        // The model was asked, without a preferred provider, and its empty reply ignored
+        let requests = stub.requests.lock().unwrap();
+        assert!(requests.len() >= 2);
+        assert!(requests.iter().all(|request| !request.metadata.contains_key("preferred_provider")));
+        let saved = std::fs::read_to_string(temp_dir.path().join(format!("{}.json", session_id))).unwrap();
+        let session: serde_json::Value = serde_json::from_str(&saved).unwrap();
+        assert_eq!(session["session_metadata"]["summarized_by"], "keywords");
+    }
+}
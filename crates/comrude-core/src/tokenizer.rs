@@ -0,0 +1,185 @@
+//! Counting tokens the way the current model does
+//!
+//! GPT models are counted exactly with their BPE tables. Anthropic does not
+//! publish Claude's tokenizer, so it is approximated from the closest public
+//! one. Local models vary too much to ship tables for; their counts start
+//! from the same approximation and are corrected with the prompt sizes
+//! Ollama reports back (`prompt_eval_count`).
+
+use crate::types::{GenerationRequest, ModelInfo};
+use std::sync::{Arc, Mutex};
+use tiktoken_rs::CoreBPE;
+
+/// How many tokens some text costs with a particular model
+pub trait Tokenizer: Send + Sync + std::fmt::Debug {
+    /// Short description shown in `/memory`
+    fn name(&self) -> &str;
+
+    fn count(&self, text: &str) -> usize;
+
+    /// Learn from a prompt the provider reported `actual_tokens` for;
+    /// tokenizers that are already exact ignore this
+    fn calibrate(&self, _text: &str, _actual_tokens: u32) {}
+}
+
+/// Exact counts from a tiktoken BPE table
+pub struct BpeTokenizer {
+    name: String,
+    bpe: &'static CoreBPE,
+}
+
+impl BpeTokenizer {
+    /// The table OpenAI uses for `model`, if it is one of theirs
+    pub fn for_model(model: &str) -> Option<Self> {
+        use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer as Encoding};
+
+        let (name, bpe) = match get_tokenizer(model)? {
+            Encoding::O200kBase => ("o200k_base", tiktoken_rs::o200k_base_singleton()),
+            Encoding::Cl100kBase => ("cl100k_base", tiktoken_rs::cl100k_base_singleton()),
+            Encoding::P50kBase => ("p50k_base", tiktoken_rs::p50k_base_singleton()),
+            Encoding::P50kEdit => ("p50k_edit", tiktoken_rs::p50k_edit_singleton()),
+            Encoding::R50kBase | Encoding::Gpt2 => ("r50k_base", tiktoken_rs::r50k_base_singleton()),
+        };
+        Some(Self { name: name.to_string(), bpe })
+    }
+
+    /// `cl100k_base`, the closest public table for most recent models
+    pub fn cl100k() -> Self {
+        Self { name: "cl100k_base".to_string(), bpe: tiktoken_rs::cl100k_base_singleton() }
+    }
+}
+
+impl std::fmt::Debug for BpeTokenizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BpeTokenizer").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+}
+
+/// Claude's tokenizer produces somewhat more tokens than `cl100k_base`,
+/// most of all for code and non-English text
+const CLAUDE_TOKEN_RATIO: f64 = 1.15;
+
+/// A `cl100k_base` count scaled by a ratio, which calibration can adjust
+#[derive(Debug)]
+pub struct ApproximateTokenizer {
+    name: String,
+    base: BpeTokenizer,
+    /// Base-table tokens and reported tokens seen so far
+    observed: Mutex<(u64, u64)>,
+    initial_ratio: f64,
+}
+
+impl ApproximateTokenizer {
+    pub fn new(name: impl Into<String>, ratio: f64) -> Self {
+        Self {
+            name: name.into(),
+            base: BpeTokenizer::cl100k(),
+            observed: Mutex::new((0, 0)),
+            initial_ratio: ratio,
+        }
+    }
+
+    /// For Claude models
+    pub fn claude() -> Self {
+        Self::new("claude (approximate)", CLAUDE_TOKEN_RATIO)
+    }
+
+    /// Reported tokens per base-table token
+    pub fn ratio(&self) -> f64 {
+        let (estimated, actual) = *self.observed.lock().unwrap();
+        if estimated == 0 {
+            self.initial_ratio
+        } else {
+            actual as f64 / estimated as f64
+        }
+    }
+}
+
+impl Tokenizer for ApproximateTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count(&self, text: &str) -> usize {
+        (self.base.count(text) as f64 * self.ratio()).ceil() as usize
+    }
+
+    fn calibrate(&self, text: &str, actual_tokens: u32) {
+        let estimated = self.base.count(text) as u64;
+        if estimated == 0 || actual_tokens == 0 {
+            return;
+        }
+        let mut observed = self.observed.lock().unwrap();
+        observed.0 += estimated;
+        observed.1 += u64::from(actual_tokens);
+    }
+}
+
+/// The tokenizer for a model, chosen by its id
+pub fn tokenizer_for(model: &ModelInfo) -> Arc<dyn Tokenizer> {
+    tokenizer_for_model(&model.id)
+}
+
+/// Like `tokenizer_for`, for models whose `ModelInfo` is not at hand
+pub fn tokenizer_for_model(model: &str) -> Arc<dyn Tokenizer> {
+    if let Some(bpe) = BpeTokenizer::for_model(model) {
+        return Arc::new(bpe);
+    }
+    if model.starts_with("claude") {
+        return Arc::new(ApproximateTokenizer::claude());
+    }
+    // Local and unknown models, corrected by calibration as answers arrive
+    Arc::new(ApproximateTokenizer::new(format!("{} (calibrated)", model), 1.0))
+}
+
+/// The parts of a request that count towards its prompt tokens
+pub fn request_text(request: &GenerationRequest) -> String {
+    let mut text = request.system_prompt.clone().unwrap_or_default();
+    for item in &request.context {
+        text.push('\n');
+        text.push_str(&item.content);
+    }
+    text.push('\n');
+    text.push_str(&request.prompt);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selects_tokenizer_by_model() {
+        assert_eq!(tokenizer_for_model("gpt-4o-mini").name(), "o200k_base");
+        assert_eq!(tokenizer_for_model("gpt-4").name(), "cl100k_base");
+        assert_eq!(tokenizer_for_model("claude-3-5-sonnet-20241022").name(), "claude (approximate)");
+        assert_eq!(tokenizer_for_model("llama3.2").name(), "llama3.2 (calibrated)");
+
+        let gpt = tokenizer_for_model("gpt-4");
+        assert_eq!(gpt.count("hello world"), 2);
+        // Accented Portuguese costs more than four characters per token suggests
+        let portuguese = "Não consigo compilar o módulo de autenticação";
+        assert!(gpt.count(portuguese) > portuguese.len() / 4);
+    }
+
+    #[test]
+    fn test_calibration_follows_reported_counts() {
+        let tokenizer = ApproximateTokenizer::new("local", 1.0);
+        let text = "fn main() { println!(\"hello\"); }";
+        let base = tokenizer.count(text);
+
+        tokenizer.calibrate(text, (base * 2) as u32);
+        assert_eq!(tokenizer.count(text), base * 2);
+        assert!((tokenizer.ratio() - 2.0).abs() < f64::EPSILON);
+    }
+}
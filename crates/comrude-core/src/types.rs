@@ -13,6 +13,9 @@ pub struct GenerationRequest {
     pub stream: bool,
     pub tools: Vec<ToolDefinition>,
     pub context: Vec<ContextItem>,
+    /// Earlier tool calls and their results, replayed after the prompt
+    #[serde(default)]
+    pub tool_exchanges: Vec<ToolExchange>,
     pub metadata: HashMap<String, serde_json::Value>,
 }
 
@@ -27,6 +30,7 @@ impl Default for GenerationRequest {
             stream: false,
             tools: Vec::new(),
             context: Vec::new(),
+            tool_exchanges: Vec::new(),
             metadata: HashMap::new(),
         }
     }
@@ -43,23 +47,13 @@ pub struct GenerationResponse {
     pub metadata: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TokenUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
 }
 
-impl Default for TokenUsage {
-    fn default() -> Self {
-        Self {
-            prompt_tokens: 0,
-            completion_tokens: 0,
-            total_tokens: 0,
-        }
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FinishReason {
     Stop,
@@ -92,6 +86,44 @@ pub struct ToolCall {
     pub arguments: serde_json::Value,
 }
 
+/// Output of running a tool call, sent back to the model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolResult {
+    /// Id of the `ToolCall` this answers
+    pub call_id: String,
+    pub content: String,
+    pub is_error: bool,
+}
+
+impl ToolResult {
+    /// A successful result; the call id is filled in by whoever dispatched the call
+    pub fn success(content: impl Into<String>) -> Self {
+        Self {
+            call_id: String::new(),
+            content: content.into(),
+            is_error: false,
+        }
+    }
+
+    /// A failure the model should see and may recover from
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            call_id: String::new(),
+            content: message.into(),
+            is_error: true,
+        }
+    }
+}
+
+/// One assistant turn that requested tools, together with the results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolExchange {
+    /// Text the assistant produced alongside the calls
+    pub content: String,
+    pub calls: Vec<ToolCall>,
+    pub results: Vec<ToolResult>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContextItem {
     pub item_type: ContextType,
@@ -99,6 +131,35 @@ pub struct ContextItem {
     pub metadata: HashMap<String, serde_json::Value>,
 }
 
+impl ContextItem {
+    /// Identity that survives edits to the content where there is something
+    /// to name the item by: the path of a file, the command that produced
+    /// output, the file a diff covers. Other items are named by a SHA-256
+    /// hash of their content.
+    pub fn id(&self) -> String {
+        match &self.item_type {
+            ContextType::File { path } => format!("file:{}", path),
+            ContextType::Command { command } => format!("command:{}", command),
+            ContextType::GitDiff => match (self.metadata.get("source"), self.metadata.get("path")) {
+                (Some(source), Some(path)) => format!(
+                    "git:{}:{}",
+                    source.as_str().unwrap_or_default(),
+                    path.as_str().unwrap_or_default()
+                ),
+                _ => format!("sha256:{}", content_hash(&self.content)),
+            },
+            ContextType::Code { .. } | ContextType::Text => format!("sha256:{}", content_hash(&self.content)),
+        }
+    }
+}
+
+/// Hex SHA-256 of some text; stable across platforms and releases, so it is
+/// safe to persist
+pub fn content_hash(content: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(content.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ContextType {
     File { path: String },
@@ -124,6 +185,28 @@ pub struct CostPer1k {
     pub output: f64,
 }
 
+impl CostPer1k {
+    /// Price of a request in dollars
+    pub fn cost_of(&self, usage: &TokenUsage) -> f64 {
+        (f64::from(usage.prompt_tokens) * self.input + f64::from(usage.completion_tokens) * self.output) / 1000.0
+    }
+
+    /// Pricing for `model` from a table keyed by model id
+    ///
+    /// APIs report dated ids such as `gpt-4o-mini-2024-07-18`, so the longest
+    /// key the model id starts with is used.
+    pub fn lookup<'a, I>(prices: I, model: &str) -> Option<&'a CostPer1k>
+    where
+        I: IntoIterator<Item = (&'a String, &'a CostPer1k)>,
+    {
+        prices
+            .into_iter()
+            .filter(|(id, _)| model.starts_with(id.as_str()))
+            .max_by_key(|(id, _)| id.len())
+            .map(|(_, price)| price)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProviderCapabilities {
     pub max_context_length: u32,
@@ -179,6 +262,8 @@ pub enum MessageStatus {
     Pending,
     Processing,
     Complete,
+    /// Cut short by the user before the provider finished
+    Interrupted,
     Error,
 }
 
@@ -199,9 +284,12 @@ pub struct ParsedCommand {
     pub flags: HashMap<String, String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageRecord {
     pub timestamp: DateTime<Utc>,
+    /// The comrude run that made the request
+    #[serde(default)]
+    pub session_id: Uuid,
     pub provider: String,
     pub model: String,
     pub tokens_used: TokenUsage,
@@ -209,11 +297,14 @@ pub struct UsageRecord {
     pub request_type: RequestType,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RequestType {
     Generation,
     Embedding,
     FineTuning,
+    /// An attempt that failed transiently and was retried or handed to a fallback
+    Retry,
 }
 
 impl Message {
@@ -237,6 +328,13 @@ impl Message {
         }
     }
 
+    pub fn new_interrupted_assistant(content: String, provider: String, model: String) -> Self {
+        Self {
+            status: MessageStatus::Interrupted,
+            ..Self::new_assistant(content, provider, model)
+        }
+    }
+
     pub fn new_system(content: String) -> Self {
         Self {
             id: Uuid::new_v4(),
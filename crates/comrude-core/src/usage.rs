@@ -0,0 +1,199 @@
+//! Append-only ledger of provider usage
+//!
+//! Every request made through the provider manager is written as one JSON
+//! line, so the file can be reconciled against provider invoices and is
+//! never rewritten in place.
+
+use crate::error::{ComrudeError, ComrudeResult};
+use crate::types::{RequestType, UsageRecord};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageConfig {
+    pub enabled: bool,
+    pub ledger_path: String,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ledger_path: ".comrude/usage.jsonl".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UsageLedger {
+    path: PathBuf,
+}
+
+impl UsageLedger {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &UsageRecord) -> ComrudeResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(ComrudeError::IoError)?;
+        }
+
+        let mut line = serde_json::to_string(record)
+            .map_err(|e| ComrudeError::SerializationError(e.to_string()))?;
+        line.push('\n');
+
+        // A single write keeps concurrent appenders from interleaving lines
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(ComrudeError::IoError)?;
+        file.write_all(line.as_bytes()).map_err(ComrudeError::IoError)
+    }
+
+    /// Every record in the ledger; a missing ledger is empty
+    pub fn read_all(&self) -> ComrudeResult<Vec<UsageRecord>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(ComrudeError::IoError(e)),
+        };
+
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|e| ComrudeError::Parse(format!(
+                    "{}:{}: {}", self.path.display(), index + 1, e
+                )))
+            })
+            .collect()
+    }
+}
+
+/// How usage is grouped in a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGrouping {
+    Provider,
+    Model,
+    Day,
+    Session,
+}
+
+impl UsageGrouping {
+    fn key(&self, record: &UsageRecord) -> String {
+        match self {
+            UsageGrouping::Provider => record.provider.clone(),
+            UsageGrouping::Model => format!("{}/{}", record.provider, record.model),
+            UsageGrouping::Day => record.timestamp.with_timezone(&Local).format("%Y-%m-%d").to_string(),
+            UsageGrouping::Session => record.session_id.to_string(),
+        }
+    }
+}
+
+impl FromStr for UsageGrouping {
+    type Err = ComrudeError;
+
+    fn from_str(s: &str) -> ComrudeResult<Self> {
+        match s {
+            "provider" => Ok(UsageGrouping::Provider),
+            "model" => Ok(UsageGrouping::Model),
+            "day" => Ok(UsageGrouping::Day),
+            "session" => Ok(UsageGrouping::Session),
+            other => Err(ComrudeError::Parse(format!("unknown usage grouping '{}'", other))),
+        }
+    }
+}
+
+/// One line of a usage report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageRow {
+    pub key: String,
+    pub requests: usize,
+    pub retries: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageRow {
+    fn add(&mut self, record: &UsageRecord) {
+        if record.request_type == RequestType::Retry {
+            self.retries += 1;
+        } else {
+            self.requests += 1;
+        }
+        self.prompt_tokens += u64::from(record.tokens_used.prompt_tokens);
+        self.completion_tokens += u64::from(record.tokens_used.completion_tokens);
+        self.cost += record.cost;
+    }
+}
+
+/// Totals per group, sorted by group key
+pub fn summarize_usage(records: &[UsageRecord], grouping: UsageGrouping) -> Vec<UsageRow> {
+    let mut rows: BTreeMap<String, UsageRow> = BTreeMap::new();
+
+    for record in records {
+        let key = grouping.key(record);
+        rows.entry(key.clone())
+            .or_insert_with(|| UsageRow { key, ..UsageRow::default() })
+            .add(record);
+    }
+
+    rows.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TokenUsage;
+    use chrono::Utc;
+    use tempfile::TempDir;
+    use uuid::Uuid;
+
+    fn record(provider: &str, request_type: RequestType, cost: f64) -> UsageRecord {
+        UsageRecord {
+            timestamp: Utc::now(),
+            session_id: Uuid::nil(),
+            provider: provider.to_string(),
+            model: "model".to_string(),
+            tokens_used: TokenUsage { prompt_tokens: 100, completion_tokens: 20, total_tokens: 120 },
+            cost,
+            request_type,
+        }
+    }
+
+    #[test]
+    fn test_ledger_round_trip_and_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let ledger = UsageLedger::new(temp_dir.path().join("nested/usage.jsonl"));
+        assert!(ledger.read_all().unwrap().is_empty());
+
+        ledger.append(&record("openai", RequestType::Generation, 0.5)).unwrap();
+        ledger.append(&record("openai", RequestType::Retry, 0.0)).unwrap();
+        ledger.append(&record("anthropic", RequestType::Generation, 0.25)).unwrap();
+
+        let records = ledger.read_all().unwrap();
+        assert_eq!(records.len(), 3);
+
+        let rows = summarize_usage(&records, UsageGrouping::Provider);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].key, "anthropic");
+        assert_eq!(rows[1].key, "openai");
+        assert_eq!(rows[1].requests, 1);
+        assert_eq!(rows[1].retries, 1);
+        assert_eq!(rows[1].prompt_tokens, 200);
+        assert_eq!(rows[1].cost, 0.5);
+    }
+}
@@ -4,18 +4,25 @@ use comrude_core::{
     ModelInfo, HealthStatus, Result, ProviderError, AnthropicConfig,
     Message, MessageSender, MessageContent, TokenUsage, FinishReason, CostPer1k
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
+use crate::retry::RateLimitTracker;
+use crate::streaming::{sse_events, SseEvent};
 use crate::traits::LLMProvider;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use futures::Stream;
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::time::Duration;
 
 #[derive(Debug)]
 pub struct AnthropicProvider {
     client: Client,
+    // Streams can legitimately run longer than the request timeout, so they
+    // use a client that only bounds connection setup.
+    stream_client: Client,
+    rate_limits: RateLimitTracker,
     config: AnthropicConfig,
     api_key: String,
 }
@@ -31,10 +38,36 @@ struct AnthropicRequest {
     tools: Option<Vec<AnthropicTool>>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 struct AnthropicMessage {
     role: String,
-    content: String,
+    content: AnthropicMessageContent,
+}
+
+/// Plain text, or content blocks when the message carries tool use
+#[derive(Serialize)]
+#[serde(untagged)]
+enum AnthropicMessageContent {
+    Text(String),
+    Blocks(Vec<AnthropicRequestBlock>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicRequestBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        is_error: bool,
+    },
 }
 
 #[derive(Serialize)]
@@ -46,28 +79,193 @@ struct AnthropicTool {
 
 #[derive(Deserialize)]
 struct AnthropicResponse {
-    id: String,
-    #[serde(rename = "type")]
-    response_type: String,
-    role: String,
-    content: Vec<AnthropicContent>,
+    content: Vec<AnthropicContentBlock>,
     model: String,
     stop_reason: Option<String>,
-    stop_sequence: Option<String>,
     usage: AnthropicUsage,
 }
 
 #[derive(Deserialize)]
-struct AnthropicContent {
-    #[serde(rename = "type")]
-    content_type: String,
-    text: Option<String>,
+struct AnthropicUsage {
+    input_tokens: u32,
+    output_tokens: u32,
 }
 
+/// Events of the Messages API `text/event-stream` response
 #[derive(Deserialize)]
-struct AnthropicUsage {
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicStreamEvent {
+    MessageStart {
+        message: AnthropicStreamMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: AnthropicContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: AnthropicContentDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        usage: Option<AnthropicDeltaUsage>,
+    },
+    MessageStop,
+    Error {
+        error: AnthropicErrorBody,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct AnthropicStreamMessage {
+    usage: AnthropicUsage,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        /// Complete in responses; empty when streaming, where it arrives as deltas
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicContentDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct AnthropicDeltaUsage {
+    output_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct AnthropicErrorBody {
+    #[serde(rename = "type")]
+    error_type: String,
+    message: String,
+}
+
+/// A `tool_use` block whose JSON input is still arriving
+struct PendingToolUse {
+    id: String,
+    name: String,
+    input_json: String,
+}
+
+/// Accumulates state across stream events and turns them into chunks
+#[derive(Default)]
+struct AnthropicStreamState {
     input_tokens: u32,
     output_tokens: u32,
+    tool_uses: HashMap<usize, PendingToolUse>,
+}
+
+impl AnthropicStreamState {
+    fn handle_event(&mut self, event: SseEvent) -> Vec<Result<StreamChunk>> {
+        let event: AnthropicStreamEvent = match serde_json::from_str(&event.data) {
+            Ok(event) => event,
+            Err(e) => {
+                return vec![Err(comrude_core::ComrudeError::Provider(
+                    ProviderError::InvalidResponse(format!("anthropic: malformed stream event: {}", e))
+                ))];
+            }
+        };
+
+        match event {
+            AnthropicStreamEvent::MessageStart { message } => {
+                self.input_tokens = message.usage.input_tokens;
+                self.output_tokens = message.usage.output_tokens;
+                Vec::new()
+            }
+            AnthropicStreamEvent::ContentBlockStart { index, content_block } => match content_block {
+                AnthropicContentBlock::Text { text } if !text.is_empty() => {
+                    vec![Ok(StreamChunk::Content(text))]
+                }
+                AnthropicContentBlock::ToolUse { id, name, .. } => {
+                    self.tool_uses.insert(index, PendingToolUse {
+                        id,
+                        name,
+                        input_json: String::new(),
+                    });
+                    Vec::new()
+                }
+                _ => Vec::new(),
+            },
+            AnthropicStreamEvent::ContentBlockDelta { index, delta } => match delta {
+                AnthropicContentDelta::TextDelta { text } => vec![Ok(StreamChunk::Content(text))],
+                AnthropicContentDelta::InputJsonDelta { partial_json } => {
+                    if let Some(tool_use) = self.tool_uses.get_mut(&index) {
+                        tool_use.input_json.push_str(&partial_json);
+                    }
+                    Vec::new()
+                }
+                AnthropicContentDelta::Other => Vec::new(),
+            },
+            AnthropicStreamEvent::ContentBlockStop { index } => {
+                let Some(tool_use) = self.tool_uses.remove(&index) else {
+                    return Vec::new();
+                };
+
+                // A tool without parameters streams no input at all
+                let arguments = if tool_use.input_json.trim().is_empty() {
+                    Ok(serde_json::Value::Object(serde_json::Map::new()))
+                } else {
+                    serde_json::from_str(&tool_use.input_json)
+                };
+
+                match arguments {
+                    Ok(arguments) => vec![Ok(StreamChunk::ToolCall(comrude_core::ToolCall {
+                        id: tool_use.id,
+                        name: tool_use.name,
+                        arguments,
+                    }))],
+                    Err(e) => vec![Err(comrude_core::ComrudeError::Provider(
+                        ProviderError::InvalidResponse(format!(
+                            "anthropic: invalid input for tool '{}': {}", tool_use.name, e
+                        ))
+                    ))],
+                }
+            }
+            AnthropicStreamEvent::MessageDelta { usage } => match usage {
+                Some(usage) => {
+                    self.output_tokens = usage.output_tokens;
+                    vec![Ok(StreamChunk::TokenUsage(TokenUsage {
+                        prompt_tokens: self.input_tokens,
+                        completion_tokens: self.output_tokens,
+                        total_tokens: self.input_tokens + self.output_tokens,
+                    }))]
+                }
+                None => Vec::new(),
+            },
+            AnthropicStreamEvent::MessageStop => vec![Ok(StreamChunk::Done)],
+            AnthropicStreamEvent::Error { error } => {
+                vec![Ok(StreamChunk::Error(format!("{}: {}", error.error_type, error.message)))]
+            }
+            AnthropicStreamEvent::Other => Vec::new(),
+        }
+    }
 }
 
 impl AnthropicProvider {
@@ -80,10 +278,17 @@ impl AnthropicProvider {
         let client = Client::builder()
             .timeout(Duration::from_secs(config.timeout_seconds))
             .build()
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
+
+        let stream_client = Client::builder()
+            .connect_timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         Ok(Self {
             client,
+            stream_client,
+            rate_limits: RateLimitTracker::default(),
             config,
             api_key,
         })
@@ -114,18 +319,42 @@ impl AnthropicProvider {
 
             Some(AnthropicMessage {
                 role: role.to_string(),
-                content,
+                content: AnthropicMessageContent::Text(content),
             })
         }).collect()
     }
 
-    fn extract_system_prompt(&self, messages: &[Message]) -> Option<String> {
-        messages.iter()
-            .find(|msg| matches!(msg.sender, MessageSender::System))
-            .and_then(|msg| match &msg.content {
-                MessageContent::Text(text) => Some(text.clone()),
-                _ => None,
-            })
+    /// Replay tool use as `tool_use` blocks from the assistant answered by
+    /// `tool_result` blocks from the user
+    fn convert_tool_exchanges(&self, exchanges: &[comrude_core::ToolExchange]) -> Vec<AnthropicMessage> {
+        exchanges.iter().flat_map(|exchange| {
+            let mut assistant_blocks = Vec::new();
+            if !exchange.content.is_empty() {
+                assistant_blocks.push(AnthropicRequestBlock::Text { text: exchange.content.clone() });
+            }
+            assistant_blocks.extend(exchange.calls.iter().map(|call| AnthropicRequestBlock::ToolUse {
+                id: call.id.clone(),
+                name: call.name.clone(),
+                input: call.arguments.clone(),
+            }));
+
+            let result_blocks = exchange.results.iter().map(|result| AnthropicRequestBlock::ToolResult {
+                tool_use_id: result.call_id.clone(),
+                content: result.content.clone(),
+                is_error: result.is_error,
+            }).collect();
+
+            [
+                AnthropicMessage {
+                    role: "assistant".to_string(),
+                    content: AnthropicMessageContent::Blocks(assistant_blocks),
+                },
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: AnthropicMessageContent::Blocks(result_blocks),
+                },
+            ]
+        }).collect()
     }
 
     fn convert_tools(&self, tools: &[comrude_core::ToolDefinition]) -> Vec<AnthropicTool> {
@@ -137,6 +366,81 @@ impl AnthropicProvider {
             }
         }).collect()
     }
+
+    fn build_request(&self, request: GenerationRequest, stream: bool) -> AnthropicRequest {
+        let model = request.model.unwrap_or_else(|| self.config.default_model.clone());
+        
+        // Build context messages
+        let mut all_messages = Vec::new();
+        
+        // Add context messages from request
+        for context_item in &request.context {
+            all_messages.push(Message {
+                id: Uuid::new_v4(),
+                timestamp: Utc::now(),
+                sender: MessageSender::User,
+                content: MessageContent::Text(format!(
+                    "Context: {}", 
+                    context_item.content
+                )),
+                status: comrude_core::MessageStatus::Complete,
+            });
+        }
+
+        // Add main prompt
+        all_messages.push(Message {
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            sender: MessageSender::User,
+            content: MessageContent::Text(request.prompt.clone()),
+            status: comrude_core::MessageStatus::Complete,
+        });
+
+        let mut messages = self.convert_messages(&all_messages);
+        messages.extend(self.convert_tool_exchanges(&request.tool_exchanges));
+        let system_prompt = request.system_prompt;
+
+        AnthropicRequest {
+            model,
+            max_tokens: request.max_tokens.unwrap_or(self.config.max_tokens),
+            messages,
+            system: system_prompt,
+            temperature: request.temperature,
+            stream: Some(stream),
+            tools: if request.tools.is_empty() {
+                None
+            } else {
+                Some(self.convert_tools(&request.tools))
+            },
+        }
+    }
+
+    /// Send a Messages API request and turn HTTP failures into provider errors
+    async fn send_request(&self, client: &Client, body: &AnthropicRequest) -> Result<reqwest::Response> {
+        let url = format!("{}/v1/messages", self.config.base_url);
+
+        let response = client
+            .post(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("Content-Type", "application/json")
+            .json(body)
+            .send()
+            .await
+            .map_err(comrude_core::ComrudeError::Network)?;
+
+        self.rate_limits.record(response.status(), response.headers());
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(comrude_core::ComrudeError::Provider(
+                ProviderError::from_http_status("anthropic", status, error_text)
+            ));
+        }
+
+        Ok(response)
+    }
 }
 
 #[async_trait]
@@ -218,6 +522,11 @@ impl LLMProvider for AnthropicProvider {
     }
 
     async fn health_check(&self) -> Result<HealthStatus> {
+        // Don't spend a request while a known limit is still in force
+        if let Some(reset_time) = self.rate_limits.reset_at() {
+            return Ok(HealthStatus::RateLimited { reset_time });
+        }
+
         // Anthropic doesn't have a simple health check endpoint
         // We'll try a minimal request to verify the API key works
         let test_request = AnthropicRequest {
@@ -225,7 +534,7 @@ impl LLMProvider for AnthropicProvider {
             max_tokens: 1,
             messages: vec![AnthropicMessage {
                 role: "user".to_string(),
-                content: "Hi".to_string(),
+                content: AnthropicMessageContent::Text("Hi".to_string()),
             }],
             system: None,
             temperature: Some(0.0),
@@ -244,6 +553,13 @@ impl LLMProvider for AnthropicProvider {
             .send()
             .await;
 
+        if let Ok(resp) = &response {
+            self.rate_limits.record(resp.status(), resp.headers());
+            if let Some(reset_time) = self.rate_limits.reset_at() {
+                return Ok(HealthStatus::RateLimited { reset_time });
+            }
+        }
+
         match response {
             Ok(resp) if resp.status().is_success() => {
                 Ok(HealthStatus::Healthy)
@@ -265,88 +581,31 @@ impl LLMProvider for AnthropicProvider {
                 ProviderError::ApiError {
                     provider: "anthropic".to_string(),
                     message: "Anthropic API is not healthy".to_string(),
+                    status: None,
                 }
             ))
         }
     }
 
     async fn generate(&self, request: GenerationRequest) -> Result<GenerationResponse> {
-        let url = format!("{}/v1/messages", self.config.base_url);
-        
-        let model = request.model.unwrap_or_else(|| self.config.default_model.clone());
-        
-        // Build context messages
-        let mut all_messages = Vec::new();
-        
-        // Add context messages from request
-        for context_item in &request.context {
-            all_messages.push(Message {
-                id: Uuid::new_v4(),
-                timestamp: Utc::now(),
-                sender: MessageSender::User,
-                content: MessageContent::Text(format!(
-                    "Context: {}", 
-                    context_item.content
-                )),
-                status: comrude_core::MessageStatus::Complete,
-            });
-        }
-
-        // Add main prompt
-        all_messages.push(Message {
-            id: Uuid::new_v4(),
-            timestamp: Utc::now(),
-            sender: MessageSender::User,
-            content: MessageContent::Text(request.prompt.clone()),
-            status: comrude_core::MessageStatus::Complete,
-        });
-
-        let messages = self.convert_messages(&all_messages);
-        let system_prompt = request.system_prompt;
-
-        let anthropic_request = AnthropicRequest {
-            model,
-            max_tokens: request.max_tokens.unwrap_or(self.config.max_tokens),
-            messages,
-            system: system_prompt,
-            temperature: request.temperature,
-            stream: Some(false),
-            tools: if request.tools.is_empty() {
-                None
-            } else {
-                Some(self.convert_tools(&request.tools))
-            },
-        };
+        let anthropic_request = self.build_request(request, false);
+        let response = self.send_request(&self.client, &anthropic_request).await?;
 
-        let response = self.client
-            .post(&url)
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .json(&anthropic_request)
-            .send()
-            .await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(comrude_core::ComrudeError::Provider(
-                ProviderError::ApiError {
-                    provider: "anthropic".to_string(),
-                    message: format!("HTTP {}: {}", status, error_text),
+        let anthropic_response: AnthropicResponse = response.json().await
+            .map_err(comrude_core::ComrudeError::Network)?;
+
+        let mut texts = Vec::new();
+        let mut tool_calls = Vec::new();
+        for block in anthropic_response.content {
+            match block {
+                AnthropicContentBlock::Text { text } => texts.push(text),
+                AnthropicContentBlock::ToolUse { id, name, input } => {
+                    tool_calls.push(comrude_core::ToolCall { id, name, arguments: input });
                 }
-            ));
+                AnthropicContentBlock::Other => {}
+            }
         }
-
-        let anthropic_response: AnthropicResponse = response.json().await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
-
-        let content = anthropic_response.content
-            .into_iter()
-            .filter_map(|c| c.text)
-            .collect::<Vec<_>>()
-            .join("\n");
+        let content = texts.join("\n");
 
         let tokens_used = TokenUsage {
             prompt_tokens: anthropic_response.usage.input_tokens,
@@ -362,25 +621,42 @@ impl LLMProvider for AnthropicProvider {
             None => FinishReason::Stop,
         };
 
+        let cost = self.estimate_cost(&anthropic_response.model, &tokens_used);
+
         Ok(GenerationResponse {
             content,
             model_used: anthropic_response.model,
             tokens_used,
-            cost: 0.0, // TODO: Calculate actual cost
+            cost,
             finish_reason,
-            tool_calls: Vec::new(), // TODO: Extract tool calls
+            tool_calls,
             metadata: std::collections::HashMap::new(),
         })
     }
 
-    async fn generate_stream(&self, _request: GenerationRequest) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
-        // For now, return an error as streaming implementation is complex
-        Err(comrude_core::ComrudeError::Provider(
-            ProviderError::ApiError {
-                provider: "anthropic".to_string(),
-                message: "Streaming not implemented yet".to_string(),
-            }
-        ))
+    async fn generate_stream(&self, request: GenerationRequest) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+        let anthropic_request = self.build_request(request, true);
+
+        // Only waiting for the response headers is bounded by the timeout
+        let response = tokio::time::timeout(
+            Duration::from_secs(self.config.timeout_seconds),
+            self.send_request(&self.stream_client, &anthropic_request),
+        )
+        .await
+        .map_err(|_| comrude_core::ComrudeError::Provider(
+            ProviderError::Timeout("anthropic".to_string())
+        ))??;
+
+        let mut state = AnthropicStreamState::default();
+        let chunks = sse_events(response.bytes_stream()).flat_map(move |event| {
+            let chunks = match event {
+                Ok(event) => state.handle_event(event),
+                Err(e) => vec![Err(e)],
+            };
+            futures::stream::iter(chunks)
+        });
+
+        Ok(Box::pin(chunks))
     }
 
     async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
@@ -389,6 +665,7 @@ impl LLMProvider for AnthropicProvider {
             ProviderError::ApiError {
                 provider: "anthropic".to_string(),
                 message: "Embeddings not supported by Anthropic".to_string(),
+                status: None,
             }
         ))
     }
@@ -398,4 +675,172 @@ impl LLMProvider for AnthropicProvider {
         // Return the static list
         Ok(self.supported_models())
     }
-}
\ No newline at end of file
+
+    fn rate_limit_reset(&self) -> Option<DateTime<Utc>> {
+        self.rate_limits.reset_at()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sse(events: &[(&str, serde_json::Value)]) -> String {
+        events.iter()
+            .map(|(name, data)| format!("event: {}\ndata: {}\n\n", name, data))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_parses_messages_events() {
+        let mut server = mockito::Server::new_async().await;
+        let body = sse(&[
+            ("message_start", serde_json::json!({
+                "type": "message_start",
+                "message": {"id": "msg_1", "model": "claude-3-5-sonnet-20241022", "usage": {"input_tokens": 12, "output_tokens": 1}}
+            })),
+            ("content_block_start", serde_json::json!({
+                "type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}
+            })),
+            ("ping", serde_json::json!({"type": "ping"})),
+            ("content_block_delta", serde_json::json!({
+                "type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello"}
+            })),
+            ("content_block_delta", serde_json::json!({
+                "type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": " world"}
+            })),
+            ("content_block_stop", serde_json::json!({"type": "content_block_stop", "index": 0})),
+            ("content_block_start", serde_json::json!({
+                "type": "content_block_start", "index": 1,
+                "content_block": {"type": "tool_use", "id": "toolu_1", "name": "read_file", "input": {}}
+            })),
+            ("content_block_delta", serde_json::json!({
+                "type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"path\": \"src/"}
+            })),
+            ("content_block_delta", serde_json::json!({
+                "type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "main.rs\"}"}
+            })),
+            ("content_block_stop", serde_json::json!({"type": "content_block_stop", "index": 1})),
+            ("message_delta", serde_json::json!({
+                "type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 30}
+            })),
+            ("message_stop", serde_json::json!({"type": "message_stop"})),
+        ]);
+
+        let mock = server.mock("POST", "/v1/messages")
+            .match_header("x-api-key", "test-key")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"stream": true})))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        std::env::set_var("COMRUDE_TEST_ANTHROPIC_STREAM_KEY", "test-key");
+        let provider = AnthropicProvider::new(AnthropicConfig {
+            api_key_env: "COMRUDE_TEST_ANTHROPIC_STREAM_KEY".to_string(),
+            base_url: server.url(),
+            ..AnthropicConfig::default()
+        }).unwrap();
+
+        let request = GenerationRequest {
+            prompt: "Say hello".to_string(),
+            stream: true,
+            ..GenerationRequest::default()
+        };
+        let chunks: Vec<StreamChunk> = provider.generate_stream(request).await.unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        mock.assert_async().await;
+        assert!(matches!(&chunks[0], StreamChunk::Content(text) if text == "Hello"));
+        assert!(matches!(&chunks[1], StreamChunk::Content(text) if text == " world"));
+        match &chunks[2] {
+            StreamChunk::ToolCall(call) => {
+                assert_eq!(call.id, "toolu_1");
+                assert_eq!(call.name, "read_file");
+                assert_eq!(call.arguments, serde_json::json!({"path": "src/main.rs"}));
+            }
+            other => panic!("expected tool call, got {:?}", other),
+        }
+        match &chunks[3] {
+            StreamChunk::TokenUsage(usage) => {
+                assert_eq!(usage.prompt_tokens, 12);
+                assert_eq!(usage.completion_tokens, 30);
+                assert_eq!(usage.total_tokens, 42);
+            }
+            other => panic!("expected token usage, got {:?}", other),
+        }
+        assert!(matches!(chunks[4], StreamChunk::Done));
+        assert_eq!(chunks.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_generate_round_trips_tool_use() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/v1/messages")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "messages": [
+                    {"role": "user", "content": "What is in main.rs?"},
+                    {"role": "assistant", "content": [
+                        {"type": "text", "text": "Let me look."},
+                        {"type": "tool_use", "id": "toolu_1", "name": "read_file", "input": {"path": "src/main.rs"}}
+                    ]},
+                    {"role": "user", "content": [
+                        {"type": "tool_result", "tool_use_id": "toolu_1", "content": "no such file", "is_error": true}
+                    ]}
+                ]
+            })))
+            .with_status(200)
+            .with_body(serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "content": [
+                    {"type": "text", "text": "Checking the directory."},
+                    {"type": "tool_use", "id": "toolu_2", "name": "list_dir", "input": {"path": "."}}
+                ],
+                "model": "claude-3-5-sonnet-20241022",
+                "stop_reason": "tool_use",
+                "stop_sequence": null,
+                "usage": {"input_tokens": 50, "output_tokens": 20}
+            }).to_string())
+            .create_async()
+            .await;
+
+        std::env::set_var("COMRUDE_TEST_ANTHROPIC_TOOLS_KEY", "test-key");
+        let provider = AnthropicProvider::new(AnthropicConfig {
+            api_key_env: "COMRUDE_TEST_ANTHROPIC_TOOLS_KEY".to_string(),
+            base_url: server.url(),
+            ..AnthropicConfig::default()
+        }).unwrap();
+
+        let request = GenerationRequest {
+            prompt: "What is in main.rs?".to_string(),
+            tool_exchanges: vec![comrude_core::ToolExchange {
+                content: "Let me look.".to_string(),
+                calls: vec![comrude_core::ToolCall {
+                    id: "toolu_1".to_string(),
+                    name: "read_file".to_string(),
+                    arguments: serde_json::json!({"path": "src/main.rs"}),
+                }],
+                results: vec![comrude_core::ToolResult {
+                    call_id: "toolu_1".to_string(),
+                    content: "no such file".to_string(),
+                    is_error: true,
+                }],
+            }],
+            ..GenerationRequest::default()
+        };
+        let response = provider.generate(request).await.unwrap();
+
+        mock.assert_async().await;
+        assert!(matches!(response.finish_reason, FinishReason::ToolCalls));
+        assert_eq!(response.content, "Checking the directory.");
+        assert_eq!(response.tool_calls.len(), 1);
+        assert_eq!(response.tool_calls[0].id, "toolu_2");
+        assert_eq!(response.tool_calls[0].name, "list_dir");
+        assert_eq!(response.tool_calls[0].arguments, serde_json::json!({"path": "."}));
+    }
+}
@@ -0,0 +1,249 @@
+//! Running totals of what generations have cost
+
+use chrono::{DateTime, Datelike, Local, Utc};
+use comrude_core::{
+    BudgetLimits, ComrudeError, ProviderError, RequestType, Result, TokenUsage, UsageLedger,
+    UsageRecord,
+};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Totals over a set of usage records
+#[derive(Debug, Clone, Default)]
+pub struct CostSummary {
+    pub requests: usize,
+    pub tokens: TokenUsage,
+    pub cost: f64,
+    /// Cost per `provider/model`
+    pub by_model: BTreeMap<String, f64>,
+}
+
+impl CostSummary {
+    fn add(&mut self, record: &UsageRecord) {
+        if record.request_type != RequestType::Retry {
+            self.requests += 1;
+        }
+        self.tokens.prompt_tokens += record.tokens_used.prompt_tokens;
+        self.tokens.completion_tokens += record.tokens_used.completion_tokens;
+        self.tokens.total_tokens += record.tokens_used.total_tokens;
+        self.cost += record.cost;
+        *self.by_model
+            .entry(format!("{}/{}", record.provider, record.model))
+            .or_default() += record.cost;
+    }
+}
+
+/// Window a budget applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPeriod {
+    Session,
+    Day,
+    Month,
+}
+
+impl BudgetPeriod {
+    fn label(self) -> &'static str {
+        match self {
+            BudgetPeriod::Session => "session",
+            BudgetPeriod::Day => "daily",
+            BudgetPeriod::Month => "monthly",
+        }
+    }
+}
+
+/// Usage recorded by a `ProviderManager` since it was created
+///
+/// With a ledger attached every record is also appended to it, and this
+/// month's records from earlier runs are loaded so daily and monthly totals
+/// span them too.
+#[derive(Debug)]
+pub struct CostTracker {
+    session_id: Uuid,
+    started_at: DateTime<Utc>,
+    ledger: Option<UsageLedger>,
+    history: Vec<UsageRecord>,
+    records: Mutex<Vec<UsageRecord>>,
+}
+
+impl Default for CostTracker {
+    fn default() -> Self {
+        Self {
+            session_id: Uuid::new_v4(),
+            started_at: Utc::now(),
+            ledger: None,
+            history: Vec::new(),
+            records: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl CostTracker {
+    pub async fn with_ledger(ledger: UsageLedger) -> Self {
+        let reader = ledger.clone();
+        let read = tokio::task::spawn_blocking(move || reader.read_all()).await
+            .map_err(|e| ComrudeError::Unknown(e.to_string()))
+            .and_then(|records| records);
+        let history = match read {
+            Ok(records) => records
+                .into_iter()
+                .filter(|record| in_period(record, BudgetPeriod::Month))
+                .collect(),
+            Err(e) => {
+                tracing::warn!("failed to read usage ledger {}: {}", ledger.path().display(), e);
+                Vec::new()
+            }
+        };
+
+        Self {
+            ledger: Some(ledger),
+            history,
+            ..Self::default()
+        }
+    }
+
+    pub fn record(
+        &self,
+        request_type: RequestType,
+        provider: &str,
+        model: &str,
+        tokens_used: TokenUsage,
+        cost: f64,
+    ) {
+        let record = UsageRecord {
+            timestamp: Utc::now(),
+            session_id: self.session_id,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            tokens_used,
+            cost,
+            request_type,
+        };
+
+        if let Some(ledger) = &self.ledger {
+            let (ledger, line) = (ledger.clone(), record.clone());
+            let append = move || {
+                // Losing a ledger line must not fail the request that was already paid for
+                if let Err(e) = ledger.append(&line) {
+                    tracing::warn!("failed to append to usage ledger {}: {}", ledger.path().display(), e);
+                }
+            };
+            // Off the async threads when there are some; the runtime waits
+            // for blocking tasks on shutdown, so no line is lost on exit
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => drop(runtime.spawn_blocking(append)),
+                Err(_) => append(),
+            }
+        }
+
+        self.records.lock().unwrap().push(record);
+    }
+
+    pub fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    /// Everything recorded in this session
+    pub fn session_summary(&self) -> CostSummary {
+        let records = self.records.lock().unwrap();
+        summarize(records.iter())
+    }
+
+    /// Records from the current local calendar day, including earlier runs
+    /// when a ledger is attached
+    pub fn daily_summary(&self) -> CostSummary {
+        let records = self.records.lock().unwrap();
+        summarize(
+            self.history
+                .iter()
+                .chain(records.iter())
+                .filter(|record| in_period(record, BudgetPeriod::Day)),
+        )
+    }
+
+    /// Dollars spent in `period`, optionally only on one provider
+    pub fn spent(&self, period: BudgetPeriod, provider: Option<&str>) -> f64 {
+        let records = self.records.lock().unwrap();
+        let earlier = match period {
+            BudgetPeriod::Session => &[][..],
+            BudgetPeriod::Day | BudgetPeriod::Month => &self.history[..],
+        };
+
+        earlier
+            .iter()
+            .chain(records.iter())
+            .filter(|record| in_period(record, period))
+            .filter(|record| provider.is_none_or(|name| record.provider == name))
+            .fold(0.0, |total, record| total + record.cost)
+    }
+
+    /// Check that spending `estimated_cost` more stays within `limits`
+    ///
+    /// Passing `provider` checks that provider's spending only. Returns a
+    /// warning once spending reaches `warn_at` of any limit.
+    pub fn check_budget(
+        &self,
+        limits: &BudgetLimits,
+        warn_at: f64,
+        provider: Option<&str>,
+        estimated_cost: f64,
+    ) -> Result<Option<String>> {
+        let periods = [
+            (BudgetPeriod::Session, limits.session),
+            (BudgetPeriod::Day, limits.daily),
+            (BudgetPeriod::Month, limits.monthly),
+        ];
+        let mut warning = None;
+
+        for (period, limit) in periods {
+            let Some(limit) = limit else { continue };
+            let spent = self.spent(period, provider);
+            let scope = match provider {
+                Some(name) => format!("{} budget for {}", period.label(), name),
+                None => format!("{} budget", period.label()),
+            };
+
+            if spent + estimated_cost > limit {
+                return Err(ComrudeError::Provider(ProviderError::BudgetExceeded(format!(
+                    "{} of ${:.2} would be exceeded (${:.2} spent)",
+                    scope, limit, spent
+                ))));
+            }
+            if warning.is_none() && spent + estimated_cost >= limit * warn_at {
+                warning = Some(format!(
+                    "{} is {:.0}% used (${:.2} of ${:.2})",
+                    scope,
+                    spent / limit * 100.0,
+                    spent,
+                    limit
+                ));
+            }
+        }
+
+        Ok(warning)
+    }
+}
+
+/// Whether a record falls in the current `period`, in local time
+fn in_period(record: &UsageRecord, period: BudgetPeriod) -> bool {
+    let now = Local::now();
+    let at = record.timestamp.with_timezone(&Local);
+    match period {
+        // Only this tracker's own records are ever passed in for a session
+        BudgetPeriod::Session => true,
+        BudgetPeriod::Day => at.date_naive() == now.date_naive(),
+        BudgetPeriod::Month => at.year() == now.year() && at.month() == now.month(),
+    }
+}
+
+fn summarize<'a>(records: impl Iterator<Item = &'a UsageRecord>) -> CostSummary {
+    let mut summary = CostSummary::default();
+    for record in records {
+        summary.add(record);
+    }
+    summary
+}
@@ -1,11 +1,19 @@
 pub mod traits;
 pub mod manager;
+pub mod cost;
+pub mod limiter;
 pub mod openai;
 pub mod anthropic;
 pub mod ollama;
+pub mod retry;
+
+mod streaming;
 
 pub use traits::*;
 pub use manager::*;
+pub use cost::*;
+pub use limiter::*;
 pub use openai::*;
 pub use anthropic::*;
-pub use ollama::*;
\ No newline at end of file
+pub use ollama::*;
+pub use retry::*;
\ No newline at end of file
@@ -0,0 +1,150 @@
+//! Client-side throttling to stay within provider rate limits
+//!
+//! Each provider gets a pair of token buckets, one for requests and one for
+//! estimated tokens, refilled continuously at the per-minute rate. Callers
+//! wait in line for capacity instead of being rejected.
+
+use comrude_core::{GenerationRequest, RateLimits, TokenUsage};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(limit: u32) -> Self {
+        let capacity = f64::from(limit.max(1));
+        Self {
+            capacity,
+            available: capacity,
+            refill_per_sec: capacity / 60.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Time until `amount` is available; requests larger than the bucket
+    /// only wait for it to be full
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.refill_per_sec)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: TokenBucket,
+    tokens: TokenBucket,
+}
+
+/// Request and token budget for a single provider
+#[derive(Debug)]
+pub struct RateLimiter {
+    // The async mutex hands out the lock in FIFO order, so waiting callers
+    // are served in the order they arrived
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits) -> Self {
+        Self {
+            buckets: Mutex::new(Buckets {
+                requests: TokenBucket::per_minute(limits.requests_per_minute),
+                tokens: TokenBucket::per_minute(limits.tokens_per_minute),
+            }),
+        }
+    }
+
+    /// Wait until one request using about `tokens` tokens fits the budget
+    pub async fn acquire(&self, tokens: u32) {
+        let tokens = f64::from(tokens);
+        let mut buckets = self.buckets.lock().await;
+
+        loop {
+            let now = Instant::now();
+            buckets.requests.refill(now);
+            buckets.tokens.refill(now);
+
+            let wait = buckets.requests.wait_for(1.0).max(buckets.tokens.wait_for(tokens));
+            if wait.is_zero() {
+                buckets.requests.take(1.0);
+                buckets.tokens.take(tokens);
+                return;
+            }
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Rough token count of a request: its text at four characters per token
+/// plus the completion it may produce
+pub fn estimate_request_tokens(request: &GenerationRequest) -> u32 {
+    estimate_request_usage(request).total_tokens
+}
+
+/// Same estimate as `estimate_request_tokens`, split into prompt and
+/// completion so it can be priced
+pub fn estimate_request_usage(request: &GenerationRequest) -> TokenUsage {
+    let characters = request.prompt.len()
+        + request.system_prompt.as_ref().map_or(0, |s| s.len())
+        + request.context.iter().map(|item| item.content.len()).sum::<usize>();
+
+    let prompt_tokens = (characters / 4) as u32;
+    let completion_tokens = request.max_tokens.unwrap_or(0);
+    TokenUsage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire_queues_once_budget_is_spent() {
+        // 600 requests per minute refills one request every 100ms
+        let limiter = RateLimiter::new(&RateLimits {
+            requests_per_minute: 600,
+            tokens_per_minute: 1_000_000,
+        });
+
+        let start = Instant::now();
+        for _ in 0..600 {
+            limiter.acquire(10).await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        limiter.acquire(10).await;
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[test]
+    fn test_oversized_requests_only_wait_for_a_full_bucket() {
+        let mut bucket = TokenBucket::per_minute(60);
+        bucket.take(1000.0);
+
+        assert_eq!(bucket.available, 0.0);
+        assert_eq!(bucket.wait_for(1000.0), Duration::from_secs(60));
+    }
+}
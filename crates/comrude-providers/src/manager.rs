@@ -1,14 +1,47 @@
+use crate::cost::CostTracker;
+use crate::limiter::{estimate_request_tokens, estimate_request_usage, RateLimiter};
+use crate::retry::RetryPolicy;
 use crate::traits::LLMProvider;
-use comrude_core::{Config, GenerationRequest, GenerationResponse, Result, ProviderError};
+use comrude_core::{
+    AgentBackend, Config, CostPer1k, GenerationRequest, GenerationResponse, ProviderCapabilities, RateLimits,
+    RequestType, Result, ProviderError, StreamChunk, TokenUsage, UsageLedger,
+};
+use futures::Stream;
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use tokio::sync::RwLock;
 
+/// A stream opened by [`ProviderManager::generate_stream`] and who is answering it
+pub struct GenerationStream {
+    pub provider: String,
+    pub model: String,
+    /// Provider that failed first when the stream came from a fallback
+    pub fallback_from: Option<String>,
+    /// Set once spending is close to a configured budget
+    pub budget_warning: Option<String>,
+    pub chunks: Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>,
+}
+
+/// Result of a call together with the provider and model that produced it
+struct Answered<T> {
+    handle: Arc<dyn LLMProvider>,
+    provider: String,
+    model: String,
+    fallback_from: Option<String>,
+    budget_warning: Option<String>,
+    value: T,
+}
+
 #[derive(Debug)]
 pub struct ProviderManager {
-    providers: Arc<RwLock<HashMap<String, Box<dyn LLMProvider>>>>,
+    providers: Arc<RwLock<HashMap<String, Arc<dyn LLMProvider>>>>,
+    limiters: Arc<RwLock<HashMap<String, Arc<RateLimiter>>>>,
     current_provider: Arc<RwLock<Option<String>>>,
     current_models: Arc<RwLock<HashMap<String, String>>>, // provider_name -> model_name
+    costs: Arc<CostTracker>,
     config: Arc<Config>,
 }
 
@@ -16,16 +49,21 @@ impl ProviderManager {
     pub fn new(config: Config) -> Self {
         Self {
             providers: Arc::new(RwLock::new(HashMap::new())),
+            limiters: Arc::new(RwLock::new(HashMap::new())),
             current_provider: Arc::new(RwLock::new(None)),
             current_models: Arc::new(RwLock::new(HashMap::new())),
+            costs: Arc::new(CostTracker::default()),
             config: Arc::new(config),
         }
     }
 
     pub async fn register_provider(&self, provider: Box<dyn LLMProvider>) -> Result<()> {
         let name = provider.name().to_string();
+        let limits = self.rate_limits(&name, provider.capabilities().rate_limits);
+        self.limiters.write().await.insert(name.clone(), Arc::new(RateLimiter::new(&limits)));
+
         let mut providers = self.providers.write().await;
-        providers.insert(name, provider);
+        providers.insert(name, Arc::from(provider));
         Ok(())
     }
 
@@ -43,9 +81,9 @@ impl ProviderManager {
     }
 
     pub async fn get_current_provider(&self) -> Result<Arc<dyn LLMProvider>> {
-        let current = self.current_provider.read().await;
-        if let Some(provider_name) = &*current {
-            self.get_provider(provider_name).await
+        let current = self.current_provider.read().await.clone();
+        if let Some(provider_name) = current {
+            self.get_provider(&provider_name).await
         } else {
             Err(comrude_core::ComrudeError::Provider(
                 ProviderError::NotConfigured("No current provider set".to_string())
@@ -53,19 +91,17 @@ impl ProviderManager {
         }
     }
 
+    /// Get a shared handle to a provider
+    ///
+    /// The registry lock is released before this returns, so callers can hold
+    /// the handle across long generations without blocking registration.
     pub async fn get_provider(&self, name: &str) -> Result<Arc<dyn LLMProvider>> {
         let providers = self.providers.read().await;
-        if let Some(_) = providers.get(name) {
-            // Note: This is a temporary workaround due to trait object limitations
-            // In a real implementation, we'd need to restructure this to use Arc<dyn LLMProvider>
-            Err(comrude_core::ComrudeError::Provider(
-                ProviderError::NotFound("Provider access pattern needs refactoring".to_string())
-            ))
-        } else {
-            Err(comrude_core::ComrudeError::Provider(
+        providers.get(name).cloned().ok_or_else(|| {
+            comrude_core::ComrudeError::Provider(
                 ProviderError::NotFound(name.to_string())
-            ))
-        }
+            )
+        })
     }
 
     pub async fn list_providers(&self) -> Vec<String> {
@@ -78,7 +114,8 @@ impl ProviderManager {
         current.clone()
     }
 
-    pub async fn generate(&self, mut request: GenerationRequest) -> Result<GenerationResponse> {
+    /// Resolve which provider should answer a request first
+    async fn resolve_provider(&self, request: &GenerationRequest) -> Result<String> {
         // Check if a specific provider is requested in metadata
         let provider_name = if let Some(preferred) = request.metadata.get("preferred_provider") {
             preferred.as_str().map(|s| s.to_string())
@@ -87,54 +124,273 @@ impl ProviderManager {
             current.clone()
         };
 
-        let provider_name = provider_name.ok_or_else(|| {
+        provider_name.ok_or_else(|| {
             comrude_core::ComrudeError::Provider(
                 ProviderError::NotConfigured("No provider specified".to_string())
             )
-        })?;
+        })
+    }
+
+    /// Model selected for a provider, or its configured default
+    async fn model_for(&self, provider_name: &str) -> String {
+        let current_models = self.current_models.read().await;
+        current_models.get(provider_name)
+            .cloned()
+            .unwrap_or_else(|| self.get_default_model(provider_name))
+    }
 
+    /// The primary provider followed by the registered fallbacks from config,
+    /// or the primary alone when the request sets `no_fallback`
+    async fn fallback_chain(&self, primary: &str, request: &GenerationRequest) -> Vec<String> {
         let providers = self.providers.read().await;
-        let provider = providers.get(&provider_name).ok_or_else(|| {
-            comrude_core::ComrudeError::Provider(
-                ProviderError::NotFound(provider_name.clone())
-            )
-        })?;
+        let mut chain = vec![primary.to_string()];
+        if request.metadata.get("no_fallback").and_then(|value| value.as_bool()) == Some(true) {
+            return chain;
+        }
 
-        // Set default model from config if not specified
-        if request.model.is_none() {
-            // Check if there's a current model set for this provider
-            let current_models = self.current_models.read().await;
-            request.model = current_models.get(&provider_name)
-                .cloned()
-                .or_else(|| Some(self.get_default_model(&provider_name)));
+        for name in &self.config.providers.fallback {
+            if providers.contains_key(name) && !chain.contains(name) {
+                chain.push(name.clone());
+            }
         }
 
-        provider.generate(request).await
+        chain
+    }
+
+    /// Run `call` against the primary provider, retrying transient failures,
+    /// and walk the fallback chain while another provider could recover
+    ///
+    /// Each provider is checked against the budgets first. A spent global
+    /// budget stops the request; a spent provider budget only rules that
+    /// provider out.
+    async fn with_fallback<T, F, Fut>(&self, request: GenerationRequest, call: F) -> Result<Answered<T>>
+    where
+        F: Fn(Arc<dyn LLMProvider>, GenerationRequest) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let primary = self.resolve_provider(&request).await?;
+        let mut last_error = None;
+
+        for provider_name in self.fallback_chain(&primary, &request).await {
+            let provider = self.get_provider(&provider_name).await?;
+            let limiter = self.limiters.read().await.get(&provider_name).cloned();
+
+            // Only the primary honours an explicitly requested model; fallbacks
+            // use whatever is selected or configured for them
+            let mut attempt = request.clone();
+            if attempt.model.is_none() || provider_name != primary {
+                attempt.model = Some(self.model_for(&provider_name).await);
+            }
+            let model = attempt.model.clone().unwrap_or_default();
+            let estimated_tokens = estimate_request_tokens(&attempt);
+
+            let estimated_cost = request_cost(&self.config, provider.as_ref(), &model, &estimate_request_usage(&attempt));
+            let budget = &self.config.budget;
+            let global_warning = self.costs.check_budget(&budget.global_limits(), budget.warn_at, None, estimated_cost)?;
+            let provider_warning = match budget.providers.get(&provider_name) {
+                Some(limits) => match self.costs.check_budget(limits, budget.warn_at, Some(&provider_name), estimated_cost) {
+                    Ok(warning) => warning,
+                    Err(e) => {
+                        last_error = Some(e);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            let budget_warning = global_warning.or(provider_warning);
+            if let Some(warning) = &budget_warning {
+                tracing::warn!("{}", warning);
+            }
+
+            // Transient failures are retried on the same provider before moving on
+            let policy = self.retry_policy(&provider_name);
+            let result = policy.run(
+                || provider.rate_limit_reset(),
+                || self.costs.record(RequestType::Retry, &provider_name, &model, TokenUsage::default(), 0.0),
+                || {
+                    let limiter = limiter.clone();
+                    let call = call(provider.clone(), attempt.clone());
+                    async move {
+                        // Queue for the provider's budget rather than risk a 429
+                        if let Some(limiter) = limiter {
+                            limiter.acquire(estimated_tokens).await;
+                        }
+                        call.await
+                    }
+                },
+            ).await;
+
+            match result {
+                Ok(value) => {
+                    return Ok(Answered {
+                        handle: provider,
+                        provider: provider_name,
+                        model,
+                        fallback_from: last_error.is_some().then(|| primary.clone()),
+                        budget_warning,
+                        value,
+                    });
+                }
+                Err(e) if e.is_failover_candidate() => last_error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.expect("fallback chain always contains the primary provider"))
+    }
+
+    pub async fn generate(&self, request: GenerationRequest) -> Result<GenerationResponse> {
+        let answered = self.with_fallback(request, |provider, request| async move {
+            provider.generate(request).await
+        }).await?;
+
+        let mut response = answered.value;
+        response.cost = request_cost(&self.config, answered.handle.as_ref(), &response.model_used, &response.tokens_used);
+        self.costs.record(
+            RequestType::Generation,
+            &answered.provider,
+            &response.model_used,
+            response.tokens_used.clone(),
+            response.cost,
+        );
+
+        response.metadata.insert("provider".to_string(), answered.provider.into());
+        if let Some(primary) = answered.fallback_from {
+            response.metadata.insert("fallback_from".to_string(), primary.into());
+        }
+        if let Some(warning) = answered.budget_warning {
+            response.metadata.insert("budget_warning".to_string(), warning.into());
+        }
+
+        Ok(response)
+    }
+
+    /// Open a stream, falling back to other providers if it cannot be started
+    ///
+    /// A provider that cannot stream, whether chosen or fallen back to,
+    /// answers in one piece that arrives as a single stretch of chunks.
+    pub async fn generate_stream(&self, mut request: GenerationRequest) -> Result<GenerationStream> {
+        request.stream = true;
+
+        let answered = self.with_fallback(request.clone(), |provider, mut request| async move {
+            if provider.capabilities().supports_streaming {
+                return provider.generate_stream(request).await;
+            }
+            request.stream = false;
+            let response = provider.generate(request).await?;
+            Ok(response_chunks(response))
+        }).await?;
+
+        let chunks = MeteredStream {
+            inner: answered.value,
+            costs: self.costs.clone(),
+            config: self.config.clone(),
+            handle: answered.handle,
+            provider: answered.provider.clone(),
+            model: answered.model.clone(),
+            prompt_tokens: estimate_request_usage(&request).prompt_tokens,
+            streamed_bytes: 0,
+            recorded: false,
+        };
+
+        Ok(GenerationStream {
+            provider: answered.provider,
+            model: answered.model,
+            fallback_from: answered.fallback_from,
+            budget_warning: answered.budget_warning,
+            chunks: Box::pin(chunks),
+        })
+    }
+
+    /// Also append every usage record to `ledger`
+    pub async fn with_usage_ledger(mut self, ledger: UsageLedger) -> Self {
+        self.costs = Arc::new(CostTracker::with_ledger(ledger).await);
+        self
+    }
+
+    /// Embed text with the current provider
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let provider = self.get_current_provider().await?;
+        let provider_name = provider.name().to_string();
+        let model = self.model_for(&provider_name).await;
+
+        let embedding = provider.embed(text).await?;
+
+        // Embedding endpoints don't all report usage, so estimate it
+        let prompt_tokens = (text.len() / 4) as u32;
+        let usage = TokenUsage { prompt_tokens, completion_tokens: 0, total_tokens: prompt_tokens };
+        let cost = request_cost(&self.config, provider.as_ref(), &model, &usage);
+        self.costs.record(RequestType::Embedding, &provider_name, &model, usage, cost);
+
+        Ok(embedding)
+    }
+
+    /// Usage and cost of every request made through this manager
+    pub fn cost_tracker(&self) -> Arc<CostTracker> {
+        self.costs.clone()
+    }
+
+    pub async fn current_capabilities(&self) -> Result<ProviderCapabilities> {
+        let provider = self.get_current_provider().await?;
+        Ok(provider.capabilities())
     }
 
     pub async fn health_check(&self, provider_name: &str) -> Result<comrude_core::HealthStatus> {
-        let providers = self.providers.read().await;
-        let provider = providers.get(provider_name).ok_or_else(|| {
-            comrude_core::ComrudeError::Provider(
-                ProviderError::NotFound(provider_name.to_string())
-            )
-        })?;
+        let provider = self.get_provider(provider_name).await?;
 
         provider.health_check().await
     }
 
     pub async fn health_check_all(&self) -> HashMap<String, Result<comrude_core::HealthStatus>> {
-        let providers = self.providers.read().await;
+        // Snapshot the handles so slow checks don't hold the registry lock
+        let providers: Vec<(String, Arc<dyn LLMProvider>)> = {
+            let providers = self.providers.read().await;
+            providers.iter().map(|(name, provider)| (name.clone(), provider.clone())).collect()
+        };
         let mut results = HashMap::new();
 
-        for (name, provider) in providers.iter() {
+        for (name, provider) in providers {
             let health = provider.health_check().await;
-            results.insert(name.clone(), health);
+            results.insert(name, health);
         }
 
         results
     }
 
+    fn retry_policy(&self, provider_name: &str) -> RetryPolicy {
+        let max_attempts = match provider_name {
+            "openai" => self.config.providers.openai.as_ref().map(|c| c.max_attempts),
+            "anthropic" => self.config.providers.anthropic.as_ref().map(|c| c.max_attempts),
+            "ollama" => self.config.providers.ollama.as_ref().map(|c| c.max_attempts),
+            _ => None,
+        };
+
+        max_attempts
+            .map(RetryPolicy::with_max_attempts)
+            .unwrap_or_default()
+    }
+
+    /// Advertised limits with any per-provider overrides from config applied
+    fn rate_limits(&self, provider_name: &str, advertised: RateLimits) -> RateLimits {
+        let (requests_per_minute, tokens_per_minute) = match provider_name {
+            "openai" => self.config.providers.openai.as_ref()
+                .map(|c| (c.requests_per_minute, c.tokens_per_minute))
+                .unwrap_or_default(),
+            "anthropic" => self.config.providers.anthropic.as_ref()
+                .map(|c| (c.requests_per_minute, c.tokens_per_minute))
+                .unwrap_or_default(),
+            "ollama" => self.config.providers.ollama.as_ref()
+                .map(|c| (c.requests_per_minute, c.tokens_per_minute))
+                .unwrap_or_default(),
+            _ => (None, None),
+        };
+
+        RateLimits {
+            requests_per_minute: requests_per_minute.unwrap_or(advertised.requests_per_minute),
+            tokens_per_minute: tokens_per_minute.unwrap_or(advertised.tokens_per_minute),
+        }
+    }
+
     fn get_default_model(&self, provider_name: &str) -> String {
         match provider_name {
             "openai" => self.config.providers.openai
@@ -179,9 +435,9 @@ impl ProviderManager {
     }
 
     pub async fn list_models_for_current_provider(&self) -> Result<Vec<comrude_core::ModelInfo>> {
-        let current = self.current_provider.read().await;
-        if let Some(provider_name) = &*current {
-            self.list_models_for_provider(provider_name).await
+        let current = self.current_provider.read().await.clone();
+        if let Some(provider_name) = current {
+            self.list_models_for_provider(&provider_name).await
         } else {
             Err(comrude_core::ComrudeError::Provider(
                 ProviderError::NotConfigured("No current provider set".to_string())
@@ -190,12 +446,7 @@ impl ProviderManager {
     }
 
     pub async fn list_models_for_provider(&self, provider_name: &str) -> Result<Vec<comrude_core::ModelInfo>> {
-        let providers = self.providers.read().await;
-        let provider = providers.get(provider_name).ok_or_else(|| {
-            comrude_core::ComrudeError::Provider(
-                ProviderError::NotFound(provider_name.to_string())
-            )
-        })?;
+        let provider = self.get_provider(provider_name).await?;
 
         provider.list_models().await
     }
@@ -225,8 +476,395 @@ impl ProviderManager {
     }
 }
 
+/// Records the usage of a stream in the cost tracker
+///
+/// Usage normally arrives as a chunk near the end. A stream that ends,
+/// fails or is dropped before that was still billed, so it is recorded
+/// with the prompt estimated from the request and the completion from the
+/// text that arrived.
+struct MeteredStream {
+    inner: Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>,
+    costs: Arc<CostTracker>,
+    config: Arc<Config>,
+    handle: Arc<dyn LLMProvider>,
+    provider: String,
+    model: String,
+    prompt_tokens: u32,
+    streamed_bytes: usize,
+    recorded: bool,
+}
+
+impl MeteredStream {
+    fn record(&mut self, usage: TokenUsage) {
+        let cost = request_cost(&self.config, self.handle.as_ref(), &self.model, &usage);
+        self.costs.record(RequestType::Generation, &self.provider, &self.model, usage, cost);
+        self.recorded = true;
+    }
+
+    fn record_estimate(&mut self) {
+        if self.recorded {
+            return;
+        }
+        let completion_tokens = (self.streamed_bytes / 4) as u32;
+        self.record(TokenUsage {
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens,
+            total_tokens: self.prompt_tokens + completion_tokens,
+        });
+    }
+}
+
+impl Stream for MeteredStream {
+    type Item = Result<StreamChunk>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let chunk = std::task::ready!(this.inner.as_mut().poll_next(cx));
+        match &chunk {
+            Some(Ok(StreamChunk::Content(text))) => this.streamed_bytes += text.len(),
+            Some(Ok(StreamChunk::TokenUsage(usage))) => this.record(usage.clone()),
+            None => this.record_estimate(),
+            _ => {}
+        }
+        Poll::Ready(chunk)
+    }
+}
+
+impl Drop for MeteredStream {
+    fn drop(&mut self) {
+        self.record_estimate();
+    }
+}
+
+/// A whole response as the chunks a stream would have delivered
+fn response_chunks(response: GenerationResponse) -> Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>> {
+    let mut chunks = Vec::new();
+    if !response.content.is_empty() {
+        chunks.push(StreamChunk::Content(response.content));
+    }
+    chunks.extend(response.tool_calls.into_iter().map(StreamChunk::ToolCall));
+    chunks.push(StreamChunk::TokenUsage(response.tokens_used));
+    chunks.push(StreamChunk::Done);
+    Box::pin(futures::stream::iter(chunks.into_iter().map(Ok)))
+}
+
+/// Price a request, preferring prices from config over the provider's table
+fn request_cost(config: &Config, provider: &dyn LLMProvider, model: &str, usage: &TokenUsage) -> f64 {
+    match CostPer1k::lookup(&config.pricing, model) {
+        Some(price) => price.cost_of(usage),
+        None => provider.estimate_cost(model, usage),
+    }
+}
+
+#[async_trait::async_trait]
+impl AgentBackend for ProviderManager {
+    async fn generate(&self, request: GenerationRequest) -> Result<GenerationResponse> {
+        ProviderManager::generate(self, request).await
+    }
+}
+
 impl Default for ProviderManager {
     fn default() -> Self {
         Self::new(Config::default())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use comrude_core::{FinishReason, HealthStatus, ModelInfo};
+    use futures::{Stream, StreamExt};
+    use std::time::Duration;
+    use tokio::sync::Notify;
+
+    #[derive(Debug)]
+    enum Behaviour {
+        /// Answer once the notify is released
+        Block(Arc<Notify>),
+        /// Fail as if the API returned this status
+        Fail(u16),
+        Answer,
+        /// Stream two pieces of text, then usage
+        Stream,
+    }
+
+    #[derive(Debug)]
+    struct TestProvider {
+        name: String,
+        behaviour: Behaviour,
+    }
+
+    #[async_trait]
+    impl LLMProvider for TestProvider {
+        fn name(&self) -> &str { &self.name }
+        fn version(&self) -> &str { "test" }
+        fn description(&self) -> &str { "test provider" }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities {
+                max_context_length: 1024,
+                supports_streaming: matches!(self.behaviour, Behaviour::Stream),
+                supports_tools: false,
+                supports_vision: false,
+                supports_embeddings: false,
+                supports_fine_tuning: false,
+                rate_limits: RateLimits { requests_per_minute: 60, tokens_per_minute: 1000 },
+            }
+        }
+
+        fn supported_models(&self) -> Vec<ModelInfo> { Vec::new() }
+
+        async fn health_check(&self) -> Result<HealthStatus> { Ok(HealthStatus::Healthy) }
+        async fn test_connection(&self) -> Result<()> { Ok(()) }
+
+        async fn generate(&self, request: GenerationRequest) -> Result<GenerationResponse> {
+            match &self.behaviour {
+                Behaviour::Block(release) => release.notified().await,
+                Behaviour::Fail(status) => {
+                    return Err(comrude_core::ComrudeError::Provider(ProviderError::from_http_status(
+                        &self.name,
+                        reqwest::StatusCode::from_u16(*status).unwrap(),
+                        "test failure".to_string(),
+                    )));
+                }
+                Behaviour::Answer | Behaviour::Stream => {}
+            }
+            Ok(GenerationResponse {
+                content: format!("done by {}", self.name),
+                model_used: request.model.unwrap_or_default(),
+                tokens_used: TokenUsage { prompt_tokens: 1000, completion_tokens: 500, total_tokens: 1500 },
+                cost: 0.0,
+                finish_reason: FinishReason::Stop,
+                tool_calls: Vec::new(),
+                metadata: HashMap::new(),
+            })
+        }
+
+        async fn generate_stream(&self, _request: GenerationRequest) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+            Ok(Box::pin(futures::stream::iter([
+                Ok(StreamChunk::Content("a".repeat(400))),
+                Ok(StreamChunk::Content("b".repeat(400))),
+                Ok(StreamChunk::TokenUsage(TokenUsage { prompt_tokens: 1000, completion_tokens: 500, total_tokens: 1500 })),
+                Ok(StreamChunk::Done),
+            ])))
+        }
+    }
+
+    fn test_provider(name: &str, behaviour: Behaviour) -> Box<dyn LLMProvider> {
+        Box::new(TestProvider { name: name.to_string(), behaviour })
+    }
+
+    #[tokio::test]
+    async fn test_registration_is_not_blocked_by_generation() {
+        let manager = Arc::new(ProviderManager::default());
+        let release = Arc::new(Notify::new());
+        manager.register_provider(test_provider("slow", Behaviour::Block(release.clone()))).await.unwrap();
+        manager.set_current_provider("slow").await.unwrap();
+
+        let generation = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.generate(GenerationRequest::default()).await }
+        });
+        tokio::task::yield_now().await;
+
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            manager.register_provider(test_provider("other", Behaviour::Answer)),
+        ).await.expect("registration blocked by an in-flight generation").unwrap();
+
+        let handle = manager.get_provider("other").await.unwrap();
+        assert_eq!(handle.name(), "other");
+
+        release.notify_one();
+        assert_eq!(generation.await.unwrap().unwrap().content, "done by slow");
+    }
+
+    #[tokio::test]
+    async fn test_generate_walks_fallback_chain() {
+        let mut config = Config::default();
+        config.providers.fallback = vec!["anthropic".to_string(), "missing".to_string(), "ollama".to_string()];
+        // Skip retries so the test does not sit through backoff delays
+        config.providers.openai.as_mut().unwrap().max_attempts = 1;
+        config.providers.anthropic.as_mut().unwrap().max_attempts = 1;
+        let manager = ProviderManager::new(config);
+        manager.register_provider(test_provider("openai", Behaviour::Fail(429))).await.unwrap();
+        manager.register_provider(test_provider("anthropic", Behaviour::Fail(503))).await.unwrap();
+        manager.register_provider(test_provider("ollama", Behaviour::Answer)).await.unwrap();
+        manager.set_current_provider("openai").await.unwrap();
+
+        let response = manager.generate(GenerationRequest::default()).await.unwrap();
+
+        assert_eq!(response.content, "done by ollama");
+        assert_eq!(response.model_used, "codellama:7b");
+        assert_eq!(response.metadata["provider"], "ollama");
+        assert_eq!(response.metadata["fallback_from"], "openai");
+    }
+
+    #[tokio::test]
+    async fn test_generate_honours_no_fallback() {
+        let mut config = Config::default();
+        config.providers.fallback = vec!["anthropic".to_string()];
+        config.providers.ollama.as_mut().unwrap().max_attempts = 1;
+        let manager = ProviderManager::new(config);
+        manager.register_provider(test_provider("ollama", Behaviour::Fail(503))).await.unwrap();
+        manager.register_provider(test_provider("anthropic", Behaviour::Answer)).await.unwrap();
+        manager.set_current_provider("anthropic").await.unwrap();
+
+        let mut request = GenerationRequest::default();
+        request.metadata.insert("preferred_provider".to_string(), "ollama".into());
+        request.metadata.insert("no_fallback".to_string(), true.into());
+        let error = manager.generate(request).await.unwrap_err();
+
+        assert_eq!(error.to_string(), "Provider error: API error from ollama: HTTP 503 Service Unavailable: test failure");
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_answers_in_one_piece_without_streaming() {
+        let mut config = Config::default();
+        config.providers.fallback = vec!["ollama".to_string()];
+        config.providers.openai.as_mut().unwrap().max_attempts = 1;
+        let manager = ProviderManager::new(config);
+        manager.register_provider(test_provider("openai", Behaviour::Fail(503))).await.unwrap();
+        manager.register_provider(test_provider("ollama", Behaviour::Answer)).await.unwrap();
+        manager.set_current_provider("openai").await.unwrap();
+
+        let generation = manager.generate_stream(GenerationRequest::default()).await.unwrap();
+        assert_eq!((generation.provider.as_str(), generation.fallback_from.as_deref()), ("ollama", Some("openai")));
+
+        let chunks: Vec<_> = generation.chunks.collect().await;
+        assert!(matches!(&chunks[0], Ok(StreamChunk::Content(text)) if text == "done by ollama"));
+        assert!(matches!(chunks.last(), Some(Ok(StreamChunk::Done))));
+        // Usage still reaches the cost tracker
+        assert_eq!(manager.cost_tracker().session_summary().tokens.total_tokens, 1500);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_stream_records_estimated_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = UsageLedger::new(dir.path().join("usage.jsonl"));
+        let manager = ProviderManager::default().with_usage_ledger(ledger.clone()).await;
+        manager.register_provider(test_provider("openai", Behaviour::Stream)).await.unwrap();
+        manager.set_current_provider("openai").await.unwrap();
+
+        // Small enough for the rate limiter to let both requests through at once
+        let request = GenerationRequest { prompt: "x".repeat(200), max_tokens: Some(100), ..GenerationRequest::default() };
+        let mut generation = manager.generate_stream(request.clone()).await.unwrap();
+        assert!(matches!(generation.chunks.next().await, Some(Ok(StreamChunk::Content(_)))));
+        drop(generation);
+
+        // 200 prompt bytes and 400 streamed bytes at four bytes per token
+        let session = manager.cost_tracker().session_summary();
+        assert_eq!(session.requests, 1);
+        assert_eq!((session.tokens.prompt_tokens, session.tokens.completion_tokens), (50, 100));
+
+        // The ledger line is appended on the blocking pool
+        let mut records = Vec::new();
+        for _ in 0..100 {
+            records = ledger.read_all().unwrap();
+            if !records.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].tokens_used.total_tokens, 150);
+
+        // A stream read to the end is recorded once, with the reported usage
+        let generation = manager.generate_stream(request).await.unwrap();
+        let _: Vec<_> = generation.chunks.collect().await;
+        let session = manager.cost_tracker().session_summary();
+        assert_eq!((session.requests, session.tokens.total_tokens), (2, 1650));
+    }
+
+    #[tokio::test]
+    async fn test_generate_does_not_fall_back_on_client_errors() {
+        let mut config = Config::default();
+        config.providers.fallback = vec!["ollama".to_string()];
+        let manager = ProviderManager::new(config);
+        manager.register_provider(test_provider("openai", Behaviour::Fail(400))).await.unwrap();
+        manager.register_provider(test_provider("ollama", Behaviour::Answer)).await.unwrap();
+        manager.set_current_provider("openai").await.unwrap();
+
+        let error = manager.generate(GenerationRequest::default()).await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            comrude_core::ComrudeError::Provider(ProviderError::ApiError { ref provider, .. }) if provider == "openai"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_generate_prices_with_config_override_and_tracks_cost() {
+        let mut config = Config::default();
+        config.pricing.insert("test-model".to_string(), CostPer1k { input: 0.01, output: 0.03 });
+        let manager = ProviderManager::new(config);
+        manager.register_provider(test_provider("openai", Behaviour::Answer)).await.unwrap();
+        manager.set_current_provider("openai").await.unwrap();
+
+        let request = GenerationRequest {
+            model: Some("test-model-2025-01-01".to_string()),
+            ..GenerationRequest::default()
+        };
+        let response = manager.generate(request).await.unwrap();
+        assert!((response.cost - 0.025).abs() < 1e-9);
+
+        let session = manager.cost_tracker().session_summary();
+        assert_eq!(session.requests, 1);
+        assert_eq!(session.tokens.total_tokens, 1500);
+        assert!((session.by_model["openai/test-model-2025-01-01"] - 0.025).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_generate_warns_then_stops_at_session_budget() {
+        let mut config = Config::default();
+        config.pricing.insert("test-model".to_string(), CostPer1k { input: 0.01, output: 0.03 });
+        config.budget.session = Some(0.06);
+        let manager = ProviderManager::new(config);
+        manager.register_provider(test_provider("openai", Behaviour::Answer)).await.unwrap();
+        manager.set_current_provider("openai").await.unwrap();
+
+        // Each answer costs $0.025 against an estimate of $0.003
+        let request = GenerationRequest {
+            model: Some("test-model".to_string()),
+            max_tokens: Some(100),
+            ..GenerationRequest::default()
+        };
+
+        for _ in 0..2 {
+            let response = manager.generate(request.clone()).await.unwrap();
+            assert!(!response.metadata.contains_key("budget_warning"));
+        }
+        let third = manager.generate(request.clone()).await.unwrap();
+        assert!(third.metadata.contains_key("budget_warning"));
+
+        let error = manager.generate(request).await.unwrap_err();
+        assert!(matches!(error, comrude_core::ComrudeError::Provider(ProviderError::BudgetExceeded(_))));
+        assert_eq!(manager.cost_tracker().session_summary().requests, 3);
+    }
+
+    #[tokio::test]
+    async fn test_spent_provider_budget_falls_back() {
+        let mut config = Config::default();
+        config.providers.fallback = vec!["ollama".to_string()];
+        config.budget.providers.insert(
+            "openai".to_string(),
+            comrude_core::BudgetLimits { daily: Some(0.0), ..Default::default() },
+        );
+        config.pricing.insert("gpt".to_string(), CostPer1k { input: 0.01, output: 0.03 });
+        let manager = ProviderManager::new(config);
+        manager.register_provider(test_provider("openai", Behaviour::Answer)).await.unwrap();
+        manager.register_provider(test_provider("ollama", Behaviour::Answer)).await.unwrap();
+        manager.set_current_provider("openai").await.unwrap();
+
+        let request = GenerationRequest {
+            prompt: "a prompt that costs something".to_string(),
+            model: Some("gpt-4".to_string()),
+            ..GenerationRequest::default()
+        };
+        let response = manager.generate(request).await.unwrap();
+
+        assert_eq!(response.content, "done by ollama");
+        assert_eq!(response.metadata["fallback_from"], "openai");
+    }
+}
@@ -6,16 +6,20 @@ use comrude_core::{
 };
 use chrono::Utc;
 use uuid::Uuid;
+use crate::streaming::ndjson_lines;
 use crate::traits::LLMProvider;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use std::pin::Pin;
 use std::time::Duration;
 
 #[derive(Debug)]
 pub struct OllamaProvider {
     client: Client,
+    // Local models can take minutes to finish a reply, so streams use a
+    // client that only bounds connection setup.
+    stream_client: Client,
     config: OllamaConfig,
 }
 
@@ -40,17 +44,38 @@ struct OllamaOptions {
     num_predict: Option<i32>,
 }
 
+#[derive(Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OllamaChatMessage>,
+    stream: bool,
+    options: Option<OllamaOptions>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaChatMessage {
+    role: String,
+    content: String,
+}
+
+/// One record of the `/api/chat` NDJSON stream
+#[derive(Deserialize)]
+struct OllamaChatChunk {
+    message: Option<OllamaChatMessage>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    error: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct OllamaResponse {
     model: String,
-    created_at: String,
     response: String,
     done: bool,
-    context: Option<Vec<i32>>,
     total_duration: Option<u64>,
-    load_duration: Option<u64>,
     prompt_eval_count: Option<u32>,
-    prompt_eval_duration: Option<u64>,
     eval_count: Option<u32>,
     eval_duration: Option<u64>,
 }
@@ -63,19 +88,6 @@ struct OllamaTagsResponse {
 #[derive(Deserialize)]
 struct OllamaModel {
     name: String,
-    modified_at: String,
-    size: u64,
-    digest: String,
-    details: Option<OllamaModelDetails>,
-}
-
-#[derive(Deserialize)]
-struct OllamaModelDetails {
-    format: String,
-    family: String,
-    families: Option<Vec<String>>,
-    parameter_size: String,
-    quantization_level: String,
 }
 
 impl OllamaProvider {
@@ -83,14 +95,57 @@ impl OllamaProvider {
         let client = Client::builder()
             .timeout(Duration::from_secs(config.timeout_seconds))
             .build()
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
+
+        let stream_client = Client::builder()
+            .connect_timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         Ok(Self {
             client,
+            stream_client,
             config,
         })
     }
 
+    fn build_chat_request(&self, request: GenerationRequest) -> OllamaChatRequest {
+        let model = request.model.unwrap_or_else(|| self.config.default_model.clone());
+
+        let mut messages = Vec::new();
+
+        if let Some(system_prompt) = request.system_prompt {
+            messages.push(OllamaChatMessage {
+                role: "system".to_string(),
+                content: system_prompt,
+            });
+        }
+
+        for context_item in &request.context {
+            messages.push(OllamaChatMessage {
+                role: "user".to_string(),
+                content: format!("Context: {}", context_item.content),
+            });
+        }
+
+        messages.push(OllamaChatMessage {
+            role: "user".to_string(),
+            content: request.prompt,
+        });
+
+        OllamaChatRequest {
+            model,
+            messages,
+            stream: true,
+            options: Some(OllamaOptions {
+                temperature: request.temperature,
+                top_p: None,
+                top_k: None,
+                num_predict: request.max_tokens.map(|t| t as i32),
+            }),
+        }
+    }
+
     fn build_prompt_from_messages(&self, messages: &[Message], main_prompt: &str) -> String {
         let mut prompt_parts = Vec::new();
 
@@ -121,6 +176,41 @@ impl OllamaProvider {
     }
 }
 
+/// Map one NDJSON record from `/api/chat` to the chunks it carries
+fn chat_chunk_to_stream_chunks(line: &str) -> Vec<Result<StreamChunk>> {
+    let chunk: OllamaChatChunk = match serde_json::from_str(line) {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            return vec![Err(comrude_core::ComrudeError::Provider(
+                ProviderError::InvalidResponse(format!("ollama: malformed stream record: {}", e))
+            ))];
+        }
+    };
+
+    if let Some(error) = chunk.error {
+        return vec![Ok(StreamChunk::Error(error))];
+    }
+
+    let mut chunks = Vec::new();
+
+    if let Some(message) = chunk.message.filter(|m| !m.content.is_empty()) {
+        chunks.push(Ok(StreamChunk::Content(message.content)));
+    }
+
+    if chunk.done {
+        let prompt_tokens = chunk.prompt_eval_count.unwrap_or(0);
+        let completion_tokens = chunk.eval_count.unwrap_or(0);
+        chunks.push(Ok(StreamChunk::TokenUsage(TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        })));
+        chunks.push(Ok(StreamChunk::Done));
+    }
+
+    chunks
+}
+
 #[async_trait]
 impl LLMProvider for OllamaProvider {
     fn name(&self) -> &str {
@@ -246,6 +336,7 @@ impl LLMProvider for OllamaProvider {
                 ProviderError::ApiError {
                     provider: "ollama".to_string(),
                     message: "Ollama server is not healthy".to_string(),
+                    status: None,
                 }
             ))
         }
@@ -300,21 +391,18 @@ impl LLMProvider for OllamaProvider {
             .json(&ollama_request)
             .send()
             .await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
             return Err(comrude_core::ComrudeError::Provider(
-                ProviderError::ApiError {
-                    provider: "ollama".to_string(),
-                    message: format!("HTTP {}: {}", status, error_text),
-                }
+                ProviderError::from_http_status("ollama", status, error_text)
             ));
         }
 
         let ollama_response: OllamaResponse = response.json().await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         // Calculate token usage from eval counts if available
         let tokens_used = if let (Some(prompt_tokens), Some(completion_tokens)) = 
@@ -354,14 +442,43 @@ impl LLMProvider for OllamaProvider {
         })
     }
 
-    async fn generate_stream(&self, _request: GenerationRequest) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
-        // For now, return an error as streaming implementation is complex
-        Err(comrude_core::ComrudeError::Provider(
-            ProviderError::ApiError {
-                provider: "ollama".to_string(),
-                message: "Streaming not implemented yet".to_string(),
-            }
-        ))
+    async fn generate_stream(&self, request: GenerationRequest) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+        let url = format!("{}/api/chat", self.config.endpoint);
+        let chat_request = self.build_chat_request(request);
+
+        // Only waiting for the response headers (which includes loading the
+        // model) is bounded by the timeout
+        let response = tokio::time::timeout(
+            Duration::from_secs(self.config.timeout_seconds),
+            self.stream_client
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .json(&chat_request)
+                .send(),
+        )
+        .await
+        .map_err(|_| comrude_core::ComrudeError::Provider(
+            ProviderError::Timeout("ollama".to_string())
+        ))?
+        .map_err(comrude_core::ComrudeError::Network)?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(comrude_core::ComrudeError::Provider(
+                ProviderError::from_http_status("ollama", status, error_text)
+            ));
+        }
+
+        let chunks = ndjson_lines(response.bytes_stream()).flat_map(|line| {
+            let chunks = match line {
+                Ok(line) => chat_chunk_to_stream_chunks(&line),
+                Err(e) => vec![Err(e)],
+            };
+            futures::stream::iter(chunks)
+        });
+
+        Ok(Box::pin(chunks))
     }
 
     async fn embed(&self, text: &str) -> Result<Vec<f32>> {
@@ -378,21 +495,18 @@ impl LLMProvider for OllamaProvider {
             .json(&request_body)
             .send()
             .await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
             return Err(comrude_core::ComrudeError::Provider(
-                ProviderError::ApiError {
-                    provider: "ollama".to_string(),
-                    message: format!("HTTP {}: {}", status, error_text),
-                }
+                ProviderError::from_http_status("ollama", status, error_text)
             ));
         }
 
         let response_json: serde_json::Value = response.json().await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         let embedding = response_json["embedding"]
             .as_array()
@@ -413,7 +527,7 @@ impl LLMProvider for OllamaProvider {
             .get(&url)
             .send()
             .await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         if !response.status().is_success() {
             return Ok(self.supported_models()); // Fallback to static list
@@ -453,4 +567,63 @@ impl LLMProvider for OllamaProvider {
 
         Ok(models)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_generate_stream_reads_chat_ndjson() {
+        let mut server = mockito::Server::new_async().await;
+        let records = [
+            serde_json::json!({"model": "codellama:7b", "message": {"role": "assistant", "content": "fn "}, "done": false}),
+            serde_json::json!({"model": "codellama:7b", "message": {"role": "assistant", "content": "main()"}, "done": false}),
+            serde_json::json!({
+                "model": "codellama:7b", "message": {"role": "assistant", "content": ""}, "done": true,
+                "prompt_eval_count": 14, "eval_count": 6
+            }),
+        ];
+        let body: String = records.iter().map(|record| format!("{}\n", record)).collect();
+
+        let mock = server.mock("POST", "/api/chat")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "stream": true,
+                "messages": [{"role": "user", "content": "Write main"}]
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/x-ndjson")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::new(OllamaConfig {
+            endpoint: server.url(),
+            ..OllamaConfig::default()
+        }).unwrap();
+
+        let request = GenerationRequest {
+            prompt: "Write main".to_string(),
+            stream: true,
+            ..GenerationRequest::default()
+        };
+        let chunks: Vec<StreamChunk> = provider.generate_stream(request).await.unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        mock.assert_async().await;
+        assert!(matches!(&chunks[0], StreamChunk::Content(text) if text == "fn "));
+        assert!(matches!(&chunks[1], StreamChunk::Content(text) if text == "main()"));
+        match &chunks[2] {
+            StreamChunk::TokenUsage(usage) => {
+                assert_eq!(usage.prompt_tokens, 14);
+                assert_eq!(usage.completion_tokens, 6);
+                assert_eq!(usage.total_tokens, 20);
+            }
+            other => panic!("expected token usage, got {:?}", other),
+        }
+        assert!(matches!(chunks[3], StreamChunk::Done));
+        assert_eq!(chunks.len(), 4);
+    }
+}
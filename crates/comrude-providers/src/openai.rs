@@ -2,18 +2,26 @@ use async_trait::async_trait;
 use comrude_core::{
     GenerationRequest, GenerationResponse, StreamChunk, ProviderCapabilities,
     ModelInfo, HealthStatus, Result, ProviderError, OpenAIConfig,
-    Message, MessageSender, MessageContent, TokenUsage, FinishReason, CostPer1k
+    TokenUsage, FinishReason, CostPer1k
 };
+use chrono::{DateTime, Utc};
+use crate::retry::RateLimitTracker;
+use crate::streaming::{sse_events, SseEvent};
 use crate::traits::LLMProvider;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use futures::Stream;
+use futures::{Stream, StreamExt};
+use std::collections::BTreeMap;
 use std::pin::Pin;
 use std::time::Duration;
 
 #[derive(Debug)]
 pub struct OpenAIProvider {
     client: Client,
+    // Streams can legitimately run longer than the request timeout, so they
+    // use a client that only bounds connection setup.
+    stream_client: Client,
+    rate_limits: RateLimitTracker,
     config: OpenAIConfig,
     api_key: String,
 }
@@ -25,13 +33,52 @@ struct OpenAIRequest {
     max_tokens: Option<u32>,
     temperature: Option<f32>,
     stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAIStreamOptions>,
     tools: Option<Vec<OpenAITool>>,
 }
 
+#[derive(Serialize)]
+struct OpenAIStreamOptions {
+    include_usage: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 struct OpenAIMessage {
     role: String,
-    content: String,
+    /// Null on assistant messages that only call tools
+    content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<OpenAIToolCall>>,
+    /// Set on `tool` messages to the call they answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl OpenAIMessage {
+    fn text(role: &str, content: String) -> Self {
+        Self {
+            role: role.to_string(),
+            content: Some(content),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct OpenAIToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    call_type: String,
+    function: OpenAIFunctionCall,
+}
+
+/// A complete function call; `arguments` is JSON encoded as a string
+#[derive(Serialize, Deserialize)]
+struct OpenAIFunctionCall {
+    name: String,
+    arguments: String,
 }
 
 #[derive(Serialize)]
@@ -50,17 +97,21 @@ struct OpenAIFunction {
 
 #[derive(Deserialize)]
 struct OpenAIResponse {
-    id: String,
-    object: String,
-    created: u64,
     model: String,
     choices: Vec<OpenAIChoice>,
     usage: Option<OpenAIUsage>,
 }
 
+/// A `chat.completion.chunk`; the envelope fields are not needed for reassembly
+#[derive(Deserialize)]
+struct OpenAIStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAIChoice>,
+    usage: Option<OpenAIUsage>,
+}
+
 #[derive(Deserialize)]
 struct OpenAIChoice {
-    index: u32,
     message: Option<OpenAIMessage>,
     delta: Option<OpenAIDelta>,
     finish_reason: Option<String>,
@@ -69,6 +120,31 @@ struct OpenAIChoice {
 #[derive(Deserialize)]
 struct OpenAIDelta {
     content: Option<String>,
+    tool_calls: Option<Vec<OpenAIToolCallDelta>>,
+}
+
+/// Fragment of a tool call; only the first fragment carries the id and name
+#[derive(Deserialize)]
+struct OpenAIToolCallDelta {
+    index: usize,
+    id: Option<String>,
+    function: Option<OpenAIFunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIErrorEnvelope {
+    error: OpenAIErrorBody,
+}
+
+#[derive(Deserialize)]
+struct OpenAIErrorBody {
+    message: String,
 }
 
 #[derive(Deserialize)]
@@ -78,6 +154,112 @@ struct OpenAIUsage {
     total_tokens: u32,
 }
 
+#[derive(Default)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Reassembles streamed `chat.completion.chunk` payloads into chunks
+#[derive(Default)]
+struct OpenAIStreamState {
+    tool_calls: BTreeMap<usize, PendingToolCall>,
+    finished: bool,
+}
+
+impl OpenAIStreamState {
+    fn handle_event(&mut self, event: SseEvent) -> Vec<Result<StreamChunk>> {
+        if self.finished {
+            return Vec::new();
+        }
+
+        if event.data.trim() == "[DONE]" {
+            self.finished = true;
+            let mut chunks = self.flush_tool_calls();
+            chunks.push(Ok(StreamChunk::Done));
+            return chunks;
+        }
+
+        let payload: OpenAIStreamChunk = match serde_json::from_str(&event.data) {
+            Ok(payload) => payload,
+            Err(e) => {
+                if let Ok(envelope) = serde_json::from_str::<OpenAIErrorEnvelope>(&event.data) {
+                    return vec![Ok(StreamChunk::Error(envelope.error.message))];
+                }
+                return vec![Err(comrude_core::ComrudeError::Provider(
+                    ProviderError::InvalidResponse(format!("openai: malformed stream chunk: {}", e))
+                ))];
+            }
+        };
+
+        let mut chunks = Vec::new();
+
+        for choice in payload.choices {
+            if let Some(delta) = choice.delta {
+                if let Some(content) = delta.content.filter(|c| !c.is_empty()) {
+                    chunks.push(Ok(StreamChunk::Content(content)));
+                }
+
+                for fragment in delta.tool_calls.unwrap_or_default() {
+                    let pending = self.tool_calls.entry(fragment.index).or_default();
+                    if let Some(id) = fragment.id {
+                        pending.id = id;
+                    }
+                    if let Some(function) = fragment.function {
+                        if let Some(name) = function.name {
+                            pending.name.push_str(&name);
+                        }
+                        if let Some(arguments) = function.arguments {
+                            pending.arguments.push_str(&arguments);
+                        }
+                    }
+                }
+            }
+
+            if choice.finish_reason.is_some() {
+                chunks.extend(self.flush_tool_calls());
+            }
+        }
+
+        // Only sent when stream_options.include_usage is honoured by the endpoint
+        if let Some(usage) = payload.usage {
+            chunks.push(Ok(StreamChunk::TokenUsage(TokenUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                total_tokens: usage.total_tokens,
+            })));
+        }
+
+        chunks
+    }
+
+    fn flush_tool_calls(&mut self) -> Vec<Result<StreamChunk>> {
+        std::mem::take(&mut self.tool_calls)
+            .into_values()
+            .map(|call| {
+                let arguments = if call.arguments.trim().is_empty() {
+                    Ok(serde_json::Value::Object(serde_json::Map::new()))
+                } else {
+                    serde_json::from_str(&call.arguments)
+                };
+
+                arguments
+                    .map(|arguments| StreamChunk::ToolCall(comrude_core::ToolCall {
+                        id: call.id,
+                        name: call.name.clone(),
+                        arguments,
+                    }))
+                    .map_err(|e| comrude_core::ComrudeError::Provider(
+                        ProviderError::InvalidResponse(format!(
+                            "openai: invalid arguments for tool '{}': {}", call.name, e
+                        ))
+                    ))
+            })
+            .collect()
+    }
+}
+
 #[derive(Deserialize)]
 struct OpenAIModelsResponse {
     data: Vec<OpenAIModel>,
@@ -86,9 +268,6 @@ struct OpenAIModelsResponse {
 #[derive(Deserialize)]
 struct OpenAIModel {
     id: String,
-    object: String,
-    created: u64,
-    owned_by: String,
 }
 
 impl OpenAIProvider {
@@ -101,40 +280,22 @@ impl OpenAIProvider {
         let client = Client::builder()
             .timeout(Duration::from_secs(config.timeout_seconds))
             .build()
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
+
+        let stream_client = Client::builder()
+            .connect_timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         Ok(Self {
             client,
+            stream_client,
+            rate_limits: RateLimitTracker::default(),
             config,
             api_key,
         })
     }
 
-    fn convert_messages(&self, messages: &[Message]) -> Vec<OpenAIMessage> {
-        messages.iter().map(|msg| {
-            let role = match &msg.sender {
-                MessageSender::User => "user",
-                MessageSender::Assistant { .. } => "assistant",
-                MessageSender::System => "system",
-            };
-
-            let content = match &msg.content {
-                MessageContent::Text(text) => text.clone(),
-                MessageContent::Code { language: _, content } => content.clone(),
-                MessageContent::File { path: _, preview } => {
-                    preview.clone().unwrap_or_else(|| "File content".to_string())
-                },
-                MessageContent::Error { error_type: _, message } => message.clone(),
-                MessageContent::Progress { stage, percentage: _ } => stage.clone(),
-            };
-
-            OpenAIMessage {
-                role: role.to_string(),
-                content,
-            }
-        }).collect()
-    }
-
     fn convert_tools(&self, tools: &[comrude_core::ToolDefinition]) -> Vec<OpenAITool> {
         tools.iter().map(|tool| {
             OpenAITool {
@@ -147,6 +308,95 @@ impl OpenAIProvider {
             }
         }).collect()
     }
+
+    /// Whether streams should end with a usage chunk
+    fn stream_usage(&self) -> bool {
+        self.config.stream_usage
+            .unwrap_or_else(|| self.config.base_url.starts_with("https://api.openai.com/"))
+    }
+
+    fn build_request(&self, request: GenerationRequest, stream: bool) -> OpenAIRequest {
+        let model = request.model.unwrap_or_else(|| self.config.default_model.clone());
+        
+        // Build context messages
+        let mut messages = Vec::new();
+        
+        // Add system prompt if provided
+        if let Some(system_prompt) = &request.system_prompt {
+            messages.push(OpenAIMessage::text("system", system_prompt.clone()));
+        }
+
+        // Add context messages from request
+        for context_item in &request.context {
+            messages.push(OpenAIMessage::text("user", format!("Context: {}", context_item.content)));
+        }
+
+        // Add main prompt
+        messages.push(OpenAIMessage::text("user", request.prompt.clone()));
+
+        // Replay earlier tool use: the assistant's calls, then one `tool` message per result
+        for exchange in &request.tool_exchanges {
+            messages.push(OpenAIMessage {
+                role: "assistant".to_string(),
+                content: (!exchange.content.is_empty()).then(|| exchange.content.clone()),
+                tool_calls: Some(exchange.calls.iter().map(|call| OpenAIToolCall {
+                    id: call.id.clone(),
+                    call_type: "function".to_string(),
+                    function: OpenAIFunctionCall {
+                        name: call.name.clone(),
+                        arguments: call.arguments.to_string(),
+                    },
+                }).collect()),
+                tool_call_id: None,
+            });
+            for result in &exchange.results {
+                messages.push(OpenAIMessage {
+                    tool_call_id: Some(result.call_id.clone()),
+                    ..OpenAIMessage::text("tool", result.content.clone())
+                });
+            }
+        }
+
+        OpenAIRequest {
+            model,
+            messages,
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            stream: Some(stream),
+            stream_options: (stream && self.stream_usage()).then_some(OpenAIStreamOptions { include_usage: true }),
+            tools: if request.tools.is_empty() {
+                None
+            } else {
+                Some(self.convert_tools(&request.tools))
+            },
+        }
+    }
+
+    /// Send a chat completions request and turn HTTP failures into provider errors
+    async fn send_request(&self, client: &Client, body: &OpenAIRequest) -> Result<reqwest::Response> {
+        let url = format!("{}/chat/completions", self.config.base_url);
+
+        let response = client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(body)
+            .send()
+            .await
+            .map_err(comrude_core::ComrudeError::Network)?;
+
+        self.rate_limits.record(response.status(), response.headers());
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(comrude_core::ComrudeError::Provider(
+                ProviderError::from_http_status("openai", status, error_text)
+            ));
+        }
+
+        Ok(response)
+    }
 }
 
 #[async_trait]
@@ -228,6 +478,10 @@ impl LLMProvider for OpenAIProvider {
     }
 
     async fn health_check(&self) -> Result<HealthStatus> {
+        if let Some(reset_time) = self.rate_limits.reset_at() {
+            return Ok(HealthStatus::RateLimited { reset_time });
+        }
+
         let url = format!("{}/models", self.config.base_url);
         
         let response = self.client
@@ -236,6 +490,13 @@ impl LLMProvider for OpenAIProvider {
             .send()
             .await;
 
+        if let Ok(resp) = &response {
+            self.rate_limits.record(resp.status(), resp.headers());
+            if let Some(reset_time) = self.rate_limits.reset_at() {
+                return Ok(HealthStatus::RateLimited { reset_time });
+            }
+        }
+
         match response {
             Ok(resp) if resp.status().is_success() => {
                 Ok(HealthStatus::Healthy)
@@ -257,85 +518,46 @@ impl LLMProvider for OpenAIProvider {
                 ProviderError::ApiError {
                     provider: "openai".to_string(),
                     message: "OpenAI API is not healthy".to_string(),
+                    status: None,
                 }
             ))
         }
     }
 
     async fn generate(&self, request: GenerationRequest) -> Result<GenerationResponse> {
-        let url = format!("{}/chat/completions", self.config.base_url);
-        
-        let model = request.model.unwrap_or_else(|| self.config.default_model.clone());
-        
-        // Build context messages
-        let mut messages = Vec::new();
-        
-        // Add system prompt if provided
-        if let Some(system_prompt) = &request.system_prompt {
-            messages.push(OpenAIMessage {
-                role: "system".to_string(),
-                content: system_prompt.clone(),
-            });
-        }
-
-        // Add context messages from request
-        for context_item in &request.context {
-            messages.push(OpenAIMessage {
-                role: "user".to_string(),
-                content: format!("Context: {}", context_item.content),
-            });
-        }
-
-        // Add main prompt
-        messages.push(OpenAIMessage {
-            role: "user".to_string(),
-            content: request.prompt.clone(),
-        });
-
-        let openai_request = OpenAIRequest {
-            model,
-            messages,
-            max_tokens: request.max_tokens,
-            temperature: request.temperature,
-            stream: Some(false),
-            tools: if request.tools.is_empty() {
-                None
-            } else {
-                Some(self.convert_tools(&request.tools))
-            },
-        };
-
-        let response = self.client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&openai_request)
-            .send()
-            .await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(comrude_core::ComrudeError::Provider(
-                ProviderError::ApiError {
-                    provider: "openai".to_string(),
-                    message: format!("HTTP {}: {}", status, error_text),
-                }
-            ));
-        }
+        let openai_request = self.build_request(request, false);
+        let response = self.send_request(&self.client, &openai_request).await?;
 
         let openai_response: OpenAIResponse = response.json().await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         let choice = openai_response.choices.into_iter().next()
             .ok_or_else(|| comrude_core::ComrudeError::Provider(
                 ProviderError::InvalidResponse("No choices in response".to_string())
             ))?;
 
-        let content = choice.message
-            .map(|msg| msg.content)
-            .unwrap_or_else(|| "No content in response".to_string());
+        let message = choice.message
+            .ok_or_else(|| comrude_core::ComrudeError::Provider(
+                ProviderError::InvalidResponse("openai: choice has no message".to_string())
+            ))?;
+        let content = message.content.unwrap_or_default();
+
+        let tool_calls = message.tool_calls.unwrap_or_default()
+            .into_iter()
+            .map(|call| {
+                let arguments = serde_json::from_str(&call.function.arguments)
+                    .map_err(|e| comrude_core::ComrudeError::Provider(
+                        ProviderError::InvalidResponse(format!(
+                            "openai: invalid arguments for tool '{}': {}", call.function.name, e
+                        ))
+                    ))?;
+                Ok(comrude_core::ToolCall {
+                    id: call.id,
+                    name: call.function.name,
+                    arguments,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
         let tokens_used = openai_response.usage.map(|usage| TokenUsage {
             prompt_tokens: usage.prompt_tokens,
@@ -343,7 +565,9 @@ impl LLMProvider for OpenAIProvider {
             total_tokens: usage.total_tokens,
         }).unwrap_or_default();
 
+        // A forced tool choice finishes with "stop" even though tools were called
         let finish_reason = match choice.finish_reason.as_deref() {
+            _ if !tool_calls.is_empty() => FinishReason::ToolCalls,
             Some("stop") => FinishReason::Stop,
             Some("length") => FinishReason::Length,
             Some("tool_calls") => FinishReason::ToolCalls,
@@ -352,25 +576,42 @@ impl LLMProvider for OpenAIProvider {
             None => FinishReason::Stop,
         };
 
+        let cost = self.estimate_cost(&openai_response.model, &tokens_used);
+
         Ok(GenerationResponse {
             content,
             model_used: openai_response.model,
             tokens_used,
-            cost: 0.0, // TODO: Calculate actual cost
+            cost,
             finish_reason,
-            tool_calls: Vec::new(), // TODO: Extract tool calls
+            tool_calls,
             metadata: std::collections::HashMap::new(),
         })
     }
 
-    async fn generate_stream(&self, _request: GenerationRequest) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
-        // For now, return an error as streaming implementation is complex
-        Err(comrude_core::ComrudeError::Provider(
-            ProviderError::ApiError {
-                provider: "openai".to_string(),
-                message: "Streaming not implemented yet".to_string(),
-            }
-        ))
+    async fn generate_stream(&self, request: GenerationRequest) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+        let openai_request = self.build_request(request, true);
+
+        // Only waiting for the response headers is bounded by the timeout
+        let response = tokio::time::timeout(
+            Duration::from_secs(self.config.timeout_seconds),
+            self.send_request(&self.stream_client, &openai_request),
+        )
+        .await
+        .map_err(|_| comrude_core::ComrudeError::Provider(
+            ProviderError::Timeout("openai".to_string())
+        ))??;
+
+        let mut state = OpenAIStreamState::default();
+        let chunks = sse_events(response.bytes_stream()).flat_map(move |event| {
+            let chunks = match event {
+                Ok(event) => state.handle_event(event),
+                Err(e) => vec![Err(e)],
+            };
+            futures::stream::iter(chunks)
+        });
+
+        Ok(Box::pin(chunks))
     }
 
     async fn embed(&self, text: &str) -> Result<Vec<f32>> {
@@ -388,21 +629,18 @@ impl LLMProvider for OpenAIProvider {
             .json(&request_body)
             .send()
             .await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
             return Err(comrude_core::ComrudeError::Provider(
-                ProviderError::ApiError {
-                    provider: "openai".to_string(),
-                    message: format!("HTTP {}: {}", status, error_text),
-                }
+                ProviderError::from_http_status("openai", status, error_text)
             ));
         }
 
         let response_json: serde_json::Value = response.json().await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         let embedding = response_json["data"][0]["embedding"]
             .as_array()
@@ -424,7 +662,7 @@ impl LLMProvider for OpenAIProvider {
             .header("Authorization", format!("Bearer {}", self.api_key))
             .send()
             .await
-            .map_err(|e| comrude_core::ComrudeError::Network(e))?;
+            .map_err(comrude_core::ComrudeError::Network)?;
 
         if !response.status().is_success() {
             return Ok(self.supported_models()); // Fallback to static list
@@ -452,4 +690,175 @@ impl LLMProvider for OpenAIProvider {
 
         Ok(models)
     }
-}
\ No newline at end of file
+
+    fn rate_limit_reset(&self) -> Option<DateTime<Utc>> {
+        self.rate_limits.reset_at()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sse(payloads: &[serde_json::Value]) -> String {
+        let mut body: String = payloads.iter()
+            .map(|data| format!("data: {}\n\n", data))
+            .collect();
+        body.push_str("data: [DONE]\n\n");
+        body
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_reassembles_deltas() {
+        let mut server = mockito::Server::new_async().await;
+        let body = sse(&[
+            serde_json::json!({"choices": [{"index": 0, "delta": {"role": "assistant", "content": "Hel"}}]}),
+            serde_json::json!({"choices": [{"index": 0, "delta": {"content": "lo"}}]}),
+            serde_json::json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "id": "call_1", "type": "function", "function": {"name": "read_file", "arguments": "{\"pa"}}
+            ]}}]}),
+            serde_json::json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "function": {"arguments": "th\": \"src/main.rs\"}"}}
+            ]}}]}),
+            serde_json::json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "tool_calls"}]}),
+            serde_json::json!({"choices": [], "usage": {"prompt_tokens": 9, "completion_tokens": 21, "total_tokens": 30}}),
+        ]);
+
+        let mock = server.mock("POST", "/chat/completions")
+            .match_header("authorization", "Bearer test-key")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "stream": true,
+                "stream_options": {"include_usage": true}
+            })))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        std::env::set_var("COMRUDE_TEST_OPENAI_STREAM_KEY", "test-key");
+        let provider = OpenAIProvider::new(OpenAIConfig {
+            api_key_env: "COMRUDE_TEST_OPENAI_STREAM_KEY".to_string(),
+            base_url: server.url(),
+            stream_usage: Some(true),
+            ..OpenAIConfig::default()
+        }).unwrap();
+
+        let request = GenerationRequest {
+            prompt: "Say hello".to_string(),
+            stream: true,
+            ..GenerationRequest::default()
+        };
+        let chunks: Vec<StreamChunk> = provider.generate_stream(request).await.unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        mock.assert_async().await;
+        assert!(matches!(&chunks[0], StreamChunk::Content(text) if text == "Hel"));
+        assert!(matches!(&chunks[1], StreamChunk::Content(text) if text == "lo"));
+        match &chunks[2] {
+            StreamChunk::ToolCall(call) => {
+                assert_eq!(call.id, "call_1");
+                assert_eq!(call.name, "read_file");
+                assert_eq!(call.arguments, serde_json::json!({"path": "src/main.rs"}));
+            }
+            other => panic!("expected tool call, got {:?}", other),
+        }
+        match &chunks[3] {
+            StreamChunk::TokenUsage(usage) => {
+                assert_eq!(usage.prompt_tokens, 9);
+                assert_eq!(usage.completion_tokens, 21);
+                assert_eq!(usage.total_tokens, 30);
+            }
+            other => panic!("expected token usage, got {:?}", other),
+        }
+        assert!(matches!(chunks[4], StreamChunk::Done));
+        assert_eq!(chunks.len(), 5);
+
+        // Other OpenAI-compatible servers are only asked for usage when configured to
+        let compatible = OpenAIProvider::new(OpenAIConfig {
+            api_key_env: "COMRUDE_TEST_OPENAI_STREAM_KEY".to_string(),
+            base_url: "http://localhost:8000/v1".to_string(),
+            ..OpenAIConfig::default()
+        }).unwrap();
+        let body = serde_json::to_value(compatible.build_request(GenerationRequest::default(), true)).unwrap();
+        assert!(body.get("stream_options").is_none());
+        let official = OpenAIProvider::new(OpenAIConfig {
+            api_key_env: "COMRUDE_TEST_OPENAI_STREAM_KEY".to_string(),
+            ..OpenAIConfig::default()
+        }).unwrap();
+        let body = serde_json::to_value(official.build_request(GenerationRequest::default(), true)).unwrap();
+        assert_eq!(body["stream_options"], serde_json::json!({"include_usage": true}));
+    }
+
+    #[tokio::test]
+    async fn test_generate_round_trips_tool_calls() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "messages": [
+                    {"role": "user", "content": "What is in main.rs?"},
+                    {"role": "assistant", "content": null, "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "read_file", "arguments": "{\"path\":\"src/main.rs\"}"}
+                    }]},
+                    {"role": "tool", "tool_call_id": "call_1", "content": "fn main() {}"}
+                ]
+            })))
+            .with_status(200)
+            .with_body(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-4",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": null, "tool_calls": [{
+                        "id": "call_2",
+                        "type": "function",
+                        "function": {"name": "list_dir", "arguments": "{\"path\": \"src\"}"}
+                    }]},
+                    "finish_reason": "tool_calls"
+                }],
+                "usage": {"prompt_tokens": 40, "completion_tokens": 12, "total_tokens": 52}
+            }).to_string())
+            .create_async()
+            .await;
+
+        std::env::set_var("COMRUDE_TEST_OPENAI_TOOLS_KEY", "test-key");
+        let provider = OpenAIProvider::new(OpenAIConfig {
+            api_key_env: "COMRUDE_TEST_OPENAI_TOOLS_KEY".to_string(),
+            base_url: server.url(),
+            ..OpenAIConfig::default()
+        }).unwrap();
+
+        let request = GenerationRequest {
+            prompt: "What is in main.rs?".to_string(),
+            tool_exchanges: vec![comrude_core::ToolExchange {
+                content: String::new(),
+                calls: vec![comrude_core::ToolCall {
+                    id: "call_1".to_string(),
+                    name: "read_file".to_string(),
+                    arguments: serde_json::json!({"path": "src/main.rs"}),
+                }],
+                results: vec![comrude_core::ToolResult {
+                    call_id: "call_1".to_string(),
+                    content: "fn main() {}".to_string(),
+                    is_error: false,
+                }],
+            }],
+            ..GenerationRequest::default()
+        };
+        let response = provider.generate(request).await.unwrap();
+
+        mock.assert_async().await;
+        assert!(matches!(response.finish_reason, FinishReason::ToolCalls));
+        assert_eq!(response.content, "");
+        assert_eq!(response.tool_calls.len(), 1);
+        assert_eq!(response.tool_calls[0].id, "call_2");
+        assert_eq!(response.tool_calls[0].name, "list_dir");
+        assert_eq!(response.tool_calls[0].arguments, serde_json::json!({"path": "src"}));
+    }
+}
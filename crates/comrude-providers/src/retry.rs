@@ -0,0 +1,275 @@
+//! Retrying transient provider failures
+//!
+//! Providers record the rate limit headers of every response in a
+//! [`RateLimitTracker`]. [`RetryPolicy::run`] waits until the advertised
+//! reset when one is known, and falls back to jittered exponential backoff
+//! otherwise.
+
+use chrono::{DateTime, Utc};
+use comrude_core::Result;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Pairs of (remaining, reset) headers sent by OpenAI and Anthropic
+const LIMIT_HEADERS: &[(&str, &str)] = &[
+    ("x-ratelimit-remaining-requests", "x-ratelimit-reset-requests"),
+    ("x-ratelimit-remaining-tokens", "x-ratelimit-reset-tokens"),
+    ("anthropic-ratelimit-requests-remaining", "anthropic-ratelimit-requests-reset"),
+    ("anthropic-ratelimit-tokens-remaining", "anthropic-ratelimit-tokens-reset"),
+    ("anthropic-ratelimit-input-tokens-remaining", "anthropic-ratelimit-input-tokens-reset"),
+    ("anthropic-ratelimit-output-tokens-remaining", "anthropic-ratelimit-output-tokens-reset"),
+];
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts including the first one
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Longest single wait; a provider asking for more is given up on
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn with_max_attempts(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Self::default()
+        }
+    }
+
+    /// Call `call` until it succeeds, fails permanently or runs out of attempts
+    ///
+    /// `rate_limit_reset` is consulted after each retryable failure so waits
+    /// follow the provider's own headers when it sent any. `on_retry` is
+    /// called each time a retry is scheduled, and not for the failure that
+    /// ends the attempts.
+    pub async fn run<T, F, Fut, R, O>(&self, rate_limit_reset: R, mut on_retry: O, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+        R: Fn() -> Option<DateTime<Utc>>,
+        O: FnMut(),
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Ok(value) => return Ok(value),
+                Err(e) if e.is_retryable() && attempt < self.max_attempts => {
+                    let advertised = rate_limit_reset()
+                        .and_then(|reset| (reset - Utc::now()).to_std().ok());
+                    let delay = match advertised {
+                        Some(wait) if wait > self.max_delay => return Err(e),
+                        Some(wait) if !wait.is_zero() => wait,
+                        _ => self.backoff(attempt),
+                    };
+
+                    on_retry();
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Exponential backoff with jitter between half and the full delay
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let millis = delay.as_millis() as u64;
+        Duration::from_millis(rand::random_range(millis / 2..=millis))
+    }
+}
+
+/// Remembers when a provider said its rate limit resets
+#[derive(Debug, Default)]
+pub(crate) struct RateLimitTracker {
+    reset_at: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl RateLimitTracker {
+    /// Update from the status and headers of a response
+    pub(crate) fn record(&self, status: StatusCode, headers: &HeaderMap) {
+        let now = Utc::now();
+        let throttled = matches!(status.as_u16(), 429 | 503 | 529);
+
+        // Retry-After is authoritative when the request was rejected
+        let mut reset = if throttled { retry_after(headers, now) } else { None };
+
+        if reset.is_none() {
+            for (remaining, reset_header) in LIMIT_HEADERS {
+                let exhausted = header(headers, remaining)
+                    .and_then(|value| value.parse::<u64>().ok())
+                    == Some(0);
+                if !(throttled || exhausted) {
+                    continue;
+                }
+                if let Some(at) = header(headers, reset_header).and_then(|value| parse_reset(value, now)) {
+                    reset = reset.max(Some(at));
+                }
+            }
+        }
+
+        *self.reset_at.lock().unwrap() = reset;
+    }
+
+    /// The recorded reset time, if it is still in the future
+    pub(crate) fn reset_at(&self) -> Option<DateTime<Utc>> {
+        let reset_at = *self.reset_at.lock().unwrap();
+        reset_at.filter(|at| *at > Utc::now())
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim)
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date
+fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let value = header(headers, "retry-after")?;
+    if let Some(wait) = value.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()) {
+        return chrono::Duration::from_std(wait).ok().map(|wait| now + wait);
+    }
+    DateTime::parse_from_rfc2822(value).ok().map(|at| at.with_timezone(&Utc))
+}
+
+/// Anthropic sends RFC 3339 timestamps, OpenAI durations such as `6m0s`
+fn parse_reset(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Some(at.with_timezone(&Utc));
+    }
+    let wait = chrono::Duration::from_std(parse_duration(value)?).ok()?;
+    Some(now + wait)
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+    if value.is_empty() {
+        return None;
+    }
+    if let Ok(secs) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let (number, tail) = rest.split_at(number_end);
+        let number: f64 = number.parse().ok()?;
+
+        let unit_end = tail.find(|c: char| c.is_ascii_digit()).unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+        total += number * match unit {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = tail;
+    }
+
+    Duration::try_from_secs_f64(total).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comrude_core::{ComrudeError, ProviderError};
+    use reqwest::header::HeaderValue;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_parse_openai_durations() {
+        assert_eq!(parse_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_duration("1h2m3s"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("soon"), None);
+    }
+
+    #[test]
+    fn test_tracker_reads_reset_headers() {
+        let tracker = RateLimitTracker::default();
+        let reset = (Utc::now() + chrono::Duration::seconds(90)).to_rfc3339();
+
+        // Plenty left: nothing to wait for
+        tracker.record(StatusCode::OK, &headers(&[
+            ("anthropic-ratelimit-requests-remaining", "12"),
+            ("anthropic-ratelimit-requests-reset", &reset),
+        ]));
+        assert_eq!(tracker.reset_at(), None);
+
+        tracker.record(StatusCode::OK, &headers(&[
+            ("anthropic-ratelimit-tokens-remaining", "0"),
+            ("anthropic-ratelimit-tokens-reset", &reset),
+        ]));
+        assert!(tracker.reset_at().is_some());
+
+        // Retry-After wins over the per-limit headers on a 429
+        tracker.record(StatusCode::TOO_MANY_REQUESTS, &headers(&[
+            ("retry-after", "5"),
+            ("x-ratelimit-reset-requests", "6m0s"),
+        ]));
+        let wait = tracker.reset_at().unwrap() - Utc::now();
+        assert!(wait <= chrono::Duration::seconds(5) && wait > chrono::Duration::seconds(3));
+    }
+
+    #[tokio::test]
+    async fn test_run_retries_only_transient_failures() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+
+        let (calls, mut retries) = (AtomicU32::new(0), 0);
+        let result = policy.run(|| None, || retries += 1, || async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(ComrudeError::Provider(ProviderError::RateLimited("test".to_string())))
+            } else {
+                Ok("answer")
+            }
+        }).await;
+        assert_eq!(result.unwrap(), "answer");
+        assert_eq!((calls.load(Ordering::SeqCst), retries), (3, 2));
+
+        // The last failure is returned, not retried
+        let (calls, mut retries) = (AtomicU32::new(0), 0);
+        let result: Result<()> = policy.run(|| None, || retries += 1, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(ComrudeError::Provider(ProviderError::RateLimited("test".to_string())))
+        }).await;
+        assert!(result.is_err());
+        assert_eq!((calls.load(Ordering::SeqCst), retries), (3, 2));
+
+        let calls = AtomicU32::new(0);
+        let result: Result<()> = policy.run(|| None, || {}, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(ComrudeError::Provider(ProviderError::AuthFailed("test".to_string())))
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
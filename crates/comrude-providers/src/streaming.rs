@@ -0,0 +1,249 @@
+//! Decoding helpers for streamed HTTP response bodies
+//!
+//! Providers stream their output either as server-sent events or as
+//! newline-delimited records. Both arrive as arbitrary byte chunks, so
+//! partial lines are buffered here until they are complete.
+
+use comrude_core::{ComrudeError, Result};
+use futures::{stream, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+
+/// Splits incoming byte chunks into complete lines
+#[derive(Debug, Default)]
+pub(crate) struct LineBuffer {
+    buffer: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Append a chunk and return every line it completed, without line terminators
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut lines = Vec::new();
+        while let Some(position) = self.buffer.iter().position(|&b| b == b'\n') {
+            let mut line: Vec<u8> = self.buffer.drain(..=position).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            lines.push(String::from_utf8_lossy(&line).into_owned());
+        }
+
+        lines
+    }
+
+    /// Return whatever is left once the body has ended
+    pub(crate) fn finish(&mut self) -> Option<String> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let rest = std::mem::take(&mut self.buffer);
+        Some(String::from_utf8_lossy(&rest).trim_end_matches('\r').to_string())
+    }
+}
+
+/// Common interface of the incremental decoders so the stream plumbing is shared
+trait Decoder {
+    type Item;
+
+    fn push(&mut self, bytes: &[u8]) -> Vec<Self::Item>;
+    fn finish(&mut self) -> Option<Self::Item>;
+}
+
+/// A single server-sent event
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Incremental parser for the `text/event-stream` format
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    lines: LineBuffer,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl Decoder for SseDecoder {
+    type Item = SseEvent;
+
+    fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.lines
+            .push(bytes)
+            .into_iter()
+            .filter_map(|line| self.process_line(&line))
+            .collect()
+    }
+
+    fn finish(&mut self) -> Option<SseEvent> {
+        if let Some(line) = self.lines.finish() {
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+}
+
+impl SseDecoder {
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        // A blank line terminates the current event
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // Lines starting with a colon are comments (often used as keep-alives)
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {} // id and retry are not used by any provider
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+
+        Some(SseEvent {
+            event,
+            data: std::mem::take(&mut self.data).join("\n"),
+        })
+    }
+}
+
+/// Incremental splitter for newline-delimited JSON, skipping blank lines
+#[derive(Debug, Default)]
+pub(crate) struct NdjsonDecoder {
+    lines: LineBuffer,
+}
+
+impl Decoder for NdjsonDecoder {
+    type Item = String;
+
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.lines
+            .push(bytes)
+            .into_iter()
+            .filter(|line| !line.trim().is_empty())
+            .collect()
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        self.lines.finish().filter(|line| !line.trim().is_empty())
+    }
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = reqwest::Result<Vec<u8>>> + Send>>;
+
+struct DecodeState<D: Decoder> {
+    body: ByteStream,
+    decoder: D,
+    pending: VecDeque<Result<D::Item>>,
+    finished: bool,
+}
+
+fn decode<S, B, D>(body: S, decoder: D) -> impl Stream<Item = Result<D::Item>> + Send
+where
+    S: Stream<Item = reqwest::Result<B>> + Send + 'static,
+    B: AsRef<[u8]>,
+    D: Decoder + Send + 'static,
+    D::Item: Send + 'static,
+{
+    let state = DecodeState {
+        body: Box::pin(body.map(|chunk| chunk.map(|bytes| bytes.as_ref().to_vec()))),
+        decoder,
+        pending: VecDeque::new(),
+        finished: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+            if state.finished {
+                return None;
+            }
+
+            match state.body.next().await {
+                Some(Ok(bytes)) => {
+                    let items = state.decoder.push(&bytes);
+                    state.pending.extend(items.into_iter().map(Ok));
+                }
+                Some(Err(e)) => {
+                    state.finished = true;
+                    state.pending.push_back(Err(ComrudeError::Network(e)));
+                }
+                None => {
+                    state.finished = true;
+                    state.pending.extend(state.decoder.finish().map(Ok));
+                }
+            }
+        }
+    })
+}
+
+/// Turn a streamed response body into a stream of server-sent events
+pub(crate) fn sse_events<S, B>(body: S) -> impl Stream<Item = Result<SseEvent>> + Send
+where
+    S: Stream<Item = reqwest::Result<B>> + Send + 'static,
+    B: AsRef<[u8]>,
+{
+    decode(body, SseDecoder::default())
+}
+
+/// Turn a streamed response body into a stream of newline-delimited records
+pub(crate) fn ndjson_lines<S, B>(body: S) -> impl Stream<Item = Result<String>> + Send
+where
+    S: Stream<Item = reqwest::Result<B>> + Send + 'static,
+    B: AsRef<[u8]>,
+{
+    decode(body, NdjsonDecoder::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_decoder_handles_split_chunks() {
+        let mut decoder = SseDecoder::default();
+
+        let mut events = decoder.push(b"event: ping\ndata: {\"type\":");
+        assert!(events.is_empty());
+
+        events.extend(decoder.push(b"\"ping\"}\r\n\r\n: keep-alive\n\ndata: a\ndata: b\n"));
+        events.extend(decoder.finish());
+
+        assert_eq!(events, vec![
+            SseEvent { event: Some("ping".to_string()), data: "{\"type\":\"ping\"}".to_string() },
+            SseEvent { event: None, data: "a\nb".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_line_buffer_keeps_multibyte_characters_intact() {
+        let mut lines = LineBuffer::default();
+        let text = "olá\n".as_bytes();
+
+        assert!(lines.push(&text[..3]).is_empty());
+        assert_eq!(lines.push(&text[3..]), vec!["olá".to_string()]);
+        assert_eq!(lines.finish(), None);
+    }
+}
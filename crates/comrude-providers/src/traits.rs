@@ -1,8 +1,9 @@
 use async_trait::async_trait;
 use comrude_core::{
     GenerationRequest, GenerationResponse, StreamChunk, ProviderCapabilities, 
-    ModelInfo, HealthStatus, Result
+    ModelInfo, HealthStatus, Result, CostPer1k, TokenUsage
 };
+use chrono::{DateTime, Utc};
 use futures::Stream;
 use std::pin::Pin;
 
@@ -26,6 +27,7 @@ pub trait LLMProvider: Send + Sync + std::fmt::Debug {
             comrude_core::ProviderError::ApiError {
                 provider: self.name().to_string(),
                 message: "Embeddings not supported by this provider".to_string(),
+                status: None,
             }
         ))
     }
@@ -33,4 +35,16 @@ pub trait LLMProvider: Send + Sync + std::fmt::Debug {
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         Ok(self.supported_models())
     }
+
+    /// Price of a request using the pricing listed in `supported_models`
+    fn estimate_cost(&self, model: &str, usage: &TokenUsage) -> f64 {
+        let models = self.supported_models();
+        CostPer1k::lookup(models.iter().map(|m| (&m.id, &m.cost_per_1k_tokens)), model)
+            .map_or(0.0, |price| price.cost_of(usage))
+    }
+
+    /// When the provider last said its rate limit resets, if that is still ahead
+    fn rate_limit_reset(&self) -> Option<DateTime<Utc>> {
+        None
+    }
 }
\ No newline at end of file
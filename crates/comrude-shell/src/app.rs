@@ -140,6 +140,7 @@ impl AppState {
             stream: false,
             tools: Vec::new(),
             context: Vec::new(),
+            tool_exchanges: Vec::new(),
             metadata: std::collections::HashMap::new(),
         };
 
@@ -1,9 +1,9 @@
 use crate::{AppState, ConversationEntry, InputMode};
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Margin},
+    layout::{Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
-    text::{Line, Span, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph},
     Frame,
 };
 use comrude_core::{MessageSender, MessageContent};
@@ -48,7 +48,7 @@ fn draw_conversation(frame: &mut Frame, area: ratatui::layout::Rect, app: &AppSt
         }
     };
 
-    draw_conversation_items(frame, area, &*conversation);
+    draw_conversation_items(frame, area, &conversation);
 }
 
 fn draw_conversation_items(
@@ -177,9 +177,7 @@ fn draw_input(frame: &mut Frame, area: ratatui::layout::Rect, app: &AppState) {
 
 fn draw_status(frame: &mut Frame, area: ratatui::layout::Rect, app: &AppState) {
     let status_text = app
-        .status_message
-        .as_ref()
-        .map(|msg| msg.as_str())
+        .status_message.as_deref()
         .unwrap_or("Ready");
 
     let status = Paragraph::new(status_text)
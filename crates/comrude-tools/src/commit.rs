@@ -0,0 +1,153 @@
+//! Drafting commit messages and pull request descriptions from diffs
+//!
+//! The model is shown the repository's recent subjects and asked to write
+//! in the same style, so projects using conventional commits get them and
+//! the rest don't.
+
+use comrude_core::GenerationRequest;
+use regex::Regex;
+
+/// Recent commits looked at to pick a style
+pub const STYLE_SAMPLE_SIZE: usize = 20;
+
+/// How a repository writes commit subjects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitStyle {
+    /// `type(scope): summary`, as in Conventional Commits
+    Conventional,
+    /// Free-form subjects
+    Plain,
+}
+
+impl CommitStyle {
+    /// Conventional when at least half of the subjects follow the format
+    pub fn detect(subjects: &[String]) -> Self {
+        let conventional = Regex::new(r"^[a-z]+(\([^)]*\))?!?: \S").expect("valid regex");
+        let matching = subjects.iter().filter(|subject| conventional.is_match(subject)).count();
+        if !subjects.is_empty() && matching * 2 >= subjects.len() {
+            CommitStyle::Conventional
+        } else {
+            CommitStyle::Plain
+        }
+    }
+
+    fn instructions(&self) -> &'static str {
+        match self {
+            CommitStyle::Conventional => "Use the Conventional Commits format: `type(scope): summary`, \
+                with a type such as feat, fix, refactor, docs, test or chore.",
+            CommitStyle::Plain => "Write the subject as a short imperative sentence, without a type prefix.",
+        }
+    }
+}
+
+/// Cut a diff to `max_bytes` at a line boundary, saying so at the end
+fn clip_diff(diff: &str, max_bytes: usize) -> String {
+    if diff.len() <= max_bytes {
+        return diff.to_string();
+    }
+    let mut end = max_bytes;
+    while !diff.is_char_boundary(end) {
+        end -= 1;
+    }
+    let cut = diff[..end].rfind('\n').map_or(0, |i| i + 1);
+    format!("{}[... diff truncated, {} of {} bytes shown ...]\n", &diff[..cut], cut, diff.len())
+}
+
+fn recent_subjects_block(subjects: &[String]) -> String {
+    if subjects.is_empty() {
+        return "(no earlier commits)".to_string();
+    }
+    subjects.iter().map(|subject| format!("- {}", subject)).collect::<Vec<_>>().join("\n")
+}
+
+/// A request for a commit message describing `diff`
+pub fn commit_message_request(diff: &str, stat: &str, recent_subjects: &[String], max_diff_bytes: usize) -> GenerationRequest {
+    let style = CommitStyle::detect(recent_subjects);
+    let system_prompt = format!(
+        "You write git commit messages. Reply with the message only: a subject line of at most \
+         72 characters, then a blank line and a short body explaining what changed and why, \
+         wrapped at 72 characters. Leave the body out for trivial changes. {} \
+         Match the tone of the repository's recent subjects.",
+        style.instructions()
+    );
+    let prompt = format!(
+        "Recent commit subjects:\n{}\n\nFiles changed:\n{}\nStaged diff:\n{}",
+        recent_subjects_block(recent_subjects),
+        stat,
+        clip_diff(diff, max_diff_bytes)
+    );
+
+    GenerationRequest {
+        prompt,
+        system_prompt: Some(system_prompt),
+        max_tokens: Some(500),
+        temperature: Some(0.2),
+        ..GenerationRequest::default()
+    }
+}
+
+/// A request for a pull request title and description for a branch
+pub fn pull_request_request(diff: &str, stat: &str, commits: &[String], base: &str, max_diff_bytes: usize) -> GenerationRequest {
+    let system_prompt = "You write pull request descriptions. Reply in Markdown: a title line \
+        starting with `# `, then one or two sentences on what the change does and why, then a \
+        short list of the notable changes. Do not invent testing that the diff does not show."
+        .to_string();
+    let prompt = format!(
+        "Commits since {}:\n{}\n\nFiles changed:\n{}\nDiff:\n{}",
+        base,
+        recent_subjects_block(commits),
+        stat,
+        clip_diff(diff, max_diff_bytes)
+    );
+
+    GenerationRequest {
+        prompt,
+        system_prompt: Some(system_prompt),
+        max_tokens: Some(1000),
+        temperature: Some(0.2),
+        ..GenerationRequest::default()
+    }
+}
+
+/// Strip what models tend to wrap a message in: code fences and blank edges
+pub fn clean_message(response: &str) -> String {
+    let trimmed = response.trim();
+    let unfenced = match trimmed.strip_prefix("```") {
+        Some(rest) => {
+            // Drop the info string of the opening fence, if any
+            let body = rest.split_once('\n').map_or("", |(_, body)| body);
+            body.strip_suffix("```").unwrap_or(body)
+        }
+        None => trimmed,
+    };
+    format!("{}\n", unfenced.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subjects(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_detect_style_and_build_requests() {
+        let conventional = subjects(&["feat(cli): add commit", "fix: handle empty diff", "Update README"]);
+        assert_eq!(CommitStyle::detect(&conventional), CommitStyle::Conventional);
+        assert_eq!(CommitStyle::detect(&subjects(&["Add commit command", "fix: typo", "Tidy up"])), CommitStyle::Plain);
+        assert_eq!(CommitStyle::detect(&[]), CommitStyle::Plain);
+
+        let diff = "diff --git a/a b/a\n+line one\n+line two\n";
+        let request = commit_message_request(diff, " a | 2 ++\n", &conventional, 30);
+        assert!(request.system_prompt.unwrap().contains("Conventional Commits"));
+        assert!(request.prompt.contains("- fix: handle empty diff"));
+        assert!(request.prompt.ends_with("diff --git a/a b/a\n+line one\n[... diff truncated, 29 of 39 bytes shown ...]\n"));
+    }
+
+    #[test]
+    fn test_clean_message_strips_fences() {
+        assert_eq!(clean_message("```text\nAdd x\n\nBody\n```\n"), "Add x\n\nBody\n");
+        assert_eq!(clean_message("\n  Add x  \n"), "Add x\n");
+    }
+}
@@ -0,0 +1,485 @@
+//! Targeted file edits from a unified diff or exact search/replace blocks
+//!
+//! Every hunk is checked against the file as it is now. The file is only
+//! written when all of them apply, so a failed edit never leaves it half
+//! changed.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use comrude_core::{unified_diff, ToolResult, DEFAULT_CONTEXT_LINES};
+use serde::{Deserialize, Serialize};
+use crate::tool::{parse_arguments, Tool};
+use crate::workspace::{Workspace, WorkspaceError};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchReplace {
+    pub search: String,
+    pub replace: String,
+}
+
+#[derive(Deserialize)]
+struct EditArguments {
+    path: String,
+    #[serde(default)]
+    diff: Option<String>,
+    #[serde(default)]
+    edits: Vec<SearchReplace>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Whether one hunk or search/replace block applied
+#[derive(Debug, Clone, Serialize)]
+pub struct HunkOutcome {
+    pub index: usize,
+    pub applied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl HunkOutcome {
+    fn applied(index: usize) -> Self {
+        Self { index, applied: true, reason: None }
+    }
+
+    fn failed(index: usize, reason: impl Into<String>) -> Self {
+        Self { index, applied: false, reason: Some(reason.into()) }
+    }
+}
+
+/// What the edit tool reports back to the model
+#[derive(Debug, Clone, Serialize)]
+pub struct EditReport {
+    pub path: String,
+    /// Whether the file was changed on disk
+    pub written: bool,
+    pub dry_run: bool,
+    pub hunks: Vec<HunkOutcome>,
+    /// Unified diff of the edit, or of the hunks that did apply when some failed
+    pub diff: String,
+}
+
+/// Apply search/replace blocks in order; each search text must occur
+/// exactly once in the content left by the blocks before it
+pub fn apply_search_replace(content: &str, edits: &[SearchReplace]) -> (String, Vec<HunkOutcome>) {
+    let mut content = content.to_string();
+    let mut outcomes = Vec::with_capacity(edits.len());
+
+    for (index, edit) in edits.iter().enumerate() {
+        if edit.search.is_empty() {
+            outcomes.push(HunkOutcome::failed(index, "search text is empty"));
+            continue;
+        }
+
+        match content.matches(edit.search.as_str()).count() {
+            0 => outcomes.push(HunkOutcome::failed(index, "search text not found")),
+            1 => {
+                content = content.replacen(edit.search.as_str(), &edit.replace, 1);
+                outcomes.push(HunkOutcome::applied(index));
+            }
+            n => outcomes.push(HunkOutcome::failed(
+                index,
+                format!("search text matches {} places; include more surrounding lines", n),
+            )),
+        }
+    }
+
+    (content, outcomes)
+}
+
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+struct Hunk {
+    old_start: usize,
+    /// Line counts from the header
+    old_count: usize,
+    new_count: usize,
+    lines: Vec<HunkLine>,
+}
+
+impl Hunk {
+    /// The lines the hunk expects in the file
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines.iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Remove(text) => Some(text.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect()
+    }
+
+    /// Whether the hunk holds as many lines as its header announced
+    fn is_complete(&self) -> bool {
+        let added = self.lines.iter().filter(|line| matches!(line, HunkLine::Add(_))).count();
+        self.old_lines().len() >= self.old_count && added >= self.new_count
+    }
+}
+
+/// "@@ -12,5 +12,6 @@ optional section heading" gives (12, 5, 6); a count
+/// left out is 1
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let mut ranges = line.strip_prefix("@@ -")?.split_whitespace();
+    let old = ranges.next()?;
+    let new = ranges.next()?.strip_prefix('+')?;
+    let range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let ((old_start, old_count), (_, new_count)) = (range(old)?, range(new)?);
+    Some((old_start, old_count, new_count))
+}
+
+fn is_file_header(line: &str) -> bool {
+    ["diff ", "index ", "--- ", "+++ "].iter().any(|prefix| line.starts_with(prefix))
+}
+
+/// Whether the file headers starting at `lines[0]` lead straight into a hunk
+fn starts_file_header(lines: &[&str]) -> bool {
+    let headers = lines.iter().take_while(|line| is_file_header(line)).count();
+    headers > 0 && lines.get(headers).is_some_and(|line| line.starts_with("@@"))
+}
+
+fn parse_unified_diff(diff: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let lines: Vec<&str> = diff.lines().collect();
+
+    for (i, &line) in lines.iter().enumerate() {
+        if line.starts_with("@@") {
+            let (old_start, old_count, new_count) = parse_hunk_header(line)
+                .ok_or_else(|| format!("malformed hunk header: {}", line))?;
+            hunks.push(Hunk { old_start, old_count, new_count, lines: Vec::new() });
+            continue;
+        }
+
+        // Anything before the first hunk is file headers or commentary
+        let Some(hunk) = hunks.last_mut() else { continue };
+
+        // "--- " and "+++ " are also what removing "-- x" or adding "++ x"
+        // looks like, so they only count as file headers once the hunk has
+        // all its lines or when they lead into the next hunk
+        let complete = hunk.is_complete();
+        if is_file_header(line) && (complete || starts_file_header(&lines[i..])) {
+            continue;
+        }
+
+        match line.chars().next() {
+            Some('+') => hunk.lines.push(HunkLine::Add(line[1..].to_string())),
+            Some('-') => hunk.lines.push(HunkLine::Remove(line[1..].to_string())),
+            Some(' ') => hunk.lines.push(HunkLine::Context(line[1..].to_string())),
+            // "\ No newline at end of file"
+            Some('\\') => {}
+            // Blank lines past the end of a hunk are trailing whitespace
+            None if complete => {}
+            // Models often drop the leading space on blank context lines
+            None => hunk.lines.push(HunkLine::Context(String::new())),
+            Some(_) => return Err(format!("unexpected line in hunk: {}", line)),
+        }
+    }
+
+    if hunks.is_empty() {
+        return Err("diff contains no hunks".to_string());
+    }
+    Ok(hunks)
+}
+
+/// A line of the file without its line ending
+fn line_text(line: &str) -> &str {
+    line.strip_suffix('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).unwrap_or(line)
+}
+
+/// Apply the hunks of a unified diff
+///
+/// A hunk whose context no longer sits at the line it names is applied at
+/// the nearest place it matches, like `patch` does with an offset. Lines
+/// the hunk keeps are left as they were, line endings included, and added
+/// lines take the file's line ending.
+pub fn apply_unified_diff(content: &str, diff: &str) -> Result<(String, Vec<HunkOutcome>), String> {
+    let hunks = parse_unified_diff(diff)?;
+    let mut lines: Vec<String> = content.split_inclusive('\n').map(String::from).collect();
+    let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut outcomes = Vec::with_capacity(hunks.len());
+    // Lines added minus lines removed by the hunks applied so far
+    let mut shift: isize = 0;
+    // Hunks apply in order and never overlap an earlier one
+    let mut earliest = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let old_lines = hunk.old_lines();
+        // A hunk that only inserts names the line it follows
+        let named = if old_lines.is_empty() { hunk.old_start } else { hunk.old_start.saturating_sub(1) };
+        let expected = (named as isize + shift).max(0) as usize;
+
+        let fits = |at: usize| {
+            at + old_lines.len() <= lines.len()
+                && lines[at..at + old_lines.len()].iter().map(|line| line_text(line)).eq(old_lines.iter().copied())
+        };
+        let last_start = lines.len().saturating_sub(old_lines.len());
+        let position = if expected >= earliest && expected <= lines.len() && fits(expected) {
+            Some(expected)
+        } else {
+            (earliest..=last_start)
+                .filter(|&at| fits(at))
+                .min_by_key(|&at| at.abs_diff(expected))
+        };
+
+        let Some(at) = position else {
+            outcomes.push(HunkOutcome::failed(
+                index,
+                format!("context does not match the file near line {}", expected + 1),
+            ));
+            continue;
+        };
+
+        let mut replaced = lines[at..at + old_lines.len()].iter();
+        let mut new_lines: Vec<String> = Vec::with_capacity(hunk.lines.len());
+        for line in &hunk.lines {
+            let next = match line {
+                HunkLine::Context(_) => replaced.next().cloned(),
+                HunkLine::Remove(_) => {
+                    replaced.next();
+                    None
+                }
+                HunkLine::Add(text) => Some(format!("{}{}", text, newline)),
+            };
+            if let Some(next) = next {
+                // A line that was last in the file may now be followed by another
+                if let Some(previous) = new_lines.last_mut().filter(|previous| !previous.ends_with('\n')) {
+                    previous.push_str(newline);
+                }
+                new_lines.push(next);
+            }
+        }
+        let new_count = new_lines.len();
+        lines.splice(at..at + old_lines.len(), new_lines);
+        shift += new_count as isize - old_lines.len() as isize;
+        earliest = at + new_count;
+        outcomes.push(HunkOutcome::applied(index));
+    }
+
+    let mut new_content = lines.concat();
+    // The file keeps ending with a newline or without one, as it did
+    if !content.is_empty() && !content.ends_with('\n') {
+        let trimmed = line_text(&new_content).len();
+        new_content.truncate(trimmed);
+    }
+    Ok((new_content, outcomes))
+}
+
+/// Edit part of a file without rewriting all of it
+#[derive(Debug)]
+pub struct EditFileTool {
+    pub workspace: Arc<Workspace>,
+}
+
+impl EditFileTool {
+    fn edit(workspace: &Workspace, arguments: EditArguments) -> Result<EditReport, String> {
+        let original = workspace.read_untruncated(&arguments.path)
+            .map_err(|e| format!("failed to read {}: {}", arguments.path, e))?;
+
+        let (edited, hunks) = match (&arguments.diff, arguments.edits.is_empty()) {
+            (Some(diff), true) => apply_unified_diff(&original, diff)?,
+            (None, false) => apply_search_replace(&original, &arguments.edits),
+            (Some(_), false) => return Err("provide either `diff` or `edits`, not both".to_string()),
+            (None, true) => return Err("provide a `diff` or a non-empty `edits` list".to_string()),
+        };
+
+        let all_applied = hunks.iter().all(|hunk| hunk.applied);
+        let written = all_applied && !arguments.dry_run && edited != original;
+        if written {
+            workspace.write_if_unchanged(&arguments.path, &original, &edited)
+                .map_err(|e| match e {
+                    WorkspaceError::Changed(_) => format!(
+                        "{} changed while it was being edited; nothing was written. Read it again and retry",
+                        arguments.path
+                    ),
+                    e => format!("failed to write {}: {}", arguments.path, e),
+                })?;
+        }
+
+        Ok(EditReport {
+            diff: unified_diff(&original, &edited, &arguments.path, DEFAULT_CONTEXT_LINES),
+            path: arguments.path,
+            written,
+            dry_run: arguments.dry_run,
+            hunks,
+        })
+    }
+}
+
+#[async_trait]
+impl Tool for EditFileTool {
+    fn name(&self) -> &str {
+        "edit_file"
+    }
+
+    fn description(&self) -> &str {
+        "Change part of a file with a unified diff or exact search/replace blocks. \
+         The file is only written if every hunk applies; failed hunks are reported \
+         with a reason. Set dry_run to preview the resulting diff."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string", "description": "Path of the file to edit"},
+                "diff": {"type": "string", "description": "Unified diff to apply to the file"},
+                "edits": {
+                    "type": "array",
+                    "description": "Search/replace blocks applied in order; each search text must match exactly once",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "search": {"type": "string"},
+                            "replace": {"type": "string"}
+                        },
+                        "required": ["search", "replace"]
+                    }
+                },
+                "dry_run": {"type": "boolean", "description": "Report the diff without writing the file"}
+            },
+            "required": ["path"]
+        })
+    }
+
+    fn requires_confirmation(&self, arguments: &serde_json::Value) -> bool {
+        !arguments["dry_run"].as_bool().unwrap_or(false)
+    }
+
+    async fn invoke(&self, arguments: serde_json::Value) -> ToolResult {
+        let arguments: EditArguments = match parse_arguments(self.name(), arguments) {
+            Ok(arguments) => arguments,
+            Err(result) => return result,
+        };
+
+        let workspace = self.workspace.clone();
+        let edited = tokio::task::spawn_blocking(move || Self::edit(&workspace, arguments)).await
+            .unwrap_or_else(|e| Err(format!("edit failed: {}", e)));
+        match edited {
+            Ok(report) => {
+                let failed = report.hunks.iter().any(|hunk| !hunk.applied);
+                let content = serde_json::to_string_pretty(&report).unwrap_or_default();
+                if failed { ToolResult::error(content) } else { ToolResult::success(content) }
+            }
+            Err(message) => ToolResult::error(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n";
+
+    #[test]
+    fn test_apply_unified_diff_with_offset_and_failures() {
+        // Header line numbers are off by two, as if lines were added above
+        let diff = "\
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -3,2 +3,2 @@
+     let x = 1;
+-    println!(\"{}\", x);
++    println!(\"x = {}\", x);
+@@ -10,1 +10,1 @@
+-    missing();
++    found();
+";
+        let (content, outcomes) = apply_unified_diff(FILE, diff).unwrap();
+
+        assert!(outcomes[0].applied);
+        assert!(!outcomes[1].applied);
+        assert!(outcomes[1].reason.as_deref().unwrap().contains("does not match"));
+        assert_eq!(content, FILE.replace("\"{}\"", "\"x = {}\""));
+        assert!(apply_unified_diff(FILE, "not a diff").is_err());
+    }
+
+    #[test]
+    fn test_apply_unified_diff_reads_header_like_lines_by_count_and_keeps_crlf() {
+        let sql = "-- users\r\nselect 1;\r\n++ counter\r\nselect 2;\r\n";
+        // The first hunk removes "-- users" and adds "++ total"; the second
+        // hunk follows file headers of its own
+        let diff = "\
+--- a/query.sql
++++ b/query.sql
+@@ -1,2 +1,2 @@
+--- users
++++ total
+ select 1;
+diff --git a/query.sql b/query.sql
+--- a/query.sql
++++ b/query.sql
+@@ -3,2 +3,2 @@
+ ++ counter
+-select 2;
++select 3;
+";
+        let (content, outcomes) = apply_unified_diff(sql, diff).unwrap();
+
+        assert!(outcomes.iter().all(|outcome| outcome.applied));
+        assert_eq!(content, "++ total\r\nselect 1;\r\n++ counter\r\nselect 3;\r\n");
+
+        // A file without a final newline keeps lacking one
+        let (content, _) = apply_unified_diff("a\nb", "@@ -2,1 +2,2 @@\n b\n+c\n").unwrap();
+        assert_eq!(content, "a\nb\nc");
+    }
+
+    #[test]
+    fn test_search_replace_requires_unique_matches() {
+        let edits = [
+            SearchReplace { search: "let x = 1;".to_string(), replace: "let x = 2;".to_string() },
+            SearchReplace { search: "x".to_string(), replace: "y".to_string() },
+            SearchReplace { search: "nowhere".to_string(), replace: String::new() },
+        ];
+        let (content, outcomes) = apply_search_replace(FILE, &edits);
+
+        assert_eq!(content, FILE.replace("let x = 1;", "let x = 2;"));
+        assert!(outcomes[0].applied);
+        assert!(outcomes[1].reason.as_deref().unwrap().contains("matches 2 places"));
+        assert_eq!(outcomes[2].reason.as_deref(), Some("search text not found"));
+    }
+
+    #[tokio::test]
+    async fn test_edit_writes_only_when_every_hunk_applies() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Arc::new(Workspace::new(dir.path(), &comrude_core::Config::default().files).unwrap());
+        std::fs::write(dir.path().join("main.rs"), FILE).unwrap();
+        let tool = EditFileTool { workspace };
+
+        let preview = tool.invoke(serde_json::json!({
+            "path": "main.rs",
+            "edits": [{"search": "let x = 1;", "replace": "let x = 2;"}],
+            "dry_run": true,
+        })).await;
+        assert!(!preview.is_error);
+        assert!(preview.content.contains("+    let x = 2;"));
+        assert_eq!(std::fs::read_to_string(dir.path().join("main.rs")).unwrap(), FILE);
+
+        let partial = tool.invoke(serde_json::json!({
+            "path": "main.rs",
+            "edits": [
+                {"search": "let x = 1;", "replace": "let x = 2;"},
+                {"search": "nowhere", "replace": ""}
+            ],
+        })).await;
+        assert!(partial.is_error);
+        assert_eq!(std::fs::read_to_string(dir.path().join("main.rs")).unwrap(), FILE);
+
+        let applied = tool.invoke(serde_json::json!({
+            "path": "main.rs",
+            "edits": [{"search": "let x = 1;", "replace": "let x = 2;"}],
+        })).await;
+        assert!(!applied.is_error);
+        assert!(std::fs::read_to_string(dir.path().join("main.rs")).unwrap().contains("let x = 2;"));
+
+        let empty = tool.invoke(serde_json::json!({"path": "main.rs"})).await;
+        assert!(empty.is_error && empty.content.contains("non-empty `edits`"));
+    }
+}
@@ -1,33 +1,170 @@
 //! File operation tools
 //! 
 //! Tools for reading, writing, and manipulating files that can be
-//! exposed to LLM providers.
+//! exposed to LLM providers. Every path is resolved inside a `Workspace`,
+//! and the filesystem work runs on the blocking thread pool.
 
-use std::path::Path;
-use std::fs;
+use std::sync::Arc;
 use anyhow::Result;
+use async_trait::async_trait;
+use comrude_core::ToolResult;
+use serde::Deserialize;
+use crate::tool::{parse_arguments, Tool};
+use crate::workspace::Workspace;
 
 /// Read the contents of a file
-pub async fn read_file<P: AsRef<Path>>(path: P) -> Result<String> {
-    let content = fs::read_to_string(path)?;
+pub async fn read_file(workspace: &Workspace, path: &str) -> Result<String> {
+    let (workspace, path) = (workspace.clone(), path.to_string());
+    let content = tokio::task::spawn_blocking(move || workspace.read_to_string(&path)).await??;
     Ok(content)
 }
 
 /// Write content to a file
-pub async fn write_file<P: AsRef<Path>>(path: P, content: &str) -> Result<()> {
-    fs::write(path, content)?;
+pub async fn write_file(workspace: &Workspace, path: &str, content: &str) -> Result<()> {
+    let (workspace, path, content) = (workspace.clone(), path.to_string(), content.to_string());
+    tokio::task::spawn_blocking(move || workspace.write(&path, &content)).await??;
     Ok(())
 }
 
 /// List files in a directory
-pub async fn list_directory<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
-    let entries = fs::read_dir(path)?;
-    let mut files = Vec::new();
-    
-    for entry in entries {
-        let entry = entry?;
-        files.push(entry.file_name().to_string_lossy().to_string());
-    }
-    
+pub async fn list_directory(workspace: &Workspace, path: &str) -> Result<Vec<String>> {
+    let (workspace, path) = (workspace.clone(), path.to_string());
+    let files = tokio::task::spawn_blocking(move || workspace.list(&path)).await??;
     Ok(files)
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+struct PathArguments {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct WriteArguments {
+    path: String,
+    content: String,
+}
+
+fn path_schema(description: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "path": {"type": "string", "description": description}
+        },
+        "required": ["path"]
+    })
+}
+
+/// `read_file` exposed as a tool
+#[derive(Debug)]
+pub struct ReadFileTool {
+    pub workspace: Arc<Workspace>,
+}
+
+#[async_trait]
+impl Tool for ReadFileTool {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn description(&self) -> &str {
+        "Read the contents of a text file"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        path_schema("Path of the file to read")
+    }
+
+    async fn invoke(&self, arguments: serde_json::Value) -> ToolResult {
+        let arguments: PathArguments = match parse_arguments(self.name(), arguments) {
+            Ok(arguments) => arguments,
+            Err(result) => return result,
+        };
+
+        match read_file(&self.workspace, &arguments.path).await {
+            Ok(content) => ToolResult::success(content),
+            Err(e) => ToolResult::error(format!("failed to read {}: {}", arguments.path, e)),
+        }
+    }
+}
+
+/// `write_file` exposed as a tool
+#[derive(Debug)]
+pub struct WriteFileTool {
+    pub workspace: Arc<Workspace>,
+}
+
+#[async_trait]
+impl Tool for WriteFileTool {
+    fn name(&self) -> &str {
+        "write_file"
+    }
+
+    fn description(&self) -> &str {
+        "Create or overwrite a file with the given content"
+    }
+
+    fn requires_confirmation(&self, _arguments: &serde_json::Value) -> bool {
+        true
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string", "description": "Path of the file to write"},
+                "content": {"type": "string", "description": "Full new content of the file"}
+            },
+            "required": ["path", "content"]
+        })
+    }
+
+    async fn invoke(&self, arguments: serde_json::Value) -> ToolResult {
+        let arguments: WriteArguments = match parse_arguments(self.name(), arguments) {
+            Ok(arguments) => arguments,
+            Err(result) => return result,
+        };
+
+        match write_file(&self.workspace, &arguments.path, &arguments.content).await {
+            Ok(()) => ToolResult::success(format!(
+                "wrote {} bytes to {}", arguments.content.len(), arguments.path
+            )),
+            Err(e) => ToolResult::error(format!("failed to write {}: {}", arguments.path, e)),
+        }
+    }
+}
+
+/// `list_directory` exposed as a tool
+#[derive(Debug)]
+pub struct ListDirectoryTool {
+    pub workspace: Arc<Workspace>,
+}
+
+#[async_trait]
+impl Tool for ListDirectoryTool {
+    fn name(&self) -> &str {
+        "list_directory"
+    }
+
+    fn description(&self) -> &str {
+        "List the names of the entries in a directory"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        path_schema("Path of the directory to list")
+    }
+
+    async fn invoke(&self, arguments: serde_json::Value) -> ToolResult {
+        let arguments: PathArguments = match parse_arguments(self.name(), arguments) {
+            Ok(arguments) => arguments,
+            Err(result) => return result,
+        };
+
+        match list_directory(&self.workspace, &arguments.path).await {
+            Ok(mut entries) => {
+                entries.sort();
+                ToolResult::success(entries.join("\n"))
+            }
+            Err(e) => ToolResult::error(format!("failed to list {}: {}", arguments.path, e)),
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Context gathered from git: diffs, recent history and blame
+//!
+//! Everything shells out to the `git` binary, so it works with whatever
+//! repository layout and configuration the user already has.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Context, Result};
+use comrude_core::{ContextItem, ContextType};
+use tokio::process::Command;
+
+/// Which changes a diff covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSource {
+    /// Changes not yet staged
+    WorkingTree,
+    /// Changes staged for the next commit
+    Staged,
+    /// Everything that differs from where the branch forked off `base`,
+    /// uncommitted changes included
+    Branch(String),
+}
+
+impl DiffSource {
+    fn describe(&self) -> String {
+        match self {
+            DiffSource::WorkingTree => "Unstaged changes".to_string(),
+            DiffSource::Staged => "Staged changes".to_string(),
+            DiffSource::Branch(base) => format!("Changes since {}", base),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            DiffSource::WorkingTree => "working_tree".to_string(),
+            DiffSource::Staged => "staged".to_string(),
+            DiffSource::Branch(base) => format!("branch:{}", base),
+        }
+    }
+}
+
+/// The changes to one file within a diff
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    pub path: String,
+    /// The `diff --git` section for this file, headers included
+    pub patch: String,
+    pub hunks: usize,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+/// Split `git diff` output into one entry per file
+pub fn parse_diff(output: &str) -> Vec<FileDiff> {
+    let mut files: Vec<FileDiff> = Vec::new();
+    // Past the first hunk header, `---` and `+++` are removed and added lines
+    let mut in_hunks = false;
+
+    for line in output.split_inclusive('\n') {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            // "a/old b/new"; a later "+++" line names the file more reliably
+            let path = header.trim_end().rsplit_once(" b/").map(|(_, path)| path).unwrap_or_default();
+            files.push(FileDiff {
+                path: path.to_string(),
+                patch: String::new(),
+                hunks: 0,
+                additions: 0,
+                deletions: 0,
+            });
+            in_hunks = false;
+        }
+        let Some(file) = files.last_mut() else { continue };
+        file.patch.push_str(line);
+
+        if line.starts_with("@@") {
+            file.hunks += 1;
+            in_hunks = true;
+        } else if !in_hunks {
+            if let Some(path) = line.strip_prefix("+++ b/") {
+                file.path = path.trim_end().to_string();
+            }
+        } else if line.starts_with('+') {
+            file.additions += 1;
+        } else if line.starts_with('-') {
+            file.deletions += 1;
+        }
+    }
+
+    files
+}
+
+/// A git work tree
+#[derive(Debug, Clone)]
+pub struct GitRepository {
+    root: PathBuf,
+}
+
+impl GitRepository {
+    /// The repository containing `path`
+    pub async fn discover(path: impl AsRef<Path>) -> Result<Self> {
+        let root = git(path.as_ref(), &["rev-parse", "--show-toplevel"]).await
+            .context("not inside a git repository")?;
+        Ok(Self { root: PathBuf::from(root.trim_end()) })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    async fn git(&self, args: &[&str]) -> Result<String> {
+        git(&self.root, args).await
+    }
+
+    async fn diff_args(&self, source: &DiffSource, extra: &str) -> Result<String> {
+        match source {
+            DiffSource::WorkingTree => self.git(&["diff", "--no-color", extra]).await,
+            DiffSource::Staged => self.git(&["diff", "--no-color", "--cached", extra]).await,
+            DiffSource::Branch(base) => {
+                check_revision(base)?;
+                let fork_point = self.git(&["merge-base", base, "HEAD"]).await
+                    .with_context(|| format!("cannot find where HEAD forked from {}", base))?;
+                self.git(&["diff", "--no-color", extra, fork_point.trim_end()]).await
+            }
+        }
+    }
+
+    /// The whole diff as `git diff` prints it
+    pub async fn patch(&self, source: &DiffSource) -> Result<String> {
+        self.diff_args(source, "--patch").await
+    }
+
+    /// Files changed and lines added or removed, as `git diff --stat` prints it
+    pub async fn diff_stat(&self, source: &DiffSource) -> Result<String> {
+        self.diff_args(source, "--stat").await
+    }
+
+    /// One context item per changed file
+    pub async fn diff(&self, source: &DiffSource) -> Result<Vec<ContextItem>> {
+        let output = self.patch(source).await?;
+
+        Ok(parse_diff(&output).into_iter().map(|file| {
+            let mut metadata = HashMap::new();
+            metadata.insert("path".to_string(), serde_json::json!(file.path));
+            metadata.insert("source".to_string(), serde_json::json!(source.label()));
+            metadata.insert("hunks".to_string(), serde_json::json!(file.hunks));
+            metadata.insert("additions".to_string(), serde_json::json!(file.additions));
+            metadata.insert("deletions".to_string(), serde_json::json!(file.deletions));
+
+            ContextItem {
+                item_type: ContextType::GitDiff,
+                content: format!("{} to {}:\n{}", source.describe(), file.path, file.patch),
+                metadata,
+            }
+        }).collect())
+    }
+
+    /// The last `count` commits, newest first
+    pub async fn recent_log(&self, count: usize) -> Result<ContextItem> {
+        let count = count.to_string();
+        let args = ["log", "--no-color", "-n", count.as_str(), "--format=%h %ad %an%n    %s", "--date=short"];
+        let output = self.git(&args).await?;
+
+        Ok(ContextItem {
+            item_type: ContextType::Command { command: format!("git {}", args.join(" ")) },
+            content: format!("Recent commits:\n{}", output),
+            metadata: HashMap::from([("commits".to_string(), serde_json::json!(count))]),
+        })
+    }
+
+    /// Subject lines of the last `count` commits, newest first
+    pub async fn recent_subjects(&self, count: usize) -> Result<Vec<String>> {
+        // A repository without commits has no history to show
+        if !self.has_commits().await? {
+            return Ok(Vec::new());
+        }
+        let count = count.to_string();
+        let output = self.git(&["log", "-n", count.as_str(), "--format=%s"]).await?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    /// Whether HEAD points at a commit yet
+    async fn has_commits(&self) -> Result<bool> {
+        let output = Command::new("git")
+            .args(["rev-parse", "--verify", "--quiet", "HEAD"])
+            .current_dir(&self.root)
+            .output()
+            .await
+            .context("failed to run git")?;
+
+        // --quiet fails silently for an unborn branch and loudly for anything else
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) if output.stderr.is_empty() => Ok(false),
+            _ => bail!("git rev-parse --verify HEAD failed: {}", String::from_utf8_lossy(&output.stderr).trim()),
+        }
+    }
+
+    /// Subject lines of the commits on HEAD that `base` does not have
+    pub async fn subjects_since(&self, base: &str) -> Result<Vec<String>> {
+        check_revision(base)?;
+        let range = format!("{}..HEAD", base);
+        let output = self.git(&["log", "--reverse", "--format=%s", &range]).await?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    /// The `.git` directory, which may live elsewhere for worktrees
+    pub async fn git_dir(&self) -> Result<PathBuf> {
+        let dir = self.git(&["rev-parse", "--absolute-git-dir"]).await?;
+        Ok(PathBuf::from(dir.trim_end()))
+    }
+
+    /// Commit what is staged with the message in `message_file`
+    pub async fn commit(&self, message_file: &Path) -> Result<String> {
+        let message_file = message_file.to_string_lossy();
+        self.git(&["commit", "--cleanup=strip", "--file", &message_file]).await
+    }
+
+    /// Who last changed lines `start..=end` of a file, and in which commit
+    pub async fn blame(&self, path: &str, start: usize, end: usize) -> Result<ContextItem> {
+        if start == 0 || end < start {
+            bail!("invalid line range {}-{}", start, end);
+        }
+        let range = format!("{},{}", start, end);
+        let output = self.git(&["blame", "--date=short", "-L", &range, "--", path]).await?;
+
+        let mut metadata = HashMap::new();
+        metadata.insert("path".to_string(), serde_json::json!(path));
+        metadata.insert("start_line".to_string(), serde_json::json!(start));
+        metadata.insert("end_line".to_string(), serde_json::json!(end));
+
+        Ok(ContextItem {
+            item_type: ContextType::Command { command: format!("git blame -L {} -- {}", range, path) },
+            content: format!("Blame for {} lines {}-{}:\n{}", path, start, end, output),
+            metadata,
+        })
+    }
+}
+
+/// Refuse a user-supplied revision git would read as an option
+fn check_revision(revision: &str) -> Result<()> {
+    if revision.is_empty() || revision.starts_with('-') {
+        bail!("invalid revision '{}'", revision);
+    }
+    Ok(())
+}
+
+async fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await
+        .context("failed to run git")?;
+
+    if !output.status.success() {
+        bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diff_splits_files_and_counts_changes() {
+        let output = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,2 +1,2 @@
+-fn old() {}
++fn new() {}
+ fn kept() {}
+@@ -10 +10,2 @@
+ x
++y
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+";
+        let files = parse_diff(output);
+        assert_eq!(files.len(), 2);
+        assert_eq!((files[0].path.as_str(), files[0].hunks, files[0].additions, files[0].deletions), ("src/lib.rs", 2, 2, 1));
+        assert_eq!((files[1].path.as_str(), files[1].deletions), ("gone.txt", 1));
+        assert!(files[1].patch.starts_with("diff --git a/gone.txt"));
+        assert!(parse_diff("").is_empty());
+
+        // Changed lines that look like file headers are still changes
+        let output = "\
+diff --git a/schema.sql b/schema.sql
+--- a/schema.sql
++++ b/schema.sql
+@@ -1,3 +1,3 @@
+--- drop this comment
++++i;
++++ b/elsewhere
+ SELECT 1;
+---
+";
+        let files = parse_diff(output);
+        assert_eq!((files[0].path.as_str(), files[0].additions, files[0].deletions), ("schema.sql", 2, 2));
+    }
+
+    #[tokio::test]
+    async fn test_diff_against_branch_includes_uncommitted_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(args)
+                .current_dir(dir.path())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        run(&["init", "-q", "-b", "main"]);
+        run(&["config", "user.name", "Test"]);
+        run(&["config", "user.email", "test@example.com"]);
+        run(&["config", "commit.gpgsign", "false"]);
+        let unborn = GitRepository::discover(dir.path()).await.unwrap();
+        assert!(unborn.recent_subjects(2).await.unwrap().is_empty());
+        std::fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        run(&["add", "."]);
+        run(&["commit", "-q", "-m", "first"]);
+        run(&["checkout", "-q", "-b", "feature"]);
+        std::fs::write(dir.path().join("a.txt"), "one\ntwo\n").unwrap();
+        run(&["commit", "-q", "-am", "second"]);
+        std::fs::write(dir.path().join("b.txt"), "new\n").unwrap();
+        run(&["add", "b.txt"]);
+
+        let repo = GitRepository::discover(dir.path()).await.unwrap();
+        let items = repo.diff(&DiffSource::Branch("main".to_string())).await.unwrap();
+        let paths: Vec<_> = items.iter().map(|item| item.metadata["path"].clone()).collect();
+        assert_eq!(paths, [serde_json::json!("a.txt"), serde_json::json!("b.txt")]);
+        assert!(matches!(items[0].item_type, ContextType::GitDiff));
+        assert!(items[0].content.starts_with("Changes since main to a.txt:\ndiff --git"));
+
+        assert_eq!(repo.diff(&DiffSource::Staged).await.unwrap().len(), 1);
+        assert!(repo.diff(&DiffSource::WorkingTree).await.unwrap().is_empty());
+        assert!(repo.recent_log(5).await.unwrap().content.contains("second"));
+        assert!(repo.blame("a.txt", 2, 2).await.unwrap().content.contains("two"));
+        assert_eq!(repo.subjects_since("main").await.unwrap(), ["second"]);
+        assert!(repo.diff(&DiffSource::Branch("--output=x".to_string())).await.is_err());
+        assert!(repo.subjects_since("-p").await.is_err());
+
+        let message = dir.path().join("message.txt");
+        std::fs::write(&message, "Add b\n# dropped\n").unwrap();
+        repo.commit(&message).await.unwrap();
+        assert_eq!(repo.recent_subjects(2).await.unwrap(), ["Add b", "second"]);
+    }
+}
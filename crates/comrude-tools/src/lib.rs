@@ -3,6 +3,22 @@
 //! This module provides various tools and utilities that can be used
 //! by LLM providers and the main application.
 
+pub mod commit;
+pub mod edit_tool;
 pub mod file_tools;
+pub mod git;
+pub mod registry;
+pub mod search;
+pub mod shell;
+pub mod tool;
+pub mod workspace;
 
-pub use file_tools::*;
\ No newline at end of file
+pub use commit::*;
+pub use edit_tool::*;
+pub use file_tools::*;
+pub use git::*;
+pub use registry::*;
+pub use search::*;
+pub use shell::*;
+pub use tool::*;
+pub use workspace::*;
\ No newline at end of file
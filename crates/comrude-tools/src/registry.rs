@@ -0,0 +1,120 @@
+//! Registry that advertises tools to providers and dispatches their calls
+
+use crate::edit_tool::EditFileTool;
+use crate::file_tools::{ListDirectoryTool, ReadFileTool, WriteFileTool};
+use crate::search::{FindFilesTool, SearchFilesTool, SearchLimits};
+use crate::shell::ShellTool;
+use crate::tool::Tool;
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use comrude_core::{ToolCall, ToolDefinition, ToolExecutor, ToolResult};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Debug, Default, Clone)]
+pub struct ToolRegistry {
+    // Ordered so the definitions sent to providers are stable between requests
+    tools: BTreeMap<String, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with every tool that ships with Comrude, confined to
+    /// `workspace`, with search and command output held to `limits`
+    pub fn with_builtin_tools(workspace: Arc<Workspace>, limits: SearchLimits) -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(ReadFileTool { workspace: workspace.clone() }));
+        registry.register(Box::new(WriteFileTool { workspace: workspace.clone() }));
+        registry.register(Box::new(EditFileTool { workspace: workspace.clone() }));
+        registry.register(Box::new(SearchFilesTool { workspace: workspace.clone(), limits }));
+        registry.register(Box::new(FindFilesTool { workspace: workspace.clone(), limits }));
+        registry.register(Box::new(ShellTool { workspace: workspace.clone(), max_output_bytes: limits.max_output_bytes }));
+        registry.register(Box::new(ListDirectoryTool { workspace }));
+        registry
+    }
+
+    /// Add a tool, replacing any tool with the same name
+    pub fn register(&mut self, tool: Box<dyn Tool>) {
+        self.tools.insert(tool.name().to_string(), Arc::from(tool));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.tools.keys().cloned().collect()
+    }
+
+    /// Definitions for `GenerationRequest::tools`
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.values().map(|tool| tool.definition()).collect()
+    }
+
+    /// Run the tool a call names and tag the result with the call's id
+    pub async fn dispatch(&self, call: &ToolCall) -> ToolResult {
+        let mut result = match self.get(&call.name) {
+            Some(tool) => tool.invoke(call.arguments.clone()).await,
+            None => ToolResult::error(format!("unknown tool: {}", call.name)),
+        };
+        result.call_id = call.id.clone();
+        result
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for ToolRegistry {
+    fn definitions(&self) -> Vec<ToolDefinition> {
+        ToolRegistry::definitions(self)
+    }
+
+    async fn execute(&self, call: &ToolCall) -> ToolResult {
+        self.dispatch(call).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, arguments: serde_json::Value) -> ToolCall {
+        ToolCall {
+            id: "call_1".to_string(),
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_runs_tools_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path(), &comrude_core::Config::default().files).unwrap();
+        let registry = ToolRegistry::with_builtin_tools(Arc::new(workspace), SearchLimits::default());
+
+        let names: Vec<String> = registry.definitions().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, [
+            "edit_file", "find_files", "list_directory", "read_file", "run_command", "search_files",
+            "write_file",
+        ]);
+
+        let written = registry.dispatch(&call("write_file", serde_json::json!({
+            "path": "notes.txt",
+            "content": "hello",
+        }))).await;
+        assert!(!written.is_error, "{}", written.content);
+
+        let read = registry.dispatch(&call("read_file", serde_json::json!({"path": "notes.txt"}))).await;
+        assert_eq!(read.call_id, "call_1");
+        assert_eq!(read.content, "hello");
+
+        let bad_arguments = registry.dispatch(&call("read_file", serde_json::json!({"file": "x"}))).await;
+        assert!(bad_arguments.is_error);
+
+        let unknown = registry.dispatch(&call("rm_rf", serde_json::json!({}))).await;
+        assert!(unknown.is_error);
+        assert_eq!(unknown.call_id, "call_1");
+    }
+}
@@ -0,0 +1,455 @@
+//! Finding files and text across the workspace
+//!
+//! Walks honour `.gitignore` and `.ignore` files and skip hidden entries, so
+//! build output and dependency folders stay out of the results. Results are
+//! paged and cut off at a byte budget so they fit in the model's context.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use async_trait::async_trait;
+use comrude_core::{MemoryConfig, ToolResult};
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::WalkBuilder;
+use regex::RegexBuilder;
+use serde::Deserialize;
+use crate::tool::{parse_arguments, Tool};
+use crate::workspace::Workspace;
+
+const DEFAULT_MATCH_LIMIT: usize = 50;
+const DEFAULT_FILE_LIMIT: usize = 200;
+const MAX_PAGE_SIZE: usize = 500;
+const MAX_CONTEXT_LINES: usize = 10;
+/// Longer lines, usually minified files, are cut to this many characters
+const MAX_LINE_CHARS: usize = 300;
+
+/// How much a single search may return
+#[derive(Debug, Clone, Copy)]
+pub struct SearchLimits {
+    pub max_output_bytes: usize,
+}
+
+impl SearchLimits {
+    /// A quarter of the context window, at about four bytes per token
+    pub fn for_context_tokens(max_context_tokens: usize) -> Self {
+        Self { max_output_bytes: max_context_tokens.max(1024) }
+    }
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self::for_context_tokens(MemoryConfig::default().max_context_tokens)
+    }
+}
+
+/// A regex search over file contents
+#[derive(Debug, Clone, Deserialize)]
+pub struct TextSearch {
+    pub pattern: String,
+    /// Directory to search, the workspace root by default
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Only search files matching this glob
+    #[serde(default)]
+    pub glob: Option<String>,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// Lines shown before and after each match
+    #[serde(default)]
+    pub context: usize,
+    /// Matches to skip, for fetching the next page
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// A glob listing of file paths
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileSearch {
+    pub pattern: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// A glob without a `/` matches file names anywhere, like in `.gitignore`;
+/// one with a `/` matches the whole path from the workspace root
+struct PathGlob {
+    matcher: GlobMatcher,
+    whole_path: bool,
+}
+
+impl PathGlob {
+    fn new(pattern: &str) -> Result<Self, String> {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("invalid glob {}: {}", pattern, e))?;
+        Ok(Self { matcher: glob.compile_matcher(), whole_path: pattern.contains('/') })
+    }
+
+    fn is_match(&self, relative: &Path) -> bool {
+        if self.whole_path {
+            self.matcher.is_match(relative)
+        } else {
+            relative.file_name().is_some_and(|name| self.matcher.is_match(name))
+        }
+    }
+}
+
+/// Files under `path` the file tools may read, relative to the root and sorted
+fn walk(workspace: &Workspace, path: Option<&str>, glob: Option<&PathGlob>) -> Result<Vec<PathBuf>, String> {
+    let path = path.unwrap_or(".");
+    let start = workspace.resolve(path).map_err(|e| e.to_string())?;
+    if !start.is_dir() {
+        return Err(format!("{} is not a directory", path));
+    }
+
+    let walker = WalkBuilder::new(&start)
+        .hidden(true)
+        .git_ignore(true)
+        // Ignore files count even outside a git checkout
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker.flatten() {
+        // Symlinks are not followed, so a walk never leaves the workspace
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(workspace.root()) else { continue };
+        if !workspace.allows(relative) || glob.is_some_and(|glob| !glob.is_match(relative)) {
+            continue;
+        }
+        if entry.metadata().map_or(true, |metadata| metadata.len() > workspace.max_file_size()) {
+            continue;
+        }
+        files.push(relative.to_path_buf());
+    }
+    Ok(files)
+}
+
+fn page_size(limit: Option<usize>, default: usize) -> usize {
+    limit.unwrap_or(default).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Tell the model how many results it got and how to ask for the rest
+fn push_page_note(out: &mut String, shown: usize, what: &str, next: Option<usize>, out_of_room: bool) {
+    if let Some(next) = next {
+        let reason = if out_of_room { "output limit reached" } else { "page full" };
+        out.push_str(&format!(
+            "\n[{} {} shown, {}; continue with offset {}]", shown, what, reason, next
+        ));
+    }
+}
+
+fn clip(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((cut, _)) => format!("{}…", &line[..cut]),
+        None => line.to_string(),
+    }
+}
+
+/// Lines matching a regex, grep style: `path:line:text` for matches and
+/// `path-line-text` for context, with `--` between separate groups
+pub fn search_text(workspace: &Workspace, search: &TextSearch, limits: SearchLimits) -> Result<String, String> {
+    let regex = RegexBuilder::new(&search.pattern)
+        .case_insensitive(search.case_insensitive)
+        .build()
+        .map_err(|e| format!("invalid pattern: {}", e))?;
+    let glob = search.glob.as_deref().map(PathGlob::new).transpose()?;
+    let files = walk(workspace, search.path.as_deref(), glob.as_ref())?;
+
+    let context = search.context.min(MAX_CONTEXT_LINES);
+    let page_end = search.offset + page_size(search.limit, DEFAULT_MATCH_LIMIT);
+    let mut out = String::new();
+    let mut shown = 0;
+    // Matches in the files searched so far
+    let mut seen = 0;
+    let mut next = None;
+    let mut out_of_room = false;
+
+    'files: for relative in files {
+        // Binary and non-UTF-8 files are skipped
+        let Ok(content) = fs::read_to_string(workspace.root().join(&relative)) else { continue };
+        let lines: Vec<&str> = content.lines().collect();
+        let matches: Vec<usize> = lines.iter().enumerate()
+            .filter(|(_, line)| regex.is_match(line))
+            .map(|(number, _)| number)
+            .collect();
+
+        let first = seen;
+        seen += matches.len();
+        if seen <= search.offset {
+            continue;
+        }
+        if first >= page_end {
+            next = Some(page_end);
+            break;
+        }
+
+        let selected = &matches[search.offset.saturating_sub(first)..(page_end - first).min(matches.len())];
+        let display = relative.to_string_lossy().replace('\\', "/");
+
+        // Matches whose context overlaps share one group
+        let mut groups: Vec<(usize, usize, usize)> = Vec::new();
+        for &line in selected {
+            let (start, end) = (line.saturating_sub(context), (line + context + 1).min(lines.len()));
+            match groups.last_mut() {
+                Some(group) if start <= group.1 => {
+                    group.1 = end;
+                    group.2 += 1;
+                }
+                _ => groups.push((start, end, 1)),
+            }
+        }
+
+        let mut index = first + search.offset.saturating_sub(first);
+        for (start, end, count) in groups {
+            let mut block = String::new();
+            if !out.is_empty() {
+                block.push_str("--\n");
+            }
+            for (number, line) in lines.iter().enumerate().take(end).skip(start) {
+                let separator = if selected.contains(&number) { ':' } else { '-' };
+                block.push_str(&format!("{}{}{}{}{}\n", display, separator, number + 1, separator, clip(line)));
+            }
+
+            if out.len() + block.len() > limits.max_output_bytes {
+                next = Some(index);
+                out_of_room = true;
+                break 'files;
+            }
+            out.push_str(&block);
+            shown += count;
+            index += count;
+        }
+
+        if page_end < seen {
+            next = Some(page_end);
+            break;
+        }
+    }
+
+    if shown == 0 && next.is_none() {
+        return Ok(format!("no matches for {}", search.pattern));
+    }
+    push_page_note(&mut out, shown, "matches", next, out_of_room);
+    Ok(out)
+}
+
+/// Paths matching a glob, one per line
+pub fn find_files(workspace: &Workspace, search: &FileSearch, limits: SearchLimits) -> Result<String, String> {
+    let glob = PathGlob::new(&search.pattern)?;
+    let files = walk(workspace, search.path.as_deref(), Some(&glob))?;
+    if files.is_empty() {
+        return Ok(format!("no files match {}", search.pattern));
+    }
+
+    let page_end = search.offset + page_size(search.limit, DEFAULT_FILE_LIMIT);
+    let mut out = String::new();
+    let mut shown = 0;
+    let mut next = (files.len() > page_end).then_some(page_end);
+    let mut out_of_room = false;
+
+    for (index, relative) in files.iter().enumerate().take(page_end).skip(search.offset) {
+        let line = format!("{}\n", relative.to_string_lossy().replace('\\', "/"));
+        if out.len() + line.len() > limits.max_output_bytes {
+            next = Some(index);
+            out_of_room = true;
+            break;
+        }
+        out.push_str(&line);
+        shown += 1;
+    }
+
+    push_page_note(&mut out, shown, "files", next, out_of_room);
+    Ok(out)
+}
+
+/// `search_text` exposed as a tool
+#[derive(Debug)]
+pub struct SearchFilesTool {
+    pub workspace: Arc<Workspace>,
+    pub limits: SearchLimits,
+}
+
+#[async_trait]
+impl Tool for SearchFilesTool {
+    fn name(&self) -> &str {
+        "search_files"
+    }
+
+    fn description(&self) -> &str {
+        "Search file contents in the workspace with a regular expression. \
+         Files ignored by .gitignore are skipped. Results are paged; pass the \
+         offset from the note at the end to get more."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "pattern": {"type": "string", "description": "Regular expression matched against each line"},
+                "path": {"type": "string", "description": "Directory to search, the workspace root by default"},
+                "glob": {"type": "string", "description": "Only search files matching this glob, such as *.rs or src/**/*.ts"},
+                "case_insensitive": {"type": "boolean"},
+                "context": {"type": "integer", "description": "Lines to show before and after each match, up to 10"},
+                "offset": {"type": "integer", "description": "Matches to skip"},
+                "limit": {"type": "integer", "description": "Matches to return, 50 by default"}
+            },
+            "required": ["pattern"]
+        })
+    }
+
+    async fn invoke(&self, arguments: serde_json::Value) -> ToolResult {
+        let search: TextSearch = match parse_arguments(self.name(), arguments) {
+            Ok(search) => search,
+            Err(result) => return result,
+        };
+
+        let (workspace, limits) = (self.workspace.clone(), self.limits);
+        let found = tokio::task::spawn_blocking(move || search_text(&workspace, &search, limits)).await;
+        match found {
+            Ok(Ok(content)) => ToolResult::success(content),
+            Ok(Err(e)) => ToolResult::error(format!("search failed: {}", e)),
+            Err(e) => ToolResult::error(format!("search failed: {}", e)),
+        }
+    }
+}
+
+/// `find_files` exposed as a tool
+#[derive(Debug)]
+pub struct FindFilesTool {
+    pub workspace: Arc<Workspace>,
+    pub limits: SearchLimits,
+}
+
+#[async_trait]
+impl Tool for FindFilesTool {
+    fn name(&self) -> &str {
+        "find_files"
+    }
+
+    fn description(&self) -> &str {
+        "List workspace files whose path matches a glob, recursively. \
+         Files ignored by .gitignore are skipped."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "pattern": {"type": "string", "description": "Glob such as *.rs (any directory) or src/**/mod.rs (from the root)"},
+                "path": {"type": "string", "description": "Directory to search, the workspace root by default"},
+                "offset": {"type": "integer", "description": "Paths to skip"},
+                "limit": {"type": "integer", "description": "Paths to return, 200 by default"}
+            },
+            "required": ["pattern"]
+        })
+    }
+
+    async fn invoke(&self, arguments: serde_json::Value) -> ToolResult {
+        let search: FileSearch = match parse_arguments(self.name(), arguments) {
+            Ok(search) => search,
+            Err(result) => return result,
+        };
+
+        let (workspace, limits) = (self.workspace.clone(), self.limits);
+        let found = tokio::task::spawn_blocking(move || find_files(&workspace, &search, limits)).await;
+        match found {
+            Ok(Ok(content)) => ToolResult::success(content),
+            Ok(Err(e)) => ToolResult::error(format!("find failed: {}", e)),
+            Err(e) => ToolResult::error(format!("find failed: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comrude_core::FilesConfig;
+
+    fn workspace() -> (tempfile::TempDir, Workspace) {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write(".gitignore", "target/\n");
+        write("src/lib.rs", "mod a;\nfn alpha() {}\nfn beta() {}\n\nfn gamma() {}\n");
+        write("src/a.rs", "fn alpha_two() {}\n");
+        write("target/out.rs", "fn alpha_built() {}\n");
+        write("notes.txt", "alpha notes\n");
+        write("image.png", "alpha");
+
+        let workspace = Workspace::new(dir.path(), &FilesConfig {
+            max_file_size_mb: 1,
+            allowed_extensions: vec!["rs".to_string(), "txt".to_string()],
+            workspace_root: None,
+        }).unwrap();
+        (dir, workspace)
+    }
+
+    fn text(pattern: &str) -> TextSearch {
+        TextSearch {
+            pattern: pattern.to_string(),
+            path: None,
+            glob: None,
+            case_insensitive: false,
+            context: 0,
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn test_search_text_pages_and_respects_gitignore() {
+        let (_dir, workspace) = workspace();
+        let limits = SearchLimits::default();
+
+        let all = search_text(&workspace, &text("fn alpha"), limits).unwrap();
+        assert_eq!(all, "src/a.rs:1:fn alpha_two() {}\n--\nsrc/lib.rs:2:fn alpha() {}\n");
+
+        let with_context = TextSearch { glob: Some("lib.rs".to_string()), context: 1, ..text("alpha|beta") };
+        assert_eq!(
+            search_text(&workspace, &with_context, limits).unwrap(),
+            "src/lib.rs-1-mod a;\nsrc/lib.rs:2:fn alpha() {}\nsrc/lib.rs:3:fn beta() {}\nsrc/lib.rs-4-\n"
+        );
+
+        let first_page = TextSearch { limit: Some(2), ..text("fn ") };
+        let page = search_text(&workspace, &first_page, limits).unwrap();
+        assert!(page.ends_with("[2 matches shown, page full; continue with offset 2]"), "{}", page);
+        let second_page = TextSearch { offset: 2, limit: Some(2), ..text("fn ") };
+        let page = search_text(&workspace, &second_page, limits).unwrap();
+        assert_eq!(page, "src/lib.rs:3:fn beta() {}\n--\nsrc/lib.rs:5:fn gamma() {}\n");
+
+        let tight = SearchLimits { max_output_bytes: 40 };
+        let cut = search_text(&workspace, &text("fn "), tight).unwrap();
+        assert!(cut.ends_with("[1 matches shown, output limit reached; continue with offset 1]"), "{}", cut);
+
+        assert_eq!(search_text(&workspace, &text("zeta"), limits).unwrap(), "no matches for zeta");
+        assert!(search_text(&workspace, &text("("), limits).is_err());
+    }
+
+    #[test]
+    fn test_find_files_matches_names_and_paths() {
+        let (_dir, workspace) = workspace();
+        let limits = SearchLimits::default();
+        let find = |pattern: &str, offset, limit| FileSearch { pattern: pattern.to_string(), path: None, offset, limit };
+
+        assert_eq!(find_files(&workspace, &find("*.rs", 0, None), limits).unwrap(), "src/a.rs\nsrc/lib.rs\n");
+        assert_eq!(find_files(&workspace, &find("*.rs", 1, None), limits).unwrap(), "src/lib.rs\n");
+        assert_eq!(find_files(&workspace, &find("src/*", 0, None), limits).unwrap(), "src/a.rs\nsrc/lib.rs\n");
+        assert_eq!(find_files(&workspace, &find("*", 0, None), limits).unwrap(), "notes.txt\nsrc/a.rs\nsrc/lib.rs\n");
+        assert!(find_files(&workspace, &find("*", 0, Some(1)), limits).unwrap().ends_with("continue with offset 1]"));
+        assert_eq!(find_files(&workspace, &find("*.png", 0, None), limits).unwrap(), "no files match *.png");
+    }
+}
@@ -0,0 +1,381 @@
+//! Running shell commands with a timeout and captured, size-limited output
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use comrude_core::{ContextItem, ContextType, ToolResult};
+use serde::{Deserialize, Serialize, Serializer};
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use crate::tool::{parse_arguments, Tool};
+use crate::workspace::Workspace;
+
+pub const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 60;
+const MAX_COMMAND_TIMEOUT_SECS: u64 = 600;
+
+/// A command line run through `bash -c`, so quoting, pipes and `&&` work as
+/// in a terminal
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShellCommand {
+    pub command: String,
+    /// Working directory inside the workspace, the root by default
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Variables added to the inherited environment
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Bytes kept from each of stdout and stderr
+    #[serde(default)]
+    pub max_output_bytes: Option<usize>,
+}
+
+impl ShellCommand {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            cwd: None,
+            env: HashMap::new(),
+            timeout_secs: None,
+            max_output_bytes: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandOutput {
+    /// None when the command was killed by a signal or the timeout
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether either stream went past the output limit
+    pub truncated: bool,
+    pub timed_out: bool,
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    pub duration: Duration,
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64((duration.as_secs_f64() * 1000.0).round() / 1000.0)
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// The output as context for the next request
+    pub fn to_context_item(&self, command: &str) -> ContextItem {
+        let status = match (self.exit_code, self.timed_out) {
+            (_, true) => "timed out".to_string(),
+            (Some(code), _) => format!("exit code {}", code),
+            (None, _) => "killed by a signal".to_string(),
+        };
+
+        let mut content = format!("$ {}\n[{}]\n", command, status);
+        content.push_str(&self.stdout);
+        if !self.stderr.is_empty() {
+            if !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content.push_str("[stderr]\n");
+            content.push_str(&self.stderr);
+        }
+        if self.truncated {
+            content.push_str("\n[... output truncated ...]");
+        }
+
+        let mut metadata = HashMap::new();
+        metadata.insert("exit_code".to_string(), serde_json::json!(self.exit_code));
+        metadata.insert("duration_secs".to_string(), serde_json::json!(self.duration.as_secs_f64()));
+
+        ContextItem {
+            item_type: ContextType::Command { command: command.to_string() },
+            content,
+            metadata,
+        }
+    }
+}
+
+/// Keeps the first `limit` bytes of a stream and notes whether more came
+struct Capture {
+    bytes: Vec<u8>,
+    limit: usize,
+    truncated: bool,
+}
+
+impl Capture {
+    fn new(limit: usize) -> Self {
+        Self { bytes: Vec::new(), limit, truncated: false }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        let room = self.limit.saturating_sub(self.bytes.len());
+        if chunk.len() > room {
+            self.truncated = true;
+        }
+        self.bytes.extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+
+    fn into_string(self) -> String {
+        String::from_utf8_lossy(&self.bytes).into_owned()
+    }
+}
+
+/// Output still arriving after the command exited, from background jobs
+/// that inherited its pipes, is read for this long and then left behind
+const OUTPUT_GRACE: Duration = Duration::from_millis(200);
+
+/// Kills the command's process group unless disarmed, so a timeout or a
+/// dropped future does not leave grandchildren running
+struct ProcessGroup {
+    pgid: Option<i32>,
+}
+
+impl ProcessGroup {
+    fn kill(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid.take() {
+            // SAFETY: killpg only sends a signal; the group was created for this command
+            unsafe {
+                libc::killpg(pgid, libc::SIGKILL);
+            }
+        }
+    }
+
+    fn disarm(&mut self) {
+        self.pgid = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Run a command inside the workspace
+///
+/// The command gets a process group of its own, which is killed as a whole
+/// on timeout or when the returned future is dropped. A command that sets no
+/// timeout of its own gets `default_timeout`, or runs until it exits when that
+/// is `None`. The output keeps being read past the limit so a chatty command
+/// never blocks on a full pipe; the excess is dropped.
+pub async fn run_command(
+    workspace: &Workspace,
+    command: &ShellCommand,
+    default_max_output_bytes: usize,
+    default_timeout: Option<Duration>,
+) -> Result<CommandOutput> {
+    if command.command.trim().is_empty() {
+        bail!("command is empty");
+    }
+    let cwd = workspace.resolve(command.cwd.as_deref().unwrap_or("."))?;
+    if !cwd.is_dir() {
+        bail!("{} is not a directory", cwd.display());
+    }
+    let timeout = match command.timeout_secs {
+        Some(secs) => Some(Duration::from_secs(secs.clamp(1, MAX_COMMAND_TIMEOUT_SECS))),
+        None => default_timeout,
+    };
+    let limit = command.max_output_bytes.unwrap_or(default_max_output_bytes);
+
+    let started = Instant::now();
+    let mut process = Command::new("bash");
+    process
+        .arg("-c")
+        .arg(&command.command)
+        .current_dir(&cwd)
+        .envs(&command.env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    process.process_group(0);
+    let mut child = process.spawn()?;
+    let mut group = ProcessGroup { pgid: child.id().map(|pid| pid as i32) };
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let (mut out, mut err) = (Capture::new(limit), Capture::new(limit));
+    let (mut out_chunk, mut err_chunk) = ([0u8; 8192], [0u8; 8192]);
+    let (mut out_open, mut err_open) = (true, true);
+
+    // Without a timeout the deadline lies far enough ahead never to fire
+    let deadline = tokio::time::sleep(timeout.unwrap_or(Duration::MAX));
+    tokio::pin!(deadline);
+    let mut status = None;
+
+    // The child is reaped as soon as it exits, whether or not its pipes
+    // have closed
+    let timed_out = loop {
+        if status.is_some() && !out_open && !err_open {
+            break false;
+        }
+        tokio::select! {
+            read = stdout.read(&mut out_chunk), if out_open => match read {
+                Ok(0) | Err(_) => out_open = false,
+                Ok(n) => out.push(&out_chunk[..n]),
+            },
+            read = stderr.read(&mut err_chunk), if err_open => match read {
+                Ok(0) | Err(_) => err_open = false,
+                Ok(n) => err.push(&err_chunk[..n]),
+            },
+            exited = child.wait(), if status.is_none() => {
+                status = Some(exited?);
+                // Jobs it left in the background are the user's to keep
+                group.disarm();
+                deadline.as_mut().reset(tokio::time::Instant::now() + OUTPUT_GRACE);
+            }
+            _ = &mut deadline => {
+                if status.is_some() {
+                    break false;
+                }
+                group.kill();
+                let _ = child.wait().await;
+                break true;
+            }
+        }
+    };
+
+    Ok(CommandOutput {
+        exit_code: status.and_then(|status| status.code()),
+        truncated: out.truncated || err.truncated,
+        stdout: out.into_string(),
+        stderr: err.into_string(),
+        timed_out,
+        duration: started.elapsed(),
+    })
+}
+
+/// `run_command` exposed as a tool
+#[derive(Debug)]
+pub struct ShellTool {
+    pub workspace: Arc<Workspace>,
+    /// Bytes kept from each stream when the call sets no limit
+    pub max_output_bytes: usize,
+}
+
+#[async_trait]
+impl Tool for ShellTool {
+    fn name(&self) -> &str {
+        "run_command"
+    }
+
+    fn description(&self) -> &str {
+        "Run a shell command in the workspace and return its exit code, stdout and stderr. \
+         Use it to build, test, or inspect the project. Interactive programs are not supported."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "command": {"type": "string", "description": "Command line, run with bash -c"},
+                "cwd": {"type": "string", "description": "Working directory, the workspace root by default"},
+                "env": {
+                    "type": "object",
+                    "description": "Extra environment variables",
+                    "additionalProperties": {"type": "string"}
+                },
+                "timeout_secs": {"type": "integer", "description": "Seconds before the command is killed, 60 by default"},
+                "max_output_bytes": {"type": "integer", "description": "Bytes kept from each of stdout and stderr"}
+            },
+            "required": ["command"]
+        })
+    }
+
+    fn requires_confirmation(&self, _arguments: &serde_json::Value) -> bool {
+        true
+    }
+
+    async fn invoke(&self, arguments: serde_json::Value) -> ToolResult {
+        let command: ShellCommand = match parse_arguments(self.name(), arguments) {
+            Ok(command) => command,
+            Err(result) => return result,
+        };
+
+        let default_timeout = Duration::from_secs(DEFAULT_COMMAND_TIMEOUT_SECS);
+        match run_command(&self.workspace, &command, self.max_output_bytes, Some(default_timeout)).await {
+            Ok(output) => {
+                let content = serde_json::to_string_pretty(&output).unwrap_or_default();
+                if output.success() { ToolResult::success(content) } else { ToolResult::error(content) }
+            }
+            Err(e) => ToolResult::error(format!("failed to run {}: {}", command.command, e)),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn workspace(dir: &tempfile::TempDir) -> Workspace {
+        Workspace::new(dir.path(), &comrude_core::Config::default().files).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_run_command_captures_output_and_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let workspace = workspace(&dir);
+
+        let command = ShellCommand {
+            cwd: Some("sub".to_string()),
+            env: HashMap::from([("GREETING".to_string(), "hello world".to_string())]),
+            ..ShellCommand::new("echo \"$GREETING\" 'quoted arg'; basename \"$PWD\"; echo oops >&2; exit 3")
+        };
+        let output = run_command(&workspace, &command, 1024, None).await.unwrap();
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.stdout, "hello world quoted arg\nsub\n");
+        assert_eq!(output.stderr, "oops\n");
+        assert!(!output.truncated && !output.timed_out);
+
+        let item = output.to_context_item(&command.command);
+        assert!(matches!(item.item_type, ContextType::Command { .. }));
+        assert!(item.content.contains("[exit code 3]\nhello world"));
+
+        let outside = ShellCommand { cwd: Some("..".to_string()), ..ShellCommand::new("true") };
+        assert!(run_command(&workspace, &outside, 1024, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_command_truncates_and_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace(&dir);
+
+        let output = run_command(&workspace, &ShellCommand::new("yes | head -c 100000"), 10, None).await.unwrap();
+        assert_eq!(output.stdout, "y\ny\ny\ny\ny\n");
+        assert!(output.truncated && output.success());
+
+        let slow = ShellCommand { timeout_secs: Some(1), ..ShellCommand::new("sleep 5") };
+        let output = run_command(&workspace, &slow, 10, None).await.unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.exit_code, None);
+        assert!(output.duration < Duration::from_secs(4));
+    }
+
+    #[tokio::test]
+    async fn test_run_command_kills_the_process_group_and_skips_background_pipes() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace(&dir);
+
+        // A background job holding the pipes open does not keep the command running
+        let output = run_command(&workspace, &ShellCommand::new("sleep 5 & echo started"), 1024, None).await.unwrap();
+        assert_eq!(output.stdout, "started\n");
+        assert!(output.success() && !output.timed_out);
+        assert!(output.duration < Duration::from_secs(3));
+
+        // On timeout the whole group goes, not just the shell
+        let slow = ShellCommand::new("(sleep 2; touch marker) & sleep 5");
+        let output = run_command(&workspace, &slow, 1024, Some(Duration::from_secs(1))).await.unwrap();
+        assert!(output.timed_out);
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!dir.path().join("marker").exists());
+    }
+}
@@ -0,0 +1,37 @@
+//! The interface every tool exposes to LLM providers
+
+use async_trait::async_trait;
+use comrude_core::{ToolDefinition, ToolResult};
+use serde::de::DeserializeOwned;
+
+/// An action a model can request through a native tool call
+#[async_trait]
+pub trait Tool: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+
+    /// JSON schema of the arguments object
+    fn parameters(&self) -> serde_json::Value;
+
+    /// Whether a person should approve this call before it runs
+    fn requires_confirmation(&self, _arguments: &serde_json::Value) -> bool {
+        false
+    }
+
+    /// Run the tool; failures are reported in the result so the model can react
+    async fn invoke(&self, arguments: serde_json::Value) -> ToolResult;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name().to_string(),
+            description: self.description().to_string(),
+            parameters: self.parameters(),
+        }
+    }
+}
+
+/// Deserialize tool arguments, describing what was wrong on failure
+pub fn parse_arguments<T: DeserializeOwned>(tool: &str, arguments: serde_json::Value) -> Result<T, ToolResult> {
+    serde_json::from_value(arguments)
+        .map_err(|e| ToolResult::error(format!("invalid arguments for {}: {}", tool, e)))
+}
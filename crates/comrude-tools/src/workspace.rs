@@ -0,0 +1,320 @@
+//! The directory tree file tools are confined to
+//!
+//! Every path a model hands to a tool is resolved here first. Paths that
+//! leave the workspace, either through `..` or through a symlink, are
+//! rejected, as are files whose extension is not allowed by `FilesConfig`.
+
+use comrude_core::FilesConfig;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum WorkspaceError {
+    #[error("{0} is outside the workspace")]
+    OutsideWorkspace(String),
+
+    #[error("files with extension '{extension}' are not allowed: {path}")]
+    ExtensionNotAllowed { path: String, extension: String },
+
+    #[error("{path} is {size} bytes, over the {limit} byte limit")]
+    TooLarge { path: String, size: u64, limit: u64 },
+
+    #[error("{0} changed since it was read")]
+    Changed(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type WorkspaceResult<T> = std::result::Result<T, WorkspaceError>;
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+    max_file_size: u64,
+    allowed_extensions: Vec<String>,
+}
+
+impl Workspace {
+    pub fn new(root: impl AsRef<Path>, files: &FilesConfig) -> WorkspaceResult<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+            max_file_size: files.max_file_size_mb * 1024 * 1024,
+            allowed_extensions: files.allowed_extensions.clone(),
+        })
+    }
+
+    /// The configured workspace root, or the current directory
+    pub fn from_config(files: &FilesConfig) -> WorkspaceResult<Self> {
+        match &files.workspace_root {
+            Some(root) => Self::new(root, files),
+            None => Self::new(std::env::current_dir()?, files),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve a path relative to the root, following symlinks in the part
+    /// that already exists, and make sure the result stays inside the root
+    pub fn resolve(&self, path: &str) -> WorkspaceResult<PathBuf> {
+        let outside = || WorkspaceError::OutsideWorkspace(path.to_string());
+
+        let requested = Path::new(path);
+        let joined = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            self.root.join(requested)
+        };
+
+        // Resolve `.` and `..` lexically so paths that don't exist yet can be checked
+        let mut normalized = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        return Err(outside());
+                    }
+                }
+                other => normalized.push(other),
+            }
+        }
+        if !normalized.starts_with(&self.root) {
+            return Err(outside());
+        }
+
+        // A symlink anywhere along the way may still lead out, so find the
+        // deepest entry that exists (dangling links included) and see where
+        // it really points
+        let mut existing = normalized.as_path();
+        let mut missing = Vec::new();
+        while fs::symlink_metadata(existing).is_err() {
+            let Some(name) = existing.file_name() else {
+                return Err(outside());
+            };
+            missing.push(name.to_os_string());
+            existing = existing.parent().ok_or_else(outside)?;
+        }
+
+        // Canonicalizing fails for dangling symlinks, which are refused too
+        let mut resolved = existing.canonicalize().map_err(|_| outside())?;
+        resolved.extend(missing.iter().rev());
+
+        if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else {
+            Err(outside())
+        }
+    }
+
+    /// Resolve a path that must name a file of an allowed type
+    pub fn resolve_file(&self, path: &str) -> WorkspaceResult<PathBuf> {
+        let resolved = self.resolve(path)?;
+
+        if !self.allows(&resolved) {
+            return Err(WorkspaceError::ExtensionNotAllowed {
+                path: path.to_string(),
+                extension: extension_of(&resolved),
+            });
+        }
+
+        Ok(resolved)
+    }
+
+    /// Whether the file tools may open a file of this type
+    pub fn allows(&self, path: &Path) -> bool {
+        // An empty list allows every file type
+        let extension = extension_of(path);
+        self.allowed_extensions.is_empty()
+            || self.allowed_extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(&extension))
+    }
+
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size
+    }
+
+    /// Read a text file, cutting it off with a marker past the size limit
+    pub fn read_to_string(&self, path: &str) -> WorkspaceResult<String> {
+        let resolved = self.resolve_file(path)?;
+        let size = fs::metadata(&resolved)?.len();
+
+        let mut bytes = Vec::new();
+        File::open(&resolved)?.take(self.max_file_size).read_to_end(&mut bytes)?;
+
+        if size <= self.max_file_size {
+            return String::from_utf8(bytes)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e).into());
+        }
+
+        // The cut may land inside a multi-byte character
+        let mut content = match String::from_utf8(bytes) {
+            Ok(content) => content,
+            Err(e) if e.utf8_error().error_len().is_none() => {
+                let valid = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid);
+                String::from_utf8(bytes).expect("prefix was validated")
+            }
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e).into()),
+        };
+        content.push_str(&format!(
+            "\n[... truncated: {} is {} bytes, showing the first {} ...]",
+            path, size, self.max_file_size
+        ));
+        Ok(content)
+    }
+
+    /// Read a whole text file, refusing files past the size limit rather
+    /// than truncating them
+    pub fn read_untruncated(&self, path: &str) -> WorkspaceResult<String> {
+        let resolved = self.resolve_file(path)?;
+        let size = fs::metadata(&resolved)?.len();
+        if size > self.max_file_size {
+            return Err(WorkspaceError::TooLarge {
+                path: path.to_string(),
+                size,
+                limit: self.max_file_size,
+            });
+        }
+        Ok(fs::read_to_string(resolved)?)
+    }
+
+    /// Replace a file's content atomically: readers see either the old file
+    /// or the new one, never a partial write
+    pub fn write(&self, path: &str, content: &str) -> WorkspaceResult<()> {
+        self.replace(path, None, content)
+    }
+
+    /// Like `write`, but only if the file still holds `expected`
+    ///
+    /// The file is compared just before the new content is renamed over it,
+    /// so a change made since it was read is reported instead of lost.
+    pub fn write_if_unchanged(&self, path: &str, expected: &str, content: &str) -> WorkspaceResult<()> {
+        self.replace(path, Some(expected), content)
+    }
+
+    fn replace(&self, path: &str, expected: Option<&str>, content: &str) -> WorkspaceResult<()> {
+        let resolved = self.resolve_file(path)?;
+
+        let size = content.len() as u64;
+        if size > self.max_file_size {
+            return Err(WorkspaceError::TooLarge {
+                path: path.to_string(),
+                size,
+                limit: self.max_file_size,
+            });
+        }
+
+        let parent = resolved.parent().ok_or_else(|| WorkspaceError::OutsideWorkspace(path.to_string()))?;
+        fs::create_dir_all(parent)?;
+
+        // The temporary file lives next to the target so the rename stays on one filesystem
+        let file_name = resolved.file_name().unwrap_or_default().to_string_lossy();
+        let temp_path = parent.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+
+        let written = (|| {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+            if let Ok(metadata) = fs::metadata(&resolved) {
+                fs::set_permissions(&temp_path, metadata.permissions())?;
+            }
+            if let Some(expected) = expected {
+                if fs::read(&resolved).ok().as_deref() != Some(expected.as_bytes()) {
+                    return Err(WorkspaceError::Changed(path.to_string()));
+                }
+            }
+            fs::rename(&temp_path, &resolved)?;
+            Ok(())
+        })();
+
+        if written.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        written
+    }
+
+    /// Names of the entries in a directory
+    pub fn list(&self, path: &str) -> WorkspaceResult<Vec<String>> {
+        let resolved = self.resolve(path)?;
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(resolved)? {
+            entries.push(entry?.file_name().to_string_lossy().to_string());
+        }
+        Ok(entries)
+    }
+}
+
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(dir: &Path, max_file_size_mb: u64) -> Workspace {
+        Workspace::new(dir, &FilesConfig {
+            max_file_size_mb,
+            allowed_extensions: vec!["rs".to_string(), "txt".to_string()],
+            workspace_root: None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_rejects_escapes() {
+        let outside = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace(dir.path(), 1);
+        fs::create_dir(dir.path().join("src")).unwrap();
+
+        assert!(workspace.resolve("src/../new.rs").is_ok());
+        assert!(workspace.resolve("new/dir/file.rs").is_ok());
+        assert!(matches!(workspace.resolve("../escape.rs"), Err(WorkspaceError::OutsideWorkspace(_))));
+        assert!(matches!(workspace.resolve("/etc/passwd"), Err(WorkspaceError::OutsideWorkspace(_))));
+        assert!(matches!(workspace.resolve_file("script.sh"), Err(WorkspaceError::ExtensionNotAllowed { .. })));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+            std::os::unix::fs::symlink(outside.path().join("missing.rs"), dir.path().join("dangling.rs")).unwrap();
+            assert!(matches!(workspace.resolve("link/file.rs"), Err(WorkspaceError::OutsideWorkspace(_))));
+            assert!(matches!(workspace.write("dangling.rs", "x"), Err(WorkspaceError::OutsideWorkspace(_))));
+            assert!(!outside.path().join("missing.rs").exists());
+        }
+    }
+
+    #[test]
+    fn test_write_is_atomic_and_reads_are_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace(dir.path(), 1);
+
+        workspace.write("src/lib.rs", "fn main() {}").unwrap();
+        assert_eq!(workspace.read_to_string("src/lib.rs").unwrap(), "fn main() {}");
+        assert_eq!(workspace.list("src").unwrap(), ["lib.rs"]);
+
+        // A file changed since it was read is left alone
+        fs::write(dir.path().join("src/lib.rs"), "fn other() {}").unwrap();
+        let stale = workspace.write_if_unchanged("src/lib.rs", "fn main() {}", "fn edited() {}");
+        assert!(matches!(stale, Err(WorkspaceError::Changed(_))));
+        assert_eq!(workspace.read_to_string("src/lib.rs").unwrap(), "fn other() {}");
+        workspace.write_if_unchanged("src/lib.rs", "fn other() {}", "fn edited() {}").unwrap();
+        assert_eq!(workspace.list("src").unwrap(), ["lib.rs"]);
+
+        let big = "é".repeat(600 * 1024);
+        fs::write(dir.path().join("big.txt"), &big).unwrap();
+        let content = workspace.read_to_string("big.txt").unwrap();
+        assert!(content.starts_with("éé"));
+        assert!(content.ends_with("showing the first 1048576 ...]"));
+
+        assert!(matches!(workspace.write("big.txt", &big), Err(WorkspaceError::TooLarge { .. })));
+        assert_eq!(fs::read_to_string(dir.path().join("big.txt")).unwrap(), big);
+    }
+}
@@ -1,8 +1,13 @@
 use clap::{Arg, Command};
-use comrude_core::{Config, ComrudeEngine};
+use comrude_core::{AgentConfig, Config, ComrudeEngine, ModelSummarizer, TokenUsage, UsageGrouping, UsageLedger};
 use comrude_core::types::Message;
 use comrude_providers::{ProviderManager, OpenAIProvider, AnthropicProvider, OllamaProvider};
+use comrude_tools::{
+    clean_message, commit_message_request, pull_request_request, run_command, CommandOutput, DiffSource,
+    GitRepository, SearchLimits, ShellCommand, ToolRegistry, Workspace, STYLE_SAMPLE_SIZE,
+};
 use std::io::{self, Write};
+use std::path::Path;
 use std::process::{Command as ProcessCommand, Stdio};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
@@ -18,8 +23,6 @@ use std::os::unix::process::CommandExt;
 // Command stack entry
 #[derive(Debug, Clone)]
 struct CommandStackEntry {
-    command: String,
-    pid: u32,
     pgid: i32,
 }
 
@@ -41,7 +44,7 @@ extern "C" fn sigint_handler(_: i32) {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Install SIGINT handler
     unsafe {
-        signal(SIGINT, sigint_handler as usize);
+        signal(SIGINT, sigint_handler as *const () as usize);
     }
     let matches = Command::new("comrude")
         .version("0.1.0")
@@ -75,14 +78,69 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .action(clap::ArgAction::SetTrue)
                 .help("Start in interactive mode")
         )
+        .arg(
+            Arg::new("agent")
+                .long("agent")
+                .action(clap::ArgAction::SetTrue)
+                .help("Let models that support tool calls act through tools")
+        )
+        .subcommand(
+            Command::new("commit")
+                .about("Draft a commit message for the staged changes, edit it, and commit")
+                .arg(
+                    Arg::new("no-edit")
+                        .long("no-edit")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Commit the drafted message without opening $EDITOR")
+                )
+        )
+        .subcommand(
+            Command::new("pr")
+                .about("Draft a pull request description for the current branch")
+                .arg(
+                    Arg::new("base")
+                        .long("base")
+                        .value_name("BRANCH")
+                        .default_value("main")
+                        .help("Branch the pull request merges into")
+                )
+        )
+        .subcommand(
+            Command::new("usage")
+                .about("Report recorded provider usage and cost")
+                .arg(
+                    Arg::new("by")
+                        .long("by")
+                        .value_name("GROUP")
+                        .value_parser(["provider", "model", "day", "session"])
+                        .default_value("day")
+                        .help("How to group the report")
+                )
+                .arg(
+                    Arg::new("csv")
+                        .long("csv")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Print the report as CSV")
+                )
+        )
         .get_matches();
 
     // Load configuration
     let config_path = matches.get_one::<String>("config");
-    let config = load_config(config_path).await?;
+    let mut config = load_config(config_path).await?;
+    if matches.get_flag("agent") {
+        config.agent.enabled = true;
+    }
+
+    if let Some(usage_matches) = matches.subcommand_matches("usage") {
+        return show_usage_report(&config, usage_matches);
+    }
 
     // Initialize provider manager
     let mut provider_manager = ProviderManager::new(config.clone());
+    if config.usage.enabled {
+        provider_manager = provider_manager.with_usage_ledger(UsageLedger::new(&config.usage.ledger_path)).await;
+    }
 
     // Register providers based on configuration
     register_providers(&mut provider_manager).await?;
@@ -95,7 +153,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         // Try to use default provider from config first
         if let Some(default_provider) = &config.app.default_provider {
-            if let Err(_) = provider_manager.set_current_provider(default_provider).await {
+            if provider_manager.set_current_provider(default_provider).await.is_err() {
                 // If default provider fails, auto-select best available provider
                 if let Ok(provider) = provider_manager.auto_select_provider().await {
                     let _ = provider_manager.set_current_provider(&provider).await;
@@ -109,6 +167,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    let repository_dir = config.files.workspace_root.clone().unwrap_or_else(|| ".".to_string());
+    let max_diff_bytes = diff_budget(config.memory.max_context_tokens);
+    if let Some(commit_matches) = matches.subcommand_matches("commit") {
+        let edit = !commit_matches.get_flag("no-edit");
+        return commit_staged_changes(&provider_manager, Path::new(&repository_dir), max_diff_bytes, edit).await;
+    }
+    if let Some(pr_matches) = matches.subcommand_matches("pr") {
+        let base = pr_matches.get_one::<String>("base").map(String::as_str).unwrap_or("main");
+        return describe_pull_request(&provider_manager, Path::new(&repository_dir), base, max_diff_bytes).await;
+    }
+
     // Start interactive mode if requested or no specific command
     if matches.get_flag("interactive") || std::env::args().len() == 1 {
         // Clear screen before starting interactive mode
@@ -122,6 +191,56 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn show_usage_report(config: &Config, matches: &clap::ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = UsageLedger::new(&config.usage.ledger_path);
+    let records = ledger.read_all()?;
+    let by = matches.get_one::<String>("by").map(String::as_str).unwrap_or("day");
+    let rows = comrude_core::summarize_usage(&records, by.parse::<UsageGrouping>()?);
+
+    if matches.get_flag("csv") {
+        println!("{},requests,retries,prompt_tokens,completion_tokens,cost_usd", by);
+        for row in &rows {
+            println!(
+                "{},{},{},{},{},{:.6}",
+                csv_field(&row.key), row.requests, row.retries,
+                row.prompt_tokens, row.completion_tokens, row.cost
+            );
+        }
+        return Ok(());
+    }
+
+    if rows.is_empty() {
+        println!("No usage recorded in {}", ledger.path().display());
+        return Ok(());
+    }
+
+    println!(
+        "{:<40} {:>9} {:>8} {:>14} {:>14} {:>11}",
+        by.to_uppercase(), "REQUESTS", "RETRIES", "PROMPT TOK", "OUTPUT TOK", "COST"
+    );
+    for row in &rows {
+        println!(
+            "{:<40} {:>9} {:>8} {:>14} {:>14} {:>11}",
+            row.key, row.requests, row.retries,
+            row.prompt_tokens, row.completion_tokens, format!("${:.4}", row.cost)
+        );
+    }
+
+    let total_cost: f64 = rows.iter().map(|row| row.cost).sum();
+    let total_requests: usize = rows.iter().map(|row| row.requests).sum();
+    println!("\nTotal: ${:.4} over {} requests", total_cost, total_requests);
+
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
 fn clear_screen() {
     // Use ANSI escape codes instead of reset command for cross-platform compatibility
     print!("\x1B[2J\x1B[1;1H");
@@ -188,12 +307,10 @@ async fn get_interactive_input(buffer: &mut String) -> Result<Option<String>, Bo
                             println!();
                             break Ok(Some(buffer.clone()));
                         }
-                        KeyCode::Backspace => {
-                            if !buffer.is_empty() {
-                                buffer.pop();
-                                print!("\x08 \x08"); // Backspace, space, backspace
-                                io::stdout().flush()?;
-                            }
+                        KeyCode::Backspace if !buffer.is_empty() => {
+                            buffer.pop();
+                            print!("\x08 \x08"); // Backspace, space, backspace
+                            io::stdout().flush()?;
                         }
                         KeyCode::Esc => {
                             // Escape key - clear current input
@@ -224,7 +341,7 @@ async fn load_config(config_path: Option<&String>) -> Result<Config, Box<dyn std
     let config = match config_path {
         Some(path) => {
             // Load from specified file
-            println!("Loading config from: {}", path);
+            eprintln!("Loading config from: {}", path);
             if std::path::Path::new(path).exists() {
                 load_config_from_file(path)?
             } else {
@@ -244,7 +361,7 @@ async fn load_config(config_path: Option<&String>) -> Result<Config, Box<dyn std
             let mut found_config = None;
             for path in &default_paths {
                 if std::path::Path::new(path).exists() {
-                    println!("Found config at: {}", path);
+                    eprintln!("Found config at: {}", path);
                     found_config = Some(load_config_from_file(path)?);
                     break;
                 }
@@ -253,7 +370,7 @@ async fn load_config(config_path: Option<&String>) -> Result<Config, Box<dyn std
             match found_config {
                 Some(config) => config,
                 None => {
-                    println!("No config file found, using defaults");
+                    eprintln!("No config file found, using defaults");
                     Config::default()
                 }
             }
@@ -321,7 +438,7 @@ async fn register_providers(manager: &mut ProviderManager) -> Result<(), Box<dyn
 
 async fn start_memory_interactive_mode(provider_manager: ProviderManager, config: Config) -> Result<(), Box<dyn std::error::Error>> {
     println!("Comrude - Universal AI Development Assistant");
-    println!("Available commands: <question>, /reset, /select, /help, /providers, /list, /model, /memory, /clear, /quit");
+    println!("Available commands: <question>, /reset, /select, /help, /providers, /list, /model, /memory, /cost, /run, /context, /commit, /pr, /clear, /quit");
     println!("Type '/help' for more information.\n");
 
     let provider_manager = Arc::new(provider_manager);
@@ -330,6 +447,29 @@ async fn start_memory_interactive_mode(provider_manager: ProviderManager, config
     let memory_config = config.memory.clone().into();
     let mut engine = ComrudeEngine::new_with_config(memory_config);
     let _session_id = engine.create_session(Some("Main Session".to_string())).await?;
+
+    use_current_model(&provider_manager, &engine).await;
+
+    // Old turns are summarized by the configured model, or by keywords without one
+    let summary_provider = config.memory.summary_provider.as_ref().filter(|_| config.memory.enable_summarization);
+    if let Some(provider) = summary_provider {
+        if provider_manager.get_provider(provider).await.is_ok() {
+            let summarizer = ModelSummarizer::new(provider_manager.clone(), Some(provider.clone()), config.memory.summary_model.clone());
+            engine.set_summarizer(Arc::new(summarizer)).await;
+        } else {
+            println!("ℹ Summary provider {} not available, summarizing by keywords", provider);
+        }
+    }
+
+    // File tools only ever touch paths inside the workspace root
+    let workspace = Workspace::from_config(&config.files)?;
+    let workspace = Arc::new(workspace);
+    let search_limits = SearchLimits::for_context_tokens(config.memory.max_context_tokens);
+    let tools = ReplToolExecutor {
+        registry: ToolRegistry::with_builtin_tools(workspace.clone(), search_limits),
+        workspace,
+        max_output_bytes: search_limits.max_output_bytes,
+    };
     
     let mut input_buffer = String::new();
     
@@ -352,7 +492,7 @@ async fn start_memory_interactive_mode(provider_manager: ProviderManager, config
             break;
         }
         
-        if let Err(e) = process_memory_command(&provider_manager, &mut engine, &command).await {
+        if let Err(e) = process_memory_command(&provider_manager, &mut engine, &config, &tools, &command).await {
             eprintln!("Error processing command: {}", e);
         }
     }
@@ -369,6 +509,8 @@ async fn start_memory_interactive_mode(provider_manager: ProviderManager, config
 async fn process_memory_command(
     provider_manager: &Arc<ProviderManager>,
     engine: &mut ComrudeEngine,
+    config: &Config,
+    tools: &ReplToolExecutor,
     command: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let parts: Vec<&str> = command.split_whitespace().collect();
@@ -386,11 +528,14 @@ async fn process_memory_command(
         "/list" => {
             list_models(provider_manager).await;
         }
+        "/cost" => {
+            show_cost(provider_manager);
+        }
         "/reset" => {
             // Clear the console
             print!("\x1B[2J\x1B[1;1H");
             println!("Comrude - Universal AI Development Assistant");
-            println!("Available commands: <question>, /reset, /select, /help, /providers, /list, /model, /memory, /clear, /quit");
+            println!("Available commands: <question>, /reset, /select, /help, /providers, /list, /model, /memory, /cost, /run, /context, /commit, /pr, /clear, /quit");
             println!("Type '/help' for more information.\n");
         }
         "/quit" | "/exit" | "/q" => {
@@ -407,6 +552,26 @@ async fn process_memory_command(
                 handle_memory_display(engine).await?;
             }
         }
+        _ if parts[0] == "/run" => {
+            let shell_command = command.trim_start()["/run".len()..].trim();
+            if shell_command.is_empty() {
+                println!("Usage: /run <command>");
+            } else {
+                handle_run_command(engine, tools, shell_command).await?;
+            }
+        }
+        _ if parts[0] == "/context" => {
+            handle_context_command(engine, tools, &parts[1..]).await?;
+        }
+        _ if parts[0] == "/commit" => {
+            let max_diff_bytes = diff_budget(config.memory.max_context_tokens);
+            commit_staged_changes(provider_manager, tools.workspace.root(), max_diff_bytes, true).await?;
+        }
+        _ if parts[0] == "/pr" => {
+            let base = parts.get(1).copied().unwrap_or("main");
+            let max_diff_bytes = diff_budget(config.memory.max_context_tokens);
+            describe_pull_request(provider_manager, tools.workspace.root(), base, max_diff_bytes).await?;
+        }
         _ if parts[0] == "/clear" => {
             // Clear both screen and memory context
             handle_clear_command(engine).await?;
@@ -418,11 +583,13 @@ async fn process_memory_command(
             } else {
                 handle_select_command(provider_manager).await?;
             }
+            use_current_model(provider_manager, engine).await;
         }
         _ if parts[0] == "/model" => {
             if parts.len() > 1 {
                 let model_name = parts[1];
                 handle_model_command(provider_manager, model_name).await?;
+                use_current_model(provider_manager, engine).await;
             } else {
                 show_current_model(provider_manager).await;
             }
@@ -430,7 +597,7 @@ async fn process_memory_command(
         _ => {
             // Always treat user input as a question for the AI with memory
             // The LLM will interpret and generate appropriate commands
-            handle_memory_ask_command(provider_manager, engine, command.to_string()).await?;
+            handle_memory_ask_command(provider_manager, engine, &config.agent, tools, command.to_string()).await?;
         }
     }
 
@@ -512,6 +679,8 @@ PROHIBITED: Explanations, comments, natural language responses."#.to_string())
 async fn handle_memory_ask_command(
     provider_manager: &Arc<ProviderManager>,
     engine: &mut ComrudeEngine,
+    agent_config: &AgentConfig,
+    tools: &ReplToolExecutor,
     question: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
     use comrude_core::GenerationRequest;
@@ -530,11 +699,24 @@ async fn handle_memory_ask_command(
     // Create user message
     let user_message = Message::new_user(question.clone());
     
-    // Start conversation turn with memory context
-    let _turn_id = engine.start_conversation_turn(user_message, vec![]).await?;
+    // Start conversation turn with memory context and anything attached with /run
+    let attached = engine.take_attached_context().await;
+    let _turn_id = engine.start_conversation_turn(user_message, attached.clone()).await?;
 
-    // Get context from memory for the request
-    let context = engine.get_context_for_request().await?;
+    // With the agent on, providers with native tool calls act through tools
+    // instead of printed commands
+    let capabilities = provider_manager.current_capabilities().await.ok();
+    if agent_config.enabled && capabilities.as_ref().is_some_and(|capabilities| capabilities.supports_tools) {
+        let context = assemble_request_context(engine, AGENT_SYSTEM_PROMPT, &question, attached).await?;
+        let request = GenerationRequest {
+            prompt: question,
+            system_prompt: Some(AGENT_SYSTEM_PROMPT.to_string()),
+            max_tokens: Some(ANSWER_MAX_TOKENS),
+            context,
+            ..GenerationRequest::default()
+        };
+        return handle_agent_answer(provider_manager, engine, agent_config, tools, request).await;
+    }
     
     // Load CLI system prompt
     let cli_system_prompt = load_cli_system_prompt()?;
@@ -543,65 +725,309 @@ async fn handle_memory_ask_command(
     let current_provider = provider_manager.get_current_provider_name().await;
     
     // Build request with CLI enforcement
-    let request = if supports_system_prompt(&current_provider) {
+    let mut request = if supports_system_prompt(&current_provider) {
         // Use system prompt for supported providers
+        let context = assemble_request_context(engine, &cli_system_prompt, &question, attached).await?;
         GenerationRequest {
             prompt: question,
             model: None,
             system_prompt: Some(cli_system_prompt),
-            max_tokens: Some(2048),
+            max_tokens: Some(ANSWER_MAX_TOKENS),
             temperature: Some(0.7),
             stream: false,
             tools: Vec::new(),
             context,
+            tool_exchanges: Vec::new(),
             metadata: HashMap::new(),
         }
     } else {
         // Fallback: wrap prompt with CLI instructions for unsupported providers
         let enforced_prompt = format!("{}\n\nUser Request: {}", cli_system_prompt, question);
+        let context = assemble_request_context(engine, "", &enforced_prompt, attached).await?;
         GenerationRequest {
             prompt: enforced_prompt,
             model: None,
             system_prompt: None,
-            max_tokens: Some(2048),
+            max_tokens: Some(ANSWER_MAX_TOKENS),
             temperature: Some(0.7),
             stream: false,
             tools: Vec::new(),
             context,
+            tool_exchanges: Vec::new(),
             metadata: HashMap::new(),
         }
     };
 
-    match provider_manager.generate(request).await {
-        Ok(response) => {
-            // Validate and potentially clean CLI response
-            let cli_response = validate_and_clean_cli_response(&response.content);
-            
-            // Print CLI-validated response
-            println!("\n{}\n", cli_response);
-            
-            // Parse and execute commands from LLM response
-            execute_commands_from_response(&cli_response).await?;
-            
-            // Create assistant message and complete the conversation turn
-            let assistant_message = Message::new_assistant(
-                cli_response.clone(), 
-                response.model_used.clone(), 
-                response.model_used.clone()
-            );
+    // Print the answer progressively; providers that cannot stream answer in one piece
+    request.stream = true;
+    handle_streamed_answer(provider_manager, engine, request).await
+}
+
+/// Room kept in the context window for the answer
+const ANSWER_MAX_TOKENS: u32 = 2048;
+
+/// Memory and attached context that fit the model's window next to the prompts
+async fn assemble_request_context(
+    engine: &ComrudeEngine,
+    system_prompt: &str,
+    prompt: &str,
+    attached: Vec<comrude_core::ContextItem>,
+) -> Result<Vec<comrude_core::ContextItem>, Box<dyn std::error::Error>> {
+    let allocation = engine.assemble_context(system_prompt, prompt, attached, ANSWER_MAX_TOKENS as usize).await?;
+    if allocation.report.trimmed() {
+        println!("ℹ Context trimmed to fit the model's window; /memory shows what was left out");
+    }
+    Ok(allocation.items)
+}
+
+const AGENT_SYSTEM_PROMPT: &str = "You are Comrude, a development assistant working in the user's current directory. \
+Use the provided tools to inspect and change files instead of asking the user to do it. \
+Search the workspace to find the code you need rather than asking the user to paste it. \
+When the task is done, reply with a short summary of what you did.";
+
+/// Shows tool calls as the agent makes them and asks before running ones
+/// that change things, unless auto-confirmation is on
+struct ReplToolExecutor {
+    registry: ToolRegistry,
+    workspace: Arc<Workspace>,
+    /// Output kept from commands whose output goes into the context
+    max_output_bytes: usize,
+}
+
+#[async_trait::async_trait]
+impl comrude_core::ToolExecutor for ReplToolExecutor {
+    fn definitions(&self) -> Vec<comrude_core::ToolDefinition> {
+        self.registry.definitions()
+    }
+
+    async fn execute(&self, call: &comrude_core::ToolCall) -> comrude_core::ToolResult {
+        println!("🔧 {} {}", call.name, call.arguments);
+
+        let needs_confirmation = self.registry.get(&call.name)
+            .is_some_and(|tool| tool.requires_confirmation(&call.arguments))
+            && !*AUTO_CONFIRM.lock().unwrap();
+        if needs_confirmation {
+            let approved = match get_user_confirmation().await {
+                Ok(UserChoice::Yes) | Ok(UserChoice::All) => true,
+                Ok(UserChoice::ToggleAutoConfirm) => {
+                    toggle_auto_confirm();
+                    true
+                }
+                Ok(UserChoice::Skip) | Err(_) => false,
+            };
+            if !approved {
+                return comrude_core::ToolResult {
+                    call_id: call.id.clone(),
+                    content: "The user declined this tool call".to_string(),
+                    is_error: true,
+                };
+            }
+        }
+
+        let result = self.registry.dispatch(call).await;
+        if result.is_error {
+            println!("   ✗ {}", result.content.lines().next().unwrap_or_default());
+        }
+        result
+    }
+}
+
+async fn handle_agent_answer(
+    provider_manager: &Arc<ProviderManager>,
+    engine: &mut ComrudeEngine,
+    agent_config: &AgentConfig,
+    tools: &ReplToolExecutor,
+    request: comrude_core::GenerationRequest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use comrude_core::{Agent, AgentStopReason};
+
+    let agent = Agent::new(provider_manager.as_ref(), tools, agent_config.clone());
+
+    // Ignore any CTRL+C that arrived before the request was sent
+    SIGINT_RECEIVED.store(false, Ordering::Relaxed);
+
+    // Dropping the run on CTRL+C cancels the request or tool in flight;
+    // steps that already finished stay recorded in the turn
+    let result = tokio::select! {
+        result = agent.run(engine, request) => result,
+        _ = wait_for_sigint() => {
+            println!("\n^C (agent interrupted)\n");
+            let provider_name = provider_manager.get_current_provider_name().await.unwrap_or_default();
+            let model = provider_manager.get_current_model().await.unwrap_or_default();
+            let assistant_message = Message::new_interrupted_assistant(String::new(), provider_name, model);
             engine.complete_conversation_turn(assistant_message).await?;
+            return Ok(());
+        }
+    };
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            print_generation_error(&e);
+            return Ok(());
+        }
+    };
+
+    if let Some(warning) = outcome.metadata.get("budget_warning").and_then(|v| v.as_str()) {
+        println!("\n⚠ {}", warning);
+    }
+    println!("\n{}\n", outcome.content);
+    match outcome.stop_reason {
+        AgentStopReason::Finished => {}
+        AgentStopReason::MaxIterations => {
+            println!("⚠ Stopped after {} model requests (agent.max_iterations); the last tool calls were not run\n", agent_config.max_iterations);
         }
+        AgentStopReason::MaxCost => {
+            println!("⚠ Stopped after spending ${:.4} (agent.max_cost); the last tool calls were not run\n", outcome.cost);
+        }
+    }
+
+    let provider_name = match outcome.metadata.get("provider").and_then(|v| v.as_str()) {
+        Some(name) => name.to_string(),
+        None => provider_manager.get_current_provider_name().await.unwrap_or_default(),
+    };
+    let assistant_message = Message::new_assistant(outcome.content, provider_name, outcome.model_used);
+    engine.complete_conversation_turn(assistant_message).await?;
+
+    Ok(())
+}
+
+async fn handle_streamed_answer(
+    provider_manager: &Arc<ProviderManager>,
+    engine: &mut ComrudeEngine,
+    request: comrude_core::GenerationRequest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use comrude_core::StreamChunk;
+    use futures::StreamExt;
+
+    // Ignore any CTRL+C that arrived before the request was sent
+    SIGINT_RECEIVED.store(false, Ordering::Relaxed);
+
+    let prompt_text = comrude_core::request_text(&request);
+    let generation = match provider_manager.generate_stream(request).await {
+        Ok(generation) => generation,
         Err(e) => {
-            eprintln!("\nError: {}", e);
-            eprintln!("\nTip: If you're getting authentication errors:");
-            eprintln!("  - For Anthropic: export ANTHROPIC_API_KEY=your_key_here");
-            eprintln!("  - For OpenAI: export OPENAI_API_KEY=your_key_here");
+            print_generation_error(&e);
+            return Ok(());
         }
+    };
+    if let Some(primary) = &generation.fallback_from {
+        println!("\n↪ {} failed, answered by {}", primary, generation.provider);
+    }
+    if let Some(warning) = &generation.budget_warning {
+        println!("\n⚠ {}", warning);
+    }
+    let (provider_name, model) = (generation.provider, generation.model);
+    let mut stream = generation.chunks;
+
+    let mut answer = String::new();
+    let mut interrupted = false;
+    let mut failure = None;
+
+    println!();
+    loop {
+        tokio::select! {
+            chunk = stream.next() => match chunk {
+                Some(Ok(StreamChunk::Content(text))) => {
+                    print!("{}", text);
+                    io::stdout().flush()?;
+                    answer.push_str(&text);
+                }
+                Some(Ok(StreamChunk::Done)) | None => break,
+                Some(Ok(StreamChunk::Error(message))) => {
+                    failure = Some(message);
+                    break;
+                }
+                Some(Err(e)) => {
+                    failure = Some(e.to_string());
+                    break;
+                }
+                Some(Ok(StreamChunk::TokenUsage(usage))) => {
+                    calibrate_tokenizer(provider_manager, engine, &prompt_text, &model, &usage).await;
+                }
+                Some(Ok(_)) => {} // Tool calls are not shown in the REPL
+            },
+            _ = wait_for_sigint() => {
+                interrupted = true;
+                break;
+            }
+        }
+    }
+    // Dropping the stream closes the connection and cancels the request
+    drop(stream);
+
+    if interrupted {
+        println!("\n^C (response interrupted)\n");
+    } else {
+        println!("\n");
+    }
+
+    if let Some(message) = failure {
+        eprintln!("Error: {}", message);
+        if answer.is_empty() {
+            return Ok(());
+        }
+        // Keep what already arrived, like a user interruption
+        interrupted = true;
+    }
+
+    let cli_response = validate_and_clean_cli_response(&answer);
+
+    if interrupted {
+        // A partial answer may contain half-written commands, so never run them
+        let assistant_message = Message::new_interrupted_assistant(cli_response, provider_name, model);
+        engine.complete_conversation_turn(assistant_message).await?;
+        return Ok(());
     }
 
+    execute_commands_from_response(&cli_response).await?;
+
+    let assistant_message = Message::new_assistant(cli_response, provider_name, model);
+    engine.complete_conversation_turn(assistant_message).await?;
+
     Ok(())
 }
 
+/// Count tokens and size requests for the current model
+async fn use_current_model(provider_manager: &ProviderManager, engine: &ComrudeEngine) {
+    use comrude_core::{tokenizer_for, tokenizer_for_model};
+
+    let Some(model) = provider_manager.get_current_model().await else { return };
+    let Ok(provider) = provider_manager.get_current_provider().await else { return };
+    let (tokenizer, context_window) = match provider.supported_models().into_iter().find(|info| info.id == model) {
+        Some(info) => (tokenizer_for(&info), info.context_length),
+        // Models the provider does not list, such as pulled Ollama models
+        None => (tokenizer_for_model(&model), provider.capabilities().max_context_length),
+    };
+    engine.set_tokenizer(tokenizer).await;
+    engine.set_context_window(Some(context_window as usize)).await;
+}
+
+/// Correct token estimates with the prompt size the model reported, as long
+/// as the answer came from the model the tokenizer was chosen for
+async fn calibrate_tokenizer(provider_manager: &ProviderManager, engine: &ComrudeEngine, prompt_text: &str, model: &str, usage: &TokenUsage) {
+    if provider_manager.get_current_model().await.as_deref() == Some(model) {
+        engine.tokenizer().await.calibrate(prompt_text, usage.prompt_tokens);
+    }
+}
+
+// Resolves once the native SIGINT handler has fired, consuming the flag
+async fn wait_for_sigint() {
+    while !SIGINT_RECEIVED.swap(false, Ordering::Relaxed) {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn print_generation_error(e: &comrude_core::ComrudeError) {
+    eprintln!("\nError: {}", e);
+    if matches!(e, comrude_core::ComrudeError::Provider(comrude_core::ProviderError::BudgetExceeded(_))) {
+        eprintln!("\nTip: Check spending with /cost or raise the limits in the [budget] section of your config");
+        return;
+    }
+    eprintln!("\nTip: If you're getting authentication errors:");
+    eprintln!("  - For Anthropic: export ANTHROPIC_API_KEY=your_key_here");
+    eprintln!("  - For OpenAI: export OPENAI_API_KEY=your_key_here");
+}
+
 async fn execute_commands_from_response(response: &str) -> Result<(), Box<dyn std::error::Error>> {
     let commands = parse_commands_from_response(response);
     
@@ -759,58 +1185,32 @@ async fn execute_interactive_command(command: &str) -> Result<(), Box<dyn std::e
     println!("📡 Running interactive command (CTRL+C to interrupt)...");
     
     
-    // Create process with new process group for signal isolation
-    let mut child = if command.contains("&&") || command.contains("||") || command.contains(";") {
-        let mut cmd = ProcessCommand::new("bash");
-        cmd.arg("-c")
-           .arg(command)
-           .stdout(Stdio::inherit())
-           .stderr(Stdio::inherit())
-           .stdin(Stdio::inherit());
-        
-        // Use pre_exec to set new process group before exec
-        unsafe {
-            cmd.pre_exec(|| {
-                // Create new process group with child as leader
-                setpgid(0, 0);
-                Ok(())
-            });
-        }
-        
-        cmd.spawn()?
-    } else {
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if parts.is_empty() {
-            return Ok(());
-        }
-        
-        let mut cmd = ProcessCommand::new(parts[0]);
-        if parts.len() > 1 {
-            cmd.args(&parts[1..]);
-        }
-        
-        cmd.stdout(Stdio::inherit())
-           .stderr(Stdio::inherit())
-           .stdin(Stdio::inherit());
-        
-        // Use pre_exec to set new process group before exec
-        unsafe {
-            cmd.pre_exec(|| {
-                // Create new process group with child as leader
-                setpgid(0, 0);
-                Ok(())
-            });
-        }
-        
-        cmd.spawn()?
-    };
+    // Create process with new process group for signal isolation; going
+    // through the shell keeps quoted arguments intact
+    let mut cmd = ProcessCommand::new("bash");
+    cmd.arg("-c")
+       .arg(command)
+       .stdout(Stdio::inherit())
+       .stderr(Stdio::inherit())
+       .stdin(Stdio::inherit());
+
+    // Use pre_exec to set new process group before exec
+    unsafe {
+        cmd.pre_exec(|| {
+            // Create new process group with child as leader
+            setpgid(0, 0);
+            Ok(())
+        });
+    }
+
+    let mut child = cmd.spawn()?;
     
     let child_pid = child.id();
     let child_pgid = child_pid as i32; // Child is its own process group leader
     println!("🔄 Child process {} running in new process group {}", child_pid, child_pgid);
     
     // Push command to stack
-    push_command_to_stack(command.to_string(), child_pid, child_pgid);
+    push_command_to_stack(child_pgid);
     
     // Execute with isolated signal handling
     let exit_status = execute_with_signal_isolation(&mut child).await?;
@@ -832,8 +1232,8 @@ async fn execute_interactive_command(command: &str) -> Result<(), Box<dyn std::e
 }
 
 // Push command to the command stack
-fn push_command_to_stack(command: String, pid: u32, pgid: i32) {
-    let entry = CommandStackEntry { command, pid, pgid };
+fn push_command_to_stack(pgid: i32) {
+    let entry = CommandStackEntry { pgid };
     let mut stack = COMMAND_STACK.lock().unwrap();
     stack.push_back(entry);
     println!("📚 Command stack depth: {}", stack.len());
@@ -846,12 +1246,6 @@ fn pop_command_from_stack() {
     println!("📚 Command stack depth: {}", stack.len());
 }
 
-// Get the current active command from stack
-fn get_current_command() -> Option<CommandStackEntry> {
-    let stack = COMMAND_STACK.lock().unwrap();
-    stack.back().cloned()
-}
-
 // Execute with clean terminal output and native signal handling
 async fn execute_with_signal_isolation(child: &mut std::process::Child) -> Result<std::process::ExitStatus, Box<dyn std::error::Error>> {
     // Main execution loop - child process has completely normal terminal access
@@ -897,46 +1291,249 @@ async fn execute_with_signal_isolation(child: &mut std::process::Child) -> Resul
 }
 
 async fn execute_batch_command(command: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let output = if command.contains("&&") || command.contains("||") || command.contains(";") {
-        // Execute complex command through shell
-        ProcessCommand::new("bash")
-            .arg("-c")
-            .arg(command)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()?
-    } else {
-        // Parse and execute simple command
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if parts.is_empty() {
-            return Ok(());
-        }
-        
-        let mut cmd = ProcessCommand::new(parts[0]);
-        if parts.len() > 1 {
-            cmd.args(&parts[1..]);
+    // Commands the user approved run where Comrude was started, like in their own shell
+    let workspace = Workspace::new(std::env::current_dir()?, &comrude_core::Config::default().files)?;
+    if let Some(output) = run_until_interrupted(&workspace, command, REPL_MAX_OUTPUT_BYTES).await? {
+        print_command_output(&output);
+    }
+    Ok(())
+}
+
+/// Run a command the user asked for with no timeout, so long builds finish;
+/// CTRL+C kills it instead and gives `None`
+async fn run_until_interrupted(
+    workspace: &Workspace,
+    command: &str,
+    max_output_bytes: usize,
+) -> Result<Option<CommandOutput>, Box<dyn std::error::Error>> {
+    // Ignore any CTRL+C that arrived before the command started
+    SIGINT_RECEIVED.store(false, Ordering::Relaxed);
+
+    let command = ShellCommand::new(command);
+    tokio::select! {
+        output = run_command(workspace, &command, max_output_bytes, None) => Ok(Some(output?)),
+        _ = wait_for_sigint() => {
+            // Dropping the run kills the command's process group
+            println!("^C (command interrupted)");
+            Ok(None)
         }
-        
-        cmd.stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()?
-    };
-    
-    if output.status.success() {
+    }
+}
+
+/// Output kept from commands whose output is only printed
+const REPL_MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+
+fn print_command_output(output: &CommandOutput) {
+    if output.success() {
         if !output.stdout.is_empty() {
             println!("✅ Output:");
-            println!("{}", String::from_utf8_lossy(&output.stdout));
+            println!("{}", output.stdout);
         } else {
             println!("✅ Command executed successfully (no output)");
         }
+    } else if output.timed_out {
+        println!("⏱ Command timed out after {:.1}s", output.duration.as_secs_f64());
     } else {
-        println!("❌ Command failed with exit code: {:?}", output.status.code());
+        match output.exit_code {
+            Some(code) => println!("❌ Command failed with exit code: {}", code),
+            None => println!("🚫 Command terminated by signal"),
+        }
+        if !output.stdout.is_empty() {
+            println!("{}", output.stdout);
+        }
         if !output.stderr.is_empty() {
             println!("Error output:");
-            println!("{}", String::from_utf8_lossy(&output.stderr));
+            println!("{}", output.stderr);
         }
     }
-    
+    if output.truncated {
+        println!("[... output truncated ...]");
+    }
+}
+
+/// Attach git diffs, history or blame to the next question
+async fn handle_context_command(
+    engine: &mut ComrudeEngine,
+    tools: &ReplToolExecutor,
+    args: &[&str],
+) -> Result<(), Box<dyn std::error::Error>> {
+    const USAGE: &str = "Usage: /context diff [branch] | /context staged | /context log [count] | /context blame <path> <start>[-<end>]";
+
+    let repository = match GitRepository::discover(tools.workspace.root()).await {
+        Ok(repository) => repository,
+        Err(e) => {
+            println!("❌ {}", e);
+            return Ok(());
+        }
+    };
+
+    let gathered = match args {
+        ["diff"] => {
+            let mut items = repository.diff(&DiffSource::Staged).await?;
+            items.extend(repository.diff(&DiffSource::WorkingTree).await?);
+            items
+        }
+        ["diff", branch] => repository.diff(&DiffSource::Branch(branch.to_string())).await?,
+        ["staged"] => repository.diff(&DiffSource::Staged).await?,
+        ["log"] => vec![repository.recent_log(10).await?],
+        ["log", count] => match count.parse() {
+            Ok(count) => vec![repository.recent_log(count).await?],
+            Err(_) => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+        },
+        ["blame", path, range] => {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            match (start.parse(), end.parse()) {
+                (Ok(start), Ok(end)) => vec![repository.blame(path, start, end).await?],
+                _ => {
+                    println!("{}", USAGE);
+                    return Ok(());
+                }
+            }
+        }
+        _ => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+
+    if gathered.is_empty() {
+        println!("No changes to attach");
+        return Ok(());
+    }
+
+    for item in gathered {
+        match item.item_type {
+            comrude_core::ContextType::GitDiff => println!(
+                "📎 {} (+{} -{})",
+                item.metadata["path"].as_str().unwrap_or_default(),
+                item.metadata["additions"],
+                item.metadata["deletions"],
+            ),
+            _ => println!("📎 {}", item.content.lines().next().unwrap_or_default().trim_end_matches(':')),
+        }
+        engine.attach_context(item).await;
+    }
+    println!("Attached to your next question");
+    Ok(())
+}
+
+/// Bytes of diff sent when drafting messages: half the context window, at
+/// about four bytes per token
+fn diff_budget(max_context_tokens: usize) -> usize {
+    max_context_tokens * 2
+}
+
+/// Draft a commit message for the staged changes in the repository's style,
+/// let the user edit it, then commit
+async fn commit_staged_changes(
+    provider_manager: &ProviderManager,
+    repository_dir: &Path,
+    max_diff_bytes: usize,
+    edit: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repository = GitRepository::discover(repository_dir).await?;
+    let diff = repository.patch(&DiffSource::Staged).await?;
+    if diff.trim().is_empty() {
+        println!("Nothing staged to commit. Stage changes with `git add` first.");
+        return Ok(());
+    }
+    let stat = repository.diff_stat(&DiffSource::Staged).await?;
+    let subjects = repository.recent_subjects(STYLE_SAMPLE_SIZE).await?;
+
+    println!("✍ Drafting a commit message...");
+    let request = commit_message_request(&diff, &stat, &subjects, max_diff_bytes);
+    let message = match provider_manager.generate(request).await {
+        Ok(response) => clean_message(&response.content),
+        Err(e) => {
+            print_generation_error(&e);
+            return Ok(());
+        }
+    };
+
+    let message_file = repository.git_dir().await?.join("COMRUDE_EDITMSG");
+    std::fs::write(&message_file, format!(
+        "{}\n# Drafted by Comrude. Lines starting with '#' are ignored;\n# an empty message aborts the commit.\n",
+        message
+    ))?;
+    if edit {
+        open_in_editor(&message_file)?;
+    } else {
+        println!("\n{}", message);
+    }
+
+    let edited = std::fs::read_to_string(&message_file)?;
+    if edited.lines().all(|line| line.trim().is_empty() || line.starts_with('#')) {
+        println!("Empty commit message, nothing committed");
+    } else {
+        let output = repository.commit(&message_file).await?;
+        println!("✅ {}", output.lines().next().unwrap_or_default());
+    }
+    let _ = std::fs::remove_file(&message_file);
+    Ok(())
+}
+
+/// Open a file in $VISUAL or $EDITOR and wait for the editor to exit
+fn open_in_editor(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // Through the shell, so settings with arguments like `code --wait` work
+    let status = ProcessCommand::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", editor, status).into());
+    }
+    Ok(())
+}
+
+/// Print a pull request description for the commits and changes since `base`
+async fn describe_pull_request(
+    provider_manager: &ProviderManager,
+    repository_dir: &Path,
+    base: &str,
+    max_diff_bytes: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repository = GitRepository::discover(repository_dir).await?;
+    let source = DiffSource::Branch(base.to_string());
+    let diff = repository.patch(&source).await?;
+    if diff.trim().is_empty() {
+        println!("No changes since {}", base);
+        return Ok(());
+    }
+    let stat = repository.diff_stat(&source).await?;
+    let commits = repository.subjects_since(base).await?;
+
+    println!("✍ Drafting a pull request description...");
+    let request = pull_request_request(&diff, &stat, &commits, base, max_diff_bytes);
+    match provider_manager.generate(request).await {
+        Ok(response) => println!("\n{}\n", clean_message(&response.content).trim_end()),
+        Err(e) => print_generation_error(&e),
+    }
+    Ok(())
+}
+
+/// Run a command the user typed and attach its output to their next question
+async fn handle_run_command(
+    engine: &mut ComrudeEngine,
+    tools: &ReplToolExecutor,
+    command: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("🔧 Executing: {}", command);
+    let Some(output) = run_until_interrupted(&tools.workspace, command, tools.max_output_bytes).await? else {
+        return Ok(());
+    };
+    print_command_output(&output);
+
+    engine.attach_context(output.to_context_item(command)).await;
+    println!("📎 Output attached to your next question");
     Ok(())
 }
 
@@ -967,10 +1564,9 @@ fn parse_commands_from_response(response: &str) -> Vec<String> {
         }
         
         // Extract commands
-        if in_bash_block || is_direct_command(trimmed) {
-            if !trimmed.is_empty() && !trimmed.starts_with("#") && trimmed != "---" {
-                commands.push(trimmed.to_string());
-            }
+        if (in_bash_block || is_direct_command(trimmed))
+            && !trimmed.is_empty() && !trimmed.starts_with("#") && trimmed != "---" {
+            commands.push(trimmed.to_string());
         }
     }
     
@@ -1052,7 +1648,7 @@ fn is_direct_command(line: &str) -> bool {
         let first_word = parts[0];
         
         // Must be reasonable length and format
-        if first_word.len() > 0 && first_word.len() < 20 &&
+        if !first_word.is_empty() && first_word.len() < 20 &&
            first_word.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') &&
            !first_word.chars().all(|c| c.is_numeric()) &&
            !first_word.chars().next().unwrap().is_uppercase() { // Commands usually lowercase
@@ -1140,6 +1736,13 @@ async fn handle_memory_display(engine: &ComrudeEngine) -> Result<(), Box<dyn std
                         }
                     }
                     
+                    for step in &turn.agent_steps {
+                        for (call, result) in step.tool_calls.iter().zip(&step.results) {
+                            let status = if result.is_error { "✗" } else { "✓" };
+                            println!("  🔧 {} {} {}", status, call.name, call.arguments);
+                        }
+                    }
+
                     println!("  📊 Tokens used: {}", turn.tokens_used);
                 }
                 println!();
@@ -1187,6 +1790,17 @@ async fn handle_memory_display(engine: &ComrudeEngine) -> Result<(), Box<dyn std
         }
     }
     
+    // Show how the last request shared out the context window
+    match engine.last_allocation().await {
+        Some(report) => {
+            println!("📐 Context Budget (last request):");
+            println!("-------------------------------");
+            print!("  {}", report);
+            println!();
+        }
+        None => println!("📐 Context Budget: no request sent yet\n"),
+    }
+
     // Show memory statistics
     println!("📊 Memory Statistics:");
     println!("-------------------");
@@ -1202,28 +1816,12 @@ async fn handle_memory_display(engine: &ComrudeEngine) -> Result<(), Box<dyn std
 async fn handle_memory_add_instruction(engine: &mut ComrudeEngine, content: String) -> Result<(), Box<dyn std::error::Error>> {
     println!("💾 Adding persistent instruction to memory...");
     
-    // Create a system message with the persistent instruction
-    let system_message = comrude_core::Message::new_system(format!("[PERSISTENT INSTRUCTION] {}", content));
-    let context = vec![];
-    
-    // Add the instruction as a conversation turn
-    match engine.start_conversation_turn(system_message.clone(), context).await {
-        Ok(_turn_id) => {
-            // Complete the turn with the same content as confirmation
-            let confirmation_message = comrude_core::Message::new_system(
-                "Persistent instruction added to memory context.".to_string()
-            );
-            
-            match engine.complete_conversation_turn(confirmation_message).await {
-                Ok(_) => {
-                    println!("✅ Persistent instruction added successfully:");
-                    println!("   📝 \"{}\"", content);
-                    println!("   ℹ️  This instruction will be included in all future requests.\n");
-                },
-                Err(e) => {
-                    println!("❌ Error completing instruction addition: {}\n", e);
-                }
-            }
+    // Pinned instructions stay out of the turn window, so they are never evicted
+    match engine.pin_instruction(content.clone()).await {
+        Ok(()) => {
+            println!("✅ Persistent instruction added successfully:");
+            println!("   📝 \"{}\"", content);
+            println!("   ℹ️  This instruction will be included in all future requests.\n");
         },
         Err(e) => {
             println!("❌ Error adding persistent instruction: {}\n", e);
@@ -1247,7 +1845,7 @@ async fn handle_clear_command(engine: &mut ComrudeEngine) -> Result<(), Box<dyn
             
             // Show the standard welcome message
             println!("Comrude - Universal AI Development Assistant");
-            println!("Available commands: <question>, /reset, /select, /help, /providers, /list, /model, /memory, /clear, /quit");
+            println!("Available commands: <question>, /reset, /select, /help, /providers, /list, /model, /memory, /cost, /run, /context, /commit, /pr, /clear, /quit");
             println!("Type '/help' for more information.\n");
         },
         Err(e) => {
@@ -1275,6 +1873,14 @@ Commands:
   /model <model_id>   - Select model for current provider
   /memory             - Display formatted memory context and conversation history
   /memory <content>   - Add persistent instruction to memory context
+  /cost               - Show spend for this session and today
+  /run <command>      - Run a shell command and attach its output to the next question
+  /context diff [branch] - Attach uncommitted changes, or everything since branch
+  /context staged     - Attach staged changes
+  /context log [n]    - Attach the last n commits (10 by default)
+  /context blame <path> <start>-<end> - Attach blame for a range of lines
+  /commit             - Draft a commit message for staged changes, edit it, and commit
+  /pr [base]          - Draft a pull request description for the changes since base (main)
   /clear              - Clear both screen and memory context (fresh session)
   /quit, /exit, /q    - Exit the application
 
@@ -1306,6 +1912,33 @@ Examples:
     println!("{}", help_text.trim());
 }
 
+fn show_cost(provider_manager: &std::sync::Arc<ProviderManager>) {
+    let tracker = provider_manager.cost_tracker();
+    let session = tracker.session_summary();
+    let today = tracker.daily_summary();
+
+    println!("\n💰 Usage cost");
+    println!(
+        "  Session: ${:.4} ({} requests, {} tokens) since {}",
+        session.cost,
+        session.requests,
+        session.tokens.total_tokens,
+        tracker.started_at().with_timezone(&chrono::Local).format("%H:%M"),
+    );
+    println!(
+        "  Today:   ${:.4} ({} requests, {} tokens)",
+        today.cost, today.requests, today.tokens.total_tokens,
+    );
+
+    if !session.by_model.is_empty() {
+        println!("\n  By model this session:");
+        for (model, cost) in &session.by_model {
+            println!("    {:<45} ${:.4}", model, cost);
+        }
+    }
+    println!();
+}
+
 async fn list_providers(provider_manager: &std::sync::Arc<ProviderManager>) {
     let providers = provider_manager.list_providers().await;
     let current_provider = provider_manager.get_current_provider_name().await;